@@ -0,0 +1,150 @@
+//! Tab completion for the shell.
+
+use pi_lib::parse::KEYWORDS;
+use rustyline::{
+    completion::{Completer, FilenameCompleter, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+
+/// Shortcuts for the Unicode symbols that the grammar accepts, e.g., `\forall` for `∀`.
+const SHORTCUTS: &[(&str, &str)] = &[("forall", "∀"), ("lambda", "λ"), ("N", "ℕ")];
+
+const COMMANDS: &[&str] = &[
+    ":type",
+    ":normalize",
+    ":load",
+    ":reload",
+    ":browse",
+    ":info",
+    ":undo",
+    ":save-point",
+    ":restore",
+    ":goals",
+    ":give",
+    ":refine",
+    ":split",
+    ":intro",
+    ":set",
+    ":help",
+    ":quit",
+];
+
+/// Completes keywords, global names, commands and the file paths after `:load`.
+#[derive(Default)]
+pub struct PiHelper {
+    /// The global names in the context, which the shell keeps up to date.
+    pub names: Vec<String>,
+    files: FilenameCompleter,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '\\' | ':')
+}
+
+impl Completer for PiHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        if [":load ", ":l "].iter().any(|cmd| line.starts_with(cmd)) {
+            return self.files.complete(line, pos, ctx);
+        }
+
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !is_name_char(*c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let word = &line[start..pos];
+
+        let candidates = match word.strip_prefix('\\') {
+            Some(word) => SHORTCUTS
+                .iter()
+                .filter(|(name, _)| name.starts_with(word))
+                .map(|(name, symbol)| Pair {
+                    display: format!("\\{name} {symbol}"),
+                    replacement: symbol.to_string(),
+                })
+                .collect(),
+            None if word.is_empty() => vec![],
+            None => {
+                let names = match word.starts_with(':') && start == 0 {
+                    true => COMMANDS.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+                    false => KEYWORDS
+                        .iter()
+                        .map(|s| s.to_string())
+                        .chain(self.names.iter().cloned())
+                        .collect(),
+                };
+
+                let mut candidates = names
+                    .into_iter()
+                    .filter(|name| name.starts_with(word))
+                    .collect::<Vec<_>>();
+                candidates.sort();
+                candidates.dedup();
+                candidates
+                    .into_iter()
+                    .map(|name| Pair {
+                        display: name.clone(),
+                        replacement: name,
+                    })
+                    .collect()
+            }
+        };
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for PiHelper {
+    type Hint = String;
+}
+
+impl Highlighter for PiHelper {}
+
+impl Validator for PiHelper {}
+
+impl Helper for PiHelper {}
+
+#[cfg(test)]
+mod tests {
+    use rustyline::{completion::Completer, history::DefaultHistory, Context};
+
+    use super::PiHelper;
+
+    fn complete(helper: &PiHelper, line: &str) -> (usize, Vec<String>) {
+        let history = DefaultHistory::new();
+        let (start, candidates) = helper
+            .complete(line, line.len(), &Context::new(&history))
+            .unwrap();
+        (
+            start,
+            candidates.into_iter().map(|c| c.replacement).collect(),
+        )
+    }
+
+    #[test]
+    fn test_complete() {
+        let helper = PiHelper {
+            names: vec!["natElim".into(), "Arith.plus".into()],
+            ..Default::default()
+        };
+
+        assert_eq!(complete(&helper, "eval na"), (5, vec!["natElim".into()]));
+        assert_eq!(complete(&helper, "eval Ar"), (5, vec!["Arith.plus".into()]));
+        assert_eq!(complete(&helper, "def P :: \\fo"), (9, vec!["∀".into()]));
+        assert_eq!(
+            complete(&helper, "re"),
+            (0, vec!["rec".into(), "record".into()])
+        );
+        assert_eq!(complete(&helper, ":ty"), (0, vec![":type".into()]));
+    }
+}
@@ -1,35 +1,296 @@
-use std::io::Write;
+mod helper;
+mod output;
+mod repl;
 
-use anyhow::Result;
-use clap::Parser;
-use log::LevelFilter;
-use pi_lib::parse::{handle_statement, CmdParser};
-
-fn propmt() -> Result<String> {
-    print!(">>> ");
-    std::io::stdout().flush()?;
-    let mut line = String::new();
-    std::io::stdin().read_line(&mut line)?;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
 
-    Ok(line.trim().to_string())
-}
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+use output::{location, to_json, Format};
+use pi_lib::{
+    env::TypeCtx,
+    err::EvalError,
+    format::format,
+    fuel::{set_limits, Limits},
+    parse::{check_file, load_file},
+    prelude::load_prelude,
+    thunk::{with_strategy, Strategy},
+};
+use repl::Repl;
+use serde_json::json;
 
 #[derive(Parser, Debug)]
-#[command(name = "kvm-monitor")]
+#[command(name = "pi-interpreter")]
+#[command(about = "An interpreter for the dependently typed lambda calculus.")]
 #[command(author = "Haobin Hiroki Chen. <haobchen@iu.edu>")]
 #[command(version = "1.0")]
 pub struct Args {
-    #[clap(short, long, default_value = "", help = "The input file to parse.")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[clap(
+        short,
+        long,
+        default_value = "",
+        help = "The input file to parse (same as `run`)."
+    )]
     input: String,
 
-    #[clap(long, default_value = "false", help = "Enter interactive mode.")]
+    #[clap(
+        long,
+        default_value = "false",
+        help = "Enter interactive mode (same as `repl`)."
+    )]
     interactive: bool,
 
-    #[clap(short, long, default_value = "info", help = "Set the log level.")]
+    #[clap(
+        short,
+        long,
+        global = true,
+        default_value = "info",
+        help = "Set the log level."
+    )]
     log_level: LevelFilter,
+
+    #[clap(
+        long,
+        global = true,
+        default_value = "false",
+        help = "Do not load the standard prelude."
+    )]
+    no_prelude: bool,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value = "text",
+        help = "How `check`, `run` and `fmt` print their results; the REPL only prints text."
+    )]
+    format: Format,
+
+    #[clap(
+        long,
+        global = true,
+        default_value = "strict",
+        help = "How arguments are evaluated: `strict` (call-by-value) or `lazy` (call-by-need)."
+    )]
+    strategy: Strategy,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Stop each statement after this many steps of evaluation and checking."
+    )]
+    fuel: Option<u64>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Stop each statement after this many seconds."
+    )]
+    timeout: Option<f64>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Type-check every statement of the given files and report the errors.
+    Check {
+        #[clap(
+            required = true,
+            help = "The files to check; globs like `lib/**/*.pi` are expanded."
+        )]
+        files: Vec<String>,
+    },
+    /// Load a file and print the value of its last statement.
+    Run { file: PathBuf },
+    /// Start the interactive shell.
+    Repl,
+    /// Reformat the given files in place in the canonical style.
+    Fmt {
+        #[clap(
+            required = true,
+            help = "The files to format; globs like `lib/**/*.pi` are expanded."
+        )]
+        files: Vec<String>,
+
+        #[clap(
+            long,
+            default_value = "false",
+            help = "Only report the files that are not formatted, and fail if there are any."
+        )]
+        check: bool,
+    },
+}
+
+/// Expands the globs in `patterns`; a pattern that matches nothing is kept as it is so that it is
+/// reported as missing.
+fn expand(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for pattern in patterns {
+        let matches = glob::glob(pattern)?.collect::<Result<Vec<_>, _>>()?;
+        match matches.is_empty() {
+            true => files.push(PathBuf::from(pattern)),
+            false => files.extend(matches),
+        }
+    }
+
+    Ok(files)
+}
+
+/// Checks `file` in `ctx` and prints its errors and holes to stderr, or all of its statements as
+/// JSON. They are the output of `check`, so they are not logged and `--log-level` does not hide
+/// them. Returns the number of definitions, errors and holes.
+fn check_one(file: &Path, ctx: &mut TypeCtx, format: Format) -> (usize, usize, usize) {
+    let report = check_file(file, ctx);
+
+    for stmt in report.statements.iter() {
+        match (format, stmt.error.as_ref()) {
+            (Format::Json, _) => println!("{}", to_json(file, &report.source, stmt)),
+            (Format::Text, Some(e)) => {
+                eprintln!("{}: {}", location(file, &report.source, stmt.span), e)
+            }
+            (Format::Text, None) => {
+                for goal in stmt.goals.iter() {
+                    let at = location(file, &report.source, Some(goal.span));
+                    eprintln!("{at}: {goal}");
+                }
+            }
+        }
+    }
+
+    (
+        report.definitions,
+        report.errors().count(),
+        report.goals().count(),
+    )
 }
 
-fn main() -> Result<()> {
+/// Checks each file in its own copy of `ctx`. Returns whether there are no errors.
+fn check(files: &[String], ctx: TypeCtx, format: Format) -> Result<bool> {
+    let files = expand(files)?;
+    let (mut definitions, mut errors, mut holes) = (0, 0, 0);
+
+    for file in files.iter() {
+        let (n, m, k) = check_one(file, &mut ctx.clone(), format);
+        definitions += n;
+        errors += m;
+        holes += k;
+    }
+
+    match format {
+        Format::Text => println!(
+            "Checked {} file(s): {definitions} definition(s), {errors} error(s), {holes} hole(s).",
+            files.len()
+        ),
+        Format::Json => println!(
+            "{}",
+            json!({
+                "kind": "summary",
+                "files": files.len(),
+                "definitions": definitions,
+                "errors": errors,
+                "holes": holes,
+            })
+        ),
+    }
+    Ok(errors == 0)
+}
+
+/// Runs a file. In JSON, every statement is printed and the errors do not stop the others.
+fn run(file: &Path, mut ctx: TypeCtx, format: Format) -> Result<bool> {
+    if format == Format::Json {
+        return Ok(check_one(file, &mut ctx, format).1 == 0);
+    }
+
+    if let Some(res) = load_file(file, &mut ctx)?.pop() {
+        println!("{}", res);
+    }
+    Ok(true)
+}
+
+/// Formats each file in place, or only reports the files that would change with `check`. In
+/// JSON, a record is printed for every file and then a summary.
+/// Returns whether every file could be formatted and, with `check`, was formatted already.
+fn fmt(files: &[String], check: bool, output: Format) -> Result<bool> {
+    let files = expand(files)?;
+    let (mut changed, mut failed) = (0, 0);
+
+    for file in files.iter() {
+        let res = fs::read_to_string(file)
+            .map_err(|e| EvalError::FileNotFound(format!("{}: {e}", file.display())))
+            .and_then(|source| Ok((format(&source)?, source)));
+        let res = match res {
+            Ok((formatted, source)) if formatted != source => {
+                changed += 1;
+                if !check {
+                    fs::write(file, formatted)?;
+                }
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) => {
+                failed += 1;
+                Err(e)
+            }
+        };
+
+        match (output, res) {
+            (Format::Json, res) => println!(
+                "{}",
+                json!({
+                    "kind": "format",
+                    "file": file.display().to_string(),
+                    "changed": res.as_ref().is_ok_and(|changed| *changed),
+                    "error": res.err().map(|e| e.to_string()),
+                })
+            ),
+            (Format::Text, Ok(true)) if check => println!("{} is not formatted.", file.display()),
+            (Format::Text, Ok(_)) => (),
+            (Format::Text, Err(e)) => eprintln!("{}: {}", file.display(), e),
+        }
+    }
+
+    match (output, check) {
+        (Format::Json, _) => println!(
+            "{}",
+            json!({
+                "kind": "summary",
+                "files": files.len(),
+                "changed": changed,
+                "failed": failed,
+            })
+        ),
+        (Format::Text, true) => println!(
+            "{changed} of {} file(s) would be reformatted, {failed} failed.",
+            files.len()
+        ),
+        (Format::Text, false) => println!(
+            "Reformatted {changed} of {} file(s), {failed} failed.",
+            files.len()
+        ),
+    }
+    Ok(failed == 0 && !(check && changed > 0))
+}
+
+/// Starts the REPL, which only prints text.
+fn repl(ctx: TypeCtx, format: Format) -> Result<bool> {
+    if format == Format::Json {
+        return Err(anyhow!(
+            "The REPL does not support `--format json`; use `check` or `run` instead."
+        ));
+    }
+
+    Repl::new(ctx)?.run().map(|_| true)
+}
+
+fn main() -> Result<ExitCode> {
     let args = Args::parse();
 
     env_logger::builder()
@@ -39,40 +300,31 @@ fn main() -> Result<()> {
         .filter_level(args.log_level)
         .init();
 
-    if args.interactive {
-        println!("Welcome to the Pi interpreter!");
-        println!("Type 'exit' to quit.\n");
-
-        let parser = CmdParser::new();
-        let mut ctx = Default::default();
-        loop {
-            let input = propmt()?;
-
-            if input.trim().to_lowercase() == "exit" {
-                return Ok(());
-            }
-
-            if input.trim().to_lowercase() == "show" {
-                println!("{:?}", ctx);
-                continue;
-            }
+    let mut ctx = TypeCtx::new();
+    if !args.no_prelude {
+        load_prelude(&mut ctx)?;
+    }
 
-            let cmd = match parser.parse(input.as_str()) {
-                Ok(cmd) => cmd,
-                Err(e) => {
-                    log::error!("{}", e);
-                    continue;
-                }
-            };
+    set_limits(Limits {
+        fuel: args.fuel,
+        timeout: args.timeout.map(Duration::try_from_secs_f64).transpose()?,
+        cancel: None,
+    });
 
-            match handle_statement(cmd, &mut ctx) {
-                Ok(res) => println!("{:?}", res),
-                Err(e) => log::error!("{}", e),
-            }
+    let ok = with_strategy(args.strategy, || match args.command {
+        Some(Command::Check { files }) => check(&files, ctx, args.format),
+        Some(Command::Run { file }) => run(&file, ctx, args.format),
+        Some(Command::Repl) => repl(ctx, args.format),
+        Some(Command::Fmt { files, check }) => fmt(&files, check, args.format),
+        None if args.interactive => repl(ctx, args.format),
+        None if args.input.is_empty() => {
+            Err(anyhow!("Nothing to do; see --help for the subcommands."))
         }
-    } else {
-        let res = pi_lib::parse::eval_file(&args.input)?;
-        println!("{:?}", res);
-        Ok(())
-    }
+        None => run(Path::new(&args.input), ctx, args.format),
+    })?;
+
+    Ok(match ok {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
 }
@@ -0,0 +1,116 @@
+//! Printing the results of statements as text or as JSON.
+
+use std::path::Path;
+
+use clap::ValueEnum;
+use pi_lib::{ast::Span, eval::lift, parse::Checked};
+use serde_json::{json, Value};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Format {
+    /// Messages for people.
+    #[default]
+    Text,
+    /// One JSON object per line for each statement.
+    Json,
+}
+
+/// `file:line:column` of the start of `span`, or just `file` without a span.
+pub fn location(file: &Path, source: &str, span: Option<Span>) -> String {
+    match span {
+        Some(span) => {
+            let (line, col) = Span::line_col(source, span.start);
+            format!("{}:{line}:{col}", file.display())
+        }
+        None => file.display().to_string(),
+    }
+}
+
+fn span_to_json(source: &str, span: Span) -> Value {
+    let (start_line, start_column) = Span::line_col(source, span.start);
+    let (end_line, end_column) = Span::line_col(source, span.end);
+
+    json!({
+        "start": span.start,
+        "end": span.end,
+        "start_line": start_line,
+        "start_column": start_column,
+        "end_line": end_line,
+        "end_column": end_column,
+    })
+}
+
+/// Describes a statement of `file`, whose source is `source`. The normal form is given both as
+/// a string and as the tree of the term it reads back to.
+pub fn to_json(file: &Path, source: &str, stmt: &Checked) -> Value {
+    let errors = stmt.error.iter().map(|e| {
+        json!({
+            "severity": "error",
+            "message": e.to_string(),
+            "span": stmt.span.map(|span| span_to_json(source, span)),
+        })
+    });
+    // Holes are not errors, but they are still to be filled.
+    let goals = stmt.goals.iter().map(|goal| {
+        json!({
+            "severity": "info",
+            "message": goal.to_string(),
+            "span": span_to_json(source, goal.span),
+        })
+    });
+    let diagnostics = errors.chain(goals).collect::<Vec<_>>();
+
+    json!({
+        "file": file.display().to_string(),
+        "kind": stmt.kind,
+        "name": stmt.name,
+        "type": stmt.ty.as_ref().map(ToString::to_string),
+        "normal_form": stmt.value.as_ref().map(|val| json!({
+            "pretty": val.to_string(),
+            "tree": lift(0, val.clone()).ok(),
+        })),
+        "span": stmt.span.map(|span| span_to_json(source, span)),
+        "time_ms": stmt.elapsed.as_secs_f64() * 1000.0,
+        "diagnostics": diagnostics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use pi_lib::{env::TypeCtx, parse::check_file};
+
+    use super::{location, to_json};
+
+    #[test]
+    fn test_to_json() {
+        let file = Path::new("../test_file/check/errors.pi");
+        let report = check_file(file, &mut TypeCtx::new());
+
+        let z = to_json(file, &report.source, &report.statements[2]);
+        assert_eq!(z["kind"], "let");
+        assert_eq!(z["name"], "z");
+        assert_eq!(z["type"], "ℕ");
+        assert_eq!(z["normal_form"]["pretty"], "S x");
+        assert!(z["normal_form"]["tree"]["Succ"].is_object());
+        assert_eq!(z["diagnostics"].as_array().map(Vec::len), Some(0));
+
+        let y = to_json(file, &report.source, &report.statements[1]);
+        assert_eq!(y["diagnostics"][0]["span"]["start_line"], 2);
+        assert!(y["normal_form"].is_null());
+
+        let span = report.statements[3].span;
+        assert_eq!(
+            location(file, &report.source, span),
+            "../test_file/check/errors.pi:4:1"
+        );
+
+        let file = Path::new("../test_file/check/holes.pi");
+        let report = check_file(file, &mut TypeCtx::new());
+        let pred = to_json(file, &report.source, &report.statements[0]);
+        assert_eq!(pred["diagnostics"][0]["severity"], "info");
+        assert_eq!(pred["diagnostics"][0]["message"], "? : ℕ\n  n : ℕ");
+        assert_eq!(pred["diagnostics"][0]["span"]["start_column"], 38);
+    }
+}
@@ -0,0 +1,421 @@
+//! The interactive shell.
+
+use std::{
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use pi_lib::{
+    ast::Statement,
+    env::TypeCtx,
+    fuel::{limits, set_limits},
+    hole::{self, collect_goals},
+    lexer::{Lexer, Tok},
+    module::resolve_name,
+    parse::{ExprParser, ProgramParser},
+    session::Session,
+};
+use rustyline::{error::ReadlineError, history::FileHistory, Editor};
+
+use crate::helper::PiHelper;
+
+const PROMPT: &str = ">>> ";
+const CONTINUATION: &str = "... ";
+const HISTORY_FILE: &str = ".pi_history";
+
+const HELP: &str = "\
+:type e        infer the type of e
+:normalize e   evaluate e to its normal form
+:load file     load the definitions in a file
+:reload        start over, load the loaded files again and replay the definitions
+:undo          undo the last definition, redefinition, :load, :reload or :restore
+:save-point s  remember the current definitions as s
+:restore s     go back to the save point s
+:browse [M]    list the globals (in module M) with their types
+:info x        show the type and the definition of x
+:goals         show the holes `?` left by the last input
+:give n e      fill hole n with e
+:refine n f    fill hole n with f applied to new holes
+:split n x     fill hole n with a case analysis on x : ℕ
+:intro n       fill hole n with lambdas for its arguments
+:set fuel N    stop each statement after N steps (or `off`); Ctrl-C stops it too
+:set timeout s stop each statement after s seconds (or `off`)
+:help          show this message
+:quit          quit (also exit or Ctrl-D)";
+
+/// Whether `input` is a whole statement, i.e., its last token is a `;` outside of braces. Modules
+/// are the only statements that end with `}` instead. Comments are skipped, and a comment or a
+/// string that is still open goes on to the next line.
+fn is_complete(input: &str) -> bool {
+    let mut toks = vec![];
+    for tok in Lexer::new(input) {
+        match tok {
+            Ok((_, Tok::Doc(_), _)) => (),
+            Ok((_, tok, _)) => toks.push(tok),
+            Err(e) if e.span.end == input.len() => return false,
+            // The parser reports the other errors.
+            Err(_) => (),
+        }
+    }
+
+    let depth = toks.iter().fold(0isize, |depth, tok| match tok {
+        Tok::LBrace => depth + 1,
+        Tok::RBrace => depth - 1,
+        _ => depth,
+    });
+    let is_module = matches!(
+        toks[..],
+        [Tok::Module, ..] | [Tok::Private, Tok::Module, ..]
+    );
+
+    depth <= 0
+        && match toks.last() {
+            Some(Tok::Semicolon) => true,
+            Some(Tok::RBrace) => is_module,
+            _ => false,
+        }
+}
+
+/// Commands that are not statements of the language and need no terminator.
+fn is_command(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with(':') || line == "exit" || line == "show" || line.starts_with("show ")
+}
+
+pub struct Repl {
+    editor: Editor<PiHelper, FileHistory>,
+    history: Option<PathBuf>,
+    session: Session,
+    /// The last input that left holes, which the hole commands rewrite.
+    pending: Option<String>,
+}
+
+impl Repl {
+    pub fn new(ctx: TypeCtx) -> Result<Self> {
+        let mut editor = Editor::new()?;
+        editor.set_helper(Some(PiHelper::default()));
+        let history = dirs::home_dir().map(|home| home.join(HISTORY_FILE));
+        if let Some(history) = history.as_ref() {
+            // There is no history before the first session.
+            let _ = editor.load_history(history);
+        }
+
+        // Ctrl-C stops the running statement rather than the shell; while a line is read, the
+        // editor handles it.
+        let cancel = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGINT, cancel.clone())?;
+        let mut limits = limits();
+        limits.cancel = Some(cancel);
+        set_limits(limits);
+
+        Ok(Self {
+            editor,
+            history,
+            session: Session::new(ctx),
+            pending: None,
+        })
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        println!("Welcome to the Pi interpreter!");
+        println!("Type :help for a list of commands, 'exit' or press Ctrl-D to quit.\n");
+
+        while let Some(input) = self.read()? {
+            let input = input.trim();
+            let res = match input.strip_prefix(':') {
+                Some("quit" | "q") => break,
+                Some(cmd) => self.command(cmd),
+                None if input == "exit" => break,
+                None if input == "show" || input.starts_with("show ") => {
+                    self.show(input["show".len()..].trim());
+                    Ok(())
+                }
+                None => self.eval(input),
+            };
+
+            if let Err(e) = res {
+                eprintln!("{e}");
+            }
+        }
+
+        self.save_history();
+        Ok(())
+    }
+
+    /// Reads lines until they form a statement or a command. Returns `None` on Ctrl-D.
+    fn read(&mut self) -> Result<Option<String>> {
+        let mut input = String::new();
+
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.names = self
+                .session
+                .ctx
+                .globals()
+                .into_iter()
+                .map(|(x, _)| x)
+                .collect();
+        }
+
+        loop {
+            let prompt = match input.is_empty() {
+                true => PROMPT,
+                false => CONTINUATION,
+            };
+
+            match self.editor.readline(prompt) {
+                Ok(line) => {
+                    if input.is_empty() && is_command(&line) {
+                        self.editor.add_history_entry(line.trim())?;
+                        return Ok(Some(line));
+                    }
+
+                    input.push_str(&line);
+                    input.push('\n');
+                    if input.trim().is_empty() {
+                        input.clear();
+                    } else if is_complete(&input) {
+                        self.editor.add_history_entry(input.trim())?;
+                        return Ok(Some(input));
+                    }
+                }
+                // Ctrl-C drops what has been typed so far.
+                Err(ReadlineError::Interrupted) => input.clear(),
+                Err(ReadlineError::Eof) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn eval(&mut self, input: &str) -> Result<()> {
+        let stmts = ProgramParser::new()
+            .parse(input)
+            .map_err(|e| anyhow!("{}", e))?;
+
+        // An error is reported and the statements after it still run, as in `check`.
+        let mut holes = false;
+        for (_, stmt) in stmts {
+            let (res, goals) = collect_goals(|| self.session.run(stmt));
+            match res {
+                Ok(res) => println!("{res}"),
+                Err(e) => eprintln!("{e}"),
+            }
+            holes |= !goals.is_empty();
+        }
+
+        self.pending = holes.then(|| input.to_string());
+        if holes {
+            self.goals()?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the goals of the pending input.
+    fn goals(&self) -> Result<()> {
+        let input = self
+            .pending
+            .as_deref()
+            .ok_or_else(|| anyhow!("There are no holes."))?;
+        for (i, goal) in hole::goals(input, &self.session.ctx)?.iter().enumerate() {
+            println!("[{i}] {goal}");
+        }
+
+        Ok(())
+    }
+
+    /// Fills hole `n` of the pending input with `fill` and runs the new input.
+    fn fill<F>(&mut self, arg: &str, fill: F) -> Result<()>
+    where
+        F: FnOnce(&str, &TypeCtx, usize, &str) -> pi_lib::err::EvalResult<String>,
+    {
+        let (n, arg) = arg.split_once(char::is_whitespace).unwrap_or((arg, ""));
+        let n = n
+            .parse::<usize>()
+            .map_err(|_| anyhow!("Expected the number of a hole, found {n}"))?;
+        let input = self
+            .pending
+            .clone()
+            .ok_or_else(|| anyhow!("There are no holes."))?;
+
+        let ctx = &self.session.ctx;
+        let goal = hole::goals(&input, ctx)?
+            .into_iter()
+            .nth(n)
+            .ok_or_else(|| anyhow!("There is no hole {n}."))?;
+        let input = fill(&input, ctx, goal.span.start, arg.trim())?;
+
+        println!("{}", input.trim());
+        self.eval(&input)
+    }
+
+    fn command(&mut self, input: &str) -> Result<()> {
+        let (cmd, arg) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+        let arg = arg.trim().trim_end_matches(';').trim_end();
+
+        match cmd {
+            "type" | "t" => {
+                let e = ExprParser::new().parse(arg).map_err(|e| anyhow!("{}", e))?;
+                println!("{}", self.session.run(Statement::Check(e.into_inner()))?);
+            }
+            "normalize" | "n" => {
+                let e = ExprParser::new().parse(arg).map_err(|e| anyhow!("{}", e))?;
+                println!("{}", self.session.run(Statement::Eval(e.into_inner()))?);
+            }
+            "load" | "l" => {
+                self.session.load(PathBuf::from(arg))?;
+                println!("Loaded {arg}.");
+            }
+            "reload" | "r" => {
+                let errors = self.session.reload();
+                for e in errors.iter() {
+                    eprintln!("{e}");
+                }
+                println!("Reloaded with {} error(s).", errors.len());
+            }
+            "undo" | "u" => match self.session.undo() {
+                Some(names) if names.is_empty() => println!("Undone."),
+                Some(names) => println!("Undone {}.", names.join(", ")),
+                None => return Err(anyhow!("There is nothing to undo.")),
+            },
+            "save-point" => {
+                if arg.is_empty() {
+                    return Err(anyhow!("Usage: :save-point name"));
+                }
+                self.session.save_point(arg);
+                println!("Saved {arg}.");
+            }
+            "restore" => {
+                self.session.restore(arg)?;
+                println!("Restored {arg}.");
+            }
+            "browse" | "b" => {
+                let prefix = match arg.is_empty() {
+                    true => String::new(),
+                    false => format!("{arg}."),
+                };
+                for (name, ty) in self.session.ctx.globals() {
+                    if name.starts_with(&prefix) {
+                        println!("{name} : {ty}");
+                    }
+                }
+            }
+            "info" | "i" => {
+                let name = resolve_name(arg, &self.session.ctx)?
+                    .ok_or_else(|| anyhow!("{arg} is not defined."))?;
+                let ty = self
+                    .session
+                    .ctx
+                    .globals()
+                    .into_iter()
+                    .find(|(x, _)| *x == name)
+                    .map(|(_, ty)| ty)
+                    .ok_or_else(|| anyhow!("{arg} is not defined."))?;
+
+                if let Some(doc) = self.session.ctx.doc(&name) {
+                    println!("{doc}");
+                }
+                println!("{name} : {ty}");
+                if let Some(val) = self.session.ctx.definition(&name) {
+                    println!("{name} := {val}");
+                }
+            }
+            "set" => self.set(arg)?,
+            "goals" => self.goals()?,
+            "give" => self.fill(arg, hole::give)?,
+            "refine" => self.fill(arg, hole::refine)?,
+            "split" => self.fill(arg, hole::case_split)?,
+            "intro" => self.fill(arg, |input, ctx, offset, _| hole::intro(input, ctx, offset))?,
+            "help" | "h" | "?" => println!("{HELP}"),
+            _ => return Err(anyhow!("Unknown command :{cmd}; type :help for a list.")),
+        }
+
+        Ok(())
+    }
+
+    /// Sets the fuel or the timeout of the statements, or turns it `off`.
+    fn set(&self, arg: &str) -> Result<()> {
+        let (option, value) = arg.split_once(char::is_whitespace).unwrap_or((arg, ""));
+        let value = value.trim();
+        let mut limits = limits();
+
+        match (option, value) {
+            ("fuel", "off") => limits.fuel = None,
+            ("fuel", n) => {
+                let n = n
+                    .parse()
+                    .map_err(|_| anyhow!("Expected a number of steps, found {n}"))?;
+                limits.fuel = Some(n);
+            }
+            ("timeout", "off") => limits.timeout = None,
+            ("timeout", s) => {
+                let timeout = s
+                    .parse()
+                    .ok()
+                    .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                    .ok_or_else(|| anyhow!("Expected a number of seconds, found {s}"))?;
+                limits.timeout = Some(timeout);
+            }
+            _ => return Err(anyhow!("Usage: :set fuel N|off or :set timeout s|off")),
+        }
+
+        set_limits(limits);
+        println!("Set {option} to {value}.");
+        Ok(())
+    }
+
+    /// Prints the globals whose names start with `prefix` in the order they are declared, with
+    /// their definitions unless they are postulates.
+    fn show(&self, prefix: &str) {
+        let globals = self
+            .session
+            .ctx
+            .globals()
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(prefix))
+            .collect::<Vec<_>>();
+        let width = globals
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        for (name, ty) in globals {
+            println!("{name:width$} : {ty}");
+            if let Some(val) = self.session.ctx.definition(&name) {
+                println!("{:width$} := {val}", "");
+            }
+        }
+    }
+
+    fn save_history(&mut self) {
+        if let Some(history) = self.history.as_ref() {
+            if let Err(e) = self.editor.save_history(history) {
+                log::warn!("Cannot save the history to {}: {}", history.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_complete;
+
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete("eval U;"));
+        assert!(!is_complete("def NatElim :: ∀ (m : ℕ -> U),\n"));
+        assert!(!is_complete("record R := { a : ℕ;"));
+        assert!(is_complete("module M where {\n let x := 1;\n}\n"));
+        assert!(!is_complete("let r := { a = 1 }"));
+
+        // Comments and strings are skipped.
+        assert!(is_complete("let x := 1; -- note\n"));
+        assert!(is_complete("--| The answer.\nlet x := {- { -} 42;\n"));
+        assert!(!is_complete("let x := 1 -- ;\n"));
+        assert!(!is_complete("let x := {- ; \n"));
+        assert!(!is_complete("import \"a;\n"));
+        assert!(is_complete("private module M where { let x := 1; } -- }\n"));
+    }
+}
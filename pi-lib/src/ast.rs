@@ -1,18 +1,87 @@
 use crate::{
     err::{EvalError, EvalResult},
-    term::{CheckableTerm, Term, VariableName},
+    fixpoint::Fixpoint,
+    pattern,
+    stack::{self, Node},
+    term::{Binder, CheckableTerm, Term, VariableName},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
     Eval(AstNode),
+    /// Infers the type of an expression.
     Check(AstNode),
     Declare(String, AstNode),
     // Alias.
     Let(String, AstNode),
+    /// Recursive definition by pattern matching.
+    Rec(String, AstNode, Vec<Clause>),
+    /// Record declaration with its parameters and fields.
+    Record(String, Vec<Node<AstNode>>, Vec<(String, AstNode)>),
+    /// Module `module M where { ... }` whose definitions are named `M.x`.
+    Module(String, Vec<(Span, Statement)>),
+    /// Loads the file at the given path, relative to the importing file.
+    Import(String),
+    /// A definition that is only visible inside its module and file.
+    Private(Box<Statement>),
+    /// A definition after its doc comment `--| ...`, given without the `--|`.
+    Documented(String, Box<Statement>),
+    /// The fixity of operators, e.g., `infixl 6 _+_ _-_;`.
+    Fixity(Fixity, Vec<String>),
 }
 
-#[derive(Debug, Clone)]
+/// How an operator groups with the operators of the same level next to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+    /// Neither, so `a == b == c` is an error.
+    Non,
+}
+
+/// How tightly an operator binds; a higher level binds tighter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixity {
+    pub assoc: Assoc,
+    pub level: usize,
+}
+
+/// A range of bytes in the source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The line and the column, both starting from 1, of the byte `offset` in `source`.
+    pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+        let before = &source[..offset.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let col = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        (line, col)
+    }
+}
+
+/// A clause `| p1 p2 ... = e` of a recursive definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clause {
+    pub patterns: Vec<Pattern>,
+    pub body: AstNode,
+}
+
+/// Patterns on the left-hand side of a clause.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Var(String),
+    Wildcard,
+    Zero,
+    Num(usize),
+    Succ(Box<Pattern>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     Boolean,
     Integer,
@@ -20,76 +89,156 @@ pub enum Type {
 }
 
 /// This represents the ast nodes in our core lambda calculus.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AstNode {
     AnnotatedTerm {
-        term: Box<AstNode>,
-        ty: Box<AstNode>,
+        term: Node<AstNode>,
+        ty: Node<AstNode>,
     },
     /// Basic types
     Type(Type),
     /// Applications.
     App {
-        clos: Box<AstNode>,
-        arg: Box<AstNode>,
+        clos: Node<AstNode>,
+        arg: Node<AstNode>,
     },
+    /// An application in parentheses, which keeps `(a + b) * c` apart from `a + b * c` until the
+    /// operators are resolved (see `crate::fixity`).
+    Paren(Node<AstNode>),
     Nat,
-    Succ(Box<AstNode>),
+    Succ(Node<AstNode>),
     Num(usize),
-    /// Variables.
-    Var(String),
+    /// Variables and where they occur.
+    Var(String, Span),
+    /// A hole `?` to be filled in later.
+    Hole(Span),
     Universe,
     /// Lambda abstractions.
     Lambda {
         arg: String,
-        body: Box<AstNode>,
+        body: Node<AstNode>,
     },
     DependentFunctionSpace {
-        arg: Box<AstNode>,
-        ret: Box<AstNode>,
+        arg: Node<AstNode>,
+        ret: Node<AstNode>,
     },
     Forall {
-        args: Vec<Box<AstNode>>,
-        ret: Box<AstNode>,
+        args: Vec<Node<AstNode>>,
+        ret: Node<AstNode>,
+    },
+    /// Pattern matching: `case e of { p1 -> e1; ... }`.
+    Case {
+        scrutinee: Node<AstNode>,
+        arms: Vec<Clause>,
+    },
+    /// Record types: `{ x : A, y : B x }`.
+    RecordType {
+        fields: Vec<(String, AstNode)>,
+    },
+    /// Record literals: `{ x = a, y = b }`.
+    Record {
+        fields: Vec<(String, AstNode)>,
+    },
+    /// Projections: `e.x`.
+    Proj {
+        term: Node<AstNode>,
+        field: String,
     },
 }
 
+impl Statement {
+    /// Desugars a record declaration `record R (x : A) ... := { ... }` into the definition
+    /// `λ x ... -> { ... } :: ∀ (x : A) ... . U`.
+    pub fn record_definition(params: &[Node<AstNode>], fields: &[(String, AstNode)]) -> AstNode {
+        let ty = match params.is_empty() {
+            true => AstNode::Universe,
+            false => AstNode::Forall {
+                args: params.to_vec(),
+                ret: Node::new(AstNode::Universe),
+            },
+        };
+        let term = params.iter().rev().fold(
+            AstNode::RecordType {
+                fields: fields.to_vec(),
+            },
+            |body, param| match param.as_ref() {
+                AstNode::AnnotatedTerm { term, .. } => match term.as_ref() {
+                    AstNode::Var(arg, _) => AstNode::Lambda {
+                        arg: arg.clone(),
+                        body: Node::new(body),
+                    },
+                    _ => body,
+                },
+                _ => body,
+            },
+        );
+
+        AstNode::AnnotatedTerm {
+            term: Node::new(term),
+            ty: Node::new(ty),
+        }
+    }
+}
+
+/// Checks that no field is given twice.
+fn check_fields(fields: &[(String, AstNode)]) -> EvalResult<()> {
+    for (i, (name, _)) in fields.iter().enumerate() {
+        if fields[..i].iter().any(|(n, _)| n == name) {
+            return Err(EvalError::ParseError(format!(
+                "Field {name} is given more than once."
+            )));
+        }
+    }
+
+    Ok(())
+}
+
 fn ast_transform_checkable(ast: &AstNode, symbols: Vec<String>) -> EvalResult<CheckableTerm> {
-    match ast {
+    stack::grow(move || match ast {
         AstNode::Lambda { arg, body } => {
             let mut symbols = symbols.clone();
             // Add the argument to the symbols list.
             symbols.push(arg.clone());
-            let body = ast_transform_checkable(&body, symbols.clone())?;
+            let body = ast_transform_checkable(body, symbols.clone())?;
 
             Ok(CheckableTerm::Lambda {
-                term: Box::new(body),
+                name: Binder::new(arg),
+                term: Node::new(body),
             })
         }
+        AstNode::Record { fields } => {
+            check_fields(fields)?;
+            // Fields are kept sorted so that equal records have equal terms.
+            let mut fields = fields
+                .iter()
+                .map(|(name, e)| Ok((name.clone(), ast_transform_checkable(e, symbols.clone())?)))
+                .collect::<EvalResult<Vec<_>>>()?;
+            fields.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+
+            Ok(CheckableTerm::Record { fields })
+        }
         _ => Ok(CheckableTerm::InfereableTerm {
-            term: Box::new(ast_transform(ast, symbols)?),
+            term: Node::new(ast_transform(ast, symbols)?),
         }),
-    }
+    })
 }
 
 fn num_to_succ(num: usize) -> Term {
-    match num {
-        0 => Term::Zero,
-        _ => Term::Succ {
-            pred: Box::new(num_to_succ(num - 1)),
-        },
-    }
+    (0..num).fold(Term::Zero, |pred, _| Term::Succ {
+        pred: Node::new(pred),
+    })
 }
 
 /// This function transforms the AST into a checkable term.
 pub(crate) fn ast_transform(ast: &AstNode, symbols: Vec<String>) -> EvalResult<Term> {
-    match ast {
+    stack::grow(move || match ast {
+        AstNode::Paren(e) => ast_transform(e, symbols),
         AstNode::Universe => Ok(Term::Universe),
         AstNode::Nat => Ok(Term::Nat),
         AstNode::Succ(pred) => {
             let pred = ast_transform(pred, symbols)?;
             Ok(Term::Succ {
-                pred: Box::new(pred),
+                pred: Node::new(pred),
             })
         }
         AstNode::AnnotatedTerm { term, ty } => {
@@ -97,28 +246,54 @@ pub(crate) fn ast_transform(ast: &AstNode, symbols: Vec<String>) -> EvalResult<T
             let ty = ast_transform_checkable(ty, symbols)?;
 
             Ok(Term::AnnotatedTerm {
-                term: Box::new(t),
-                ty: Box::new(ty),
+                term: Node::new(t),
+                ty: Node::new(ty),
             })
         }
         // Get its relative index; if not, we defer it and look up in the context.
         //
         // Why don't we just return the error? This is because parsing is unaware
         // of the context, so we must defer the error to the type checking phase.
-        AstNode::Var(name) => match symbols.iter().rev().position(|x| x == name) {
+        AstNode::Var(name, _) => match symbols.iter().rev().position(|x| x == name) {
             Some(index) => Ok(Term::Bounded(index)),
             None => Ok(Term::Var(VariableName::Global(name.clone()))),
         },
+        AstNode::Hole(span) => Ok(Term::Hole {
+            span: *span,
+            names: symbols,
+        }),
         AstNode::Lambda { .. } => Err(EvalError::ParseError(
             "Cannot parse lambda without type annotation.".to_string(),
         )),
+        AstNode::Record { .. } => Err(EvalError::ParseError(
+            "Cannot parse record literal without type annotation.".to_string(),
+        )),
+        AstNode::RecordType { fields } => {
+            check_fields(fields)?;
+            // Each field can refer to the fields before it.
+            let mut symbols = symbols.clone();
+            let fields = fields
+                .iter()
+                .map(|(name, ty)| {
+                    let ty = ast_transform_checkable(ty, symbols.clone())?;
+                    symbols.push(name.clone());
+                    Ok((name.clone(), ty))
+                })
+                .collect::<EvalResult<Vec<_>>>()?;
+
+            Ok(Term::RecordType { fields })
+        }
+        AstNode::Proj { term, field } => Ok(Term::Proj {
+            term: Node::new(ast_transform(term, symbols)?),
+            field: field.clone(),
+        }),
         AstNode::App { clos, arg } => {
             let clos = ast_transform(clos, symbols.clone())?;
             let arg = ast_transform_checkable(arg, symbols)?;
 
             Ok(Term::App {
-                clos: Box::new(clos),
-                arg: Box::new(arg),
+                clos: Node::new(clos),
+                arg: Node::new(arg),
             })
         }
         AstNode::DependentFunctionSpace { arg, ret } => {
@@ -129,23 +304,109 @@ pub(crate) fn ast_transform(ast: &AstNode, symbols: Vec<String>) -> EvalResult<T
             let ret = ast_transform_checkable(ret, new_symbol)?;
 
             Ok(Term::DependentFunctionSpace {
-                arg: Box::new(arg),
-                ret: Box::new(ret),
+                name: Binder::default(),
+                arg: Node::new(arg),
+                ret: Node::new(ret),
             })
         }
         AstNode::Num(num) => Ok(Term::AnnotatedTerm {
-            term: Box::new(CheckableTerm::InfereableTerm {
-                term: Box::new(num_to_succ(*num)),
+            term: Node::new(CheckableTerm::InfereableTerm {
+                term: Node::new(num_to_succ(*num)),
             }),
-            ty: Box::new(CheckableTerm::InfereableTerm {
-                term: Box::new(Term::Nat),
+            ty: Node::new(CheckableTerm::InfereableTerm {
+                term: Node::new(Term::Nat),
             }),
         }),
         AstNode::Forall { args, ret } => build_forall_binding_list(args, ret, symbols.clone()),
+        AstNode::Case { scrutinee, arms } => {
+            let scrutinee = ast_transform_checkable(scrutinee, symbols.clone())?;
+            let arms = arms
+                .iter()
+                .map(|arm| ast_transform_clause(arm, symbols.clone()))
+                .collect::<EvalResult<Vec<_>>>()?;
+
+            Ok(Term::Case {
+                scrutinee: Node::new(scrutinee),
+                arms,
+            })
+        }
         _ => todo!("{ast:?}"),
+    })
+}
+
+/// This transforms a pattern into its nameless form and records the variables it binds in
+/// `symbols`, from left to right.
+fn pattern_transform(pattern: &Pattern, symbols: &mut Vec<String>) -> EvalResult<pattern::Pattern> {
+    match pattern {
+        Pattern::Var(name) => {
+            if symbols.contains(name) {
+                return Err(EvalError::ParseError(format!(
+                    "Variable {name} is bound more than once in the same clause."
+                )));
+            }
+
+            symbols.push(name.clone());
+            Ok(pattern::Pattern::Var(Binder::new(name)))
+        }
+        Pattern::Wildcard => {
+            symbols.push("".to_string());
+            Ok(pattern::Pattern::wildcard())
+        }
+        Pattern::Zero => Ok(pattern::Pattern::Zero),
+        Pattern::Num(num) => Ok((0..*num).fold(pattern::Pattern::Zero, |pred, _| {
+            pattern::Pattern::Succ(Box::new(pred))
+        })),
+        Pattern::Succ(pred) => Ok(pattern::Pattern::Succ(Box::new(pattern_transform(
+            pred, symbols,
+        )?))),
     }
 }
 
+/// This transforms a clause; the body sees the pattern variables as bounded variables where
+/// the rightmost one has index 0.
+pub(crate) fn ast_transform_clause(
+    clause: &Clause,
+    mut symbols: Vec<String>,
+) -> EvalResult<pattern::Clause> {
+    let mut bound = vec![];
+    let patterns = clause
+        .patterns
+        .iter()
+        .map(|p| pattern_transform(p, &mut bound))
+        .collect::<EvalResult<Vec<_>>>()?;
+    symbols.extend(bound);
+    let body = ast_transform_checkable(&clause.body, symbols)?;
+
+    Ok(pattern::Clause { patterns, body })
+}
+
+/// This transforms a recursive definition and ensures that all its clauses agree on the arity.
+pub(crate) fn ast_transform_fixpoint(name: &str, clauses: &[Clause]) -> EvalResult<Fixpoint> {
+    let clauses = clauses
+        .iter()
+        .map(|clause| ast_transform_clause(clause, vec![]))
+        .collect::<EvalResult<Vec<_>>>()?;
+    let arity = clauses.first().map_or(0, |c| c.patterns.len());
+
+    if arity == 0 {
+        return Err(EvalError::ParseError(format!(
+            "Recursive definition {name} must match on at least one argument."
+        )));
+    }
+
+    if clauses.iter().any(|c| c.patterns.len() != arity) {
+        return Err(EvalError::ParseError(format!(
+            "All clauses of {name} must have {arity} patterns."
+        )));
+    }
+
+    Ok(Fixpoint {
+        name: name.to_string(),
+        arity,
+        clauses,
+    })
+}
+
 /// This builds the forall binding list by constructing a nested dependent function type.
 ///
 /// # Examples
@@ -160,7 +421,7 @@ pub(crate) fn ast_transform(ast: &AstNode, symbols: Vec<String>) -> EvalResult<T
 /// ℕ -> (ℕ -> ℕ)
 /// ```
 pub(crate) fn build_forall_binding_list(
-    bindings: &[Box<AstNode>],
+    bindings: &[Node<AstNode>],
     ret: &AstNode,
     mut symbols: Vec<String>,
 ) -> EvalResult<Term> {
@@ -171,9 +432,9 @@ pub(crate) fn build_forall_binding_list(
     }
 
     if let AstNode::AnnotatedTerm { term, ty } = bindings.first().unwrap().as_ref() {
-        if let AstNode::Var(x) = term.as_ref() {
+        if let AstNode::Var(x, _) = term.as_ref() {
             let arg = CheckableTerm::InfereableTerm {
-                term: Box::new(ast_transform(ty, symbols.clone())?),
+                term: Node::new(ast_transform(ty, symbols.clone())?),
             };
             symbols.push(x.clone());
 
@@ -182,19 +443,20 @@ pub(crate) fn build_forall_binding_list(
                 false => build_forall_binding_list(&bindings[1..], ret, symbols.clone())?,
             };
             Ok(Term::DependentFunctionSpace {
-                arg: Box::new(arg),
-                ret: Box::new(CheckableTerm::InfereableTerm {
-                    term: Box::new(ret),
+                name: Binder::new(x),
+                arg: Node::new(arg),
+                ret: Node::new(CheckableTerm::InfereableTerm {
+                    term: Node::new(ret),
                 }),
             })
         } else {
-            return Err(EvalError::ParseError(
+            Err(EvalError::ParseError(
                 "Cannot parse forall binding list.".to_string(),
-            ));
+            ))
         }
     } else {
-        return Err(EvalError::ParseError(
+        Err(EvalError::ParseError(
             "Cannot parse forall binding list.".to_string(),
-        ));
+        ))
     }
 }
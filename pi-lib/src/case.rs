@@ -0,0 +1,259 @@
+//! Dependent pattern matching with `case e of { p1 -> e1; ... }`.
+//!
+//! When a case expression is checked against a type `T`, the motive is inferred by abstracting
+//! every occurrence of the scrutinee in `T`; each arm is then checked against `T` where the
+//! scrutinee is replaced by the arm's pattern. For example, in
+//!
+//! ```
+//! λ n -> case n of { O -> e1; S m -> e2 } :: ∀ (n : ℕ) . P n
+//! ```
+//!
+//! `e1` is checked against `P O` and `e2` against `P (S m)`.
+
+use crate::{
+    coverage::check_coverage,
+    env::{Ctx, EvalCtx, TypeCtx},
+    err::{EvalError, EvalResult},
+    eval::{convertible, eval_checked, lift, sanity_check, subst_checked, type_check},
+    pattern::{check_pattern, match_clause, Clause, Match},
+    stack::{self, Node},
+    term::{CheckableTerm, Neutral, Term, Type, Value, VariableName},
+};
+
+/// Evaluates a case expression, which is neutral if no arm can be selected yet.
+pub(crate) fn eval_case(
+    scrutinee: CheckableTerm,
+    arms: Vec<Clause>,
+    ctx: EvalCtx,
+) -> EvalResult<Value> {
+    let val = eval_checked(scrutinee, ctx.clone())?;
+
+    for arm in arms.iter() {
+        match match_clause(&arm.patterns, std::slice::from_ref(&val))? {
+            Match::Matched(bindings) => {
+                let mut ctx = ctx;
+                for val in bindings {
+                    ctx.1 = ctx.1.push(val);
+                }
+
+                return eval_checked(arm.body.clone(), ctx);
+            }
+            Match::Mismatch => continue,
+            Match::Stuck => break,
+        }
+    }
+
+    Ok(Value::VNeutral(Neutral::NCase {
+        scrutinee: Node::new(val),
+        arms,
+        ctx: Box::new(ctx),
+    }))
+}
+
+fn infer_scrutinee(
+    de_brujin_index: usize,
+    scrutinee: &CheckableTerm,
+    ctx: &TypeCtx,
+) -> EvalResult<Type> {
+    match scrutinee {
+        CheckableTerm::InfereableTerm { term } => {
+            type_check(de_brujin_index, (**term).clone(), ctx.clone())
+        }
+        CheckableTerm::Zero | CheckableTerm::Succ { .. } => {
+            sanity_check(de_brujin_index, scrutinee.clone(), ctx.clone(), Value::VNat)
+                .map(|_| Value::VNat)
+        }
+        CheckableTerm::Lambda { .. } | CheckableTerm::Record { .. } => Err(
+            EvalError::TypeMismatch(format!("Cannot match on {}", scrutinee)),
+        ),
+    }
+}
+
+/// Binds the pattern variables of an arm and substitutes them into its body.
+///
+/// Returns the value the pattern stands for, the body and the index of the next fresh local.
+fn bind_arm(
+    de_brujin_index: usize,
+    arm: &Clause,
+    scrutinee_ty: &Type,
+    ctx: &mut TypeCtx,
+) -> EvalResult<(Value, CheckableTerm, usize)> {
+    let mut next = de_brujin_index;
+    let val = check_pattern(&arm.patterns[0], scrutinee_ty.clone(), ctx, &mut next)?;
+
+    let arity = next - de_brujin_index;
+    let body = (0..arity).fold(arm.body.clone(), |body, i| {
+        subst_checked(
+            arity - i - 1,
+            Term::Var(VariableName::Local(de_brujin_index + i)),
+            body,
+        )
+    });
+
+    Ok((val, body, next))
+}
+
+fn check_arms_coverage(arms: &[Clause]) -> EvalResult<()> {
+    let rows = arms
+        .iter()
+        .map(|arm| arm.patterns.clone())
+        .collect::<Vec<_>>();
+    check_coverage("the case expression", 1, &rows)
+}
+
+/// Checks a case expression against `ty`, refining `ty` in each arm.
+pub(crate) fn check_case(
+    de_brujin_index: usize,
+    scrutinee: &CheckableTerm,
+    arms: &[Clause],
+    ctx: TypeCtx,
+    ty: Type,
+) -> EvalResult<()> {
+    let scrutinee_ty = infer_scrutinee(de_brujin_index, scrutinee, &ctx)?;
+    check_arms_coverage(arms)?;
+
+    let env = EvalCtx(ctx.0.clone(), Ctx::new());
+    let what = lift(0, eval_checked(scrutinee.clone(), env.clone())?)?;
+    let motive = lift(0, ty)?;
+
+    arms.iter().try_for_each(|arm| {
+        let mut ctx = ctx.clone();
+        let (val, body, next) = bind_arm(de_brujin_index, arm, &scrutinee_ty, &mut ctx)?;
+        let refined = replace_checked(&motive, &what, &lift(0, val)?);
+
+        sanity_check(next, body, ctx, eval_checked(refined, env.clone())?)
+    })
+}
+
+/// Infers the type of a case expression whose arms all have the same type.
+pub(crate) fn infer_case(
+    de_brujin_index: usize,
+    scrutinee: &CheckableTerm,
+    arms: &[Clause],
+    ctx: TypeCtx,
+) -> EvalResult<Type> {
+    let scrutinee_ty = infer_scrutinee(de_brujin_index, scrutinee, &ctx)?;
+    check_arms_coverage(arms)?;
+
+    let mut res: Option<Type> = None;
+    for arm in arms.iter() {
+        let mut ctx = ctx.clone();
+        let (_, body, next) = bind_arm(de_brujin_index, arm, &scrutinee_ty, &mut ctx)?;
+
+        let ty = match body {
+            CheckableTerm::InfereableTerm { term } => type_check(next, term.into_inner(), ctx)?,
+            _ => {
+                return Err(EvalError::TypeMismatch(
+                    "Cannot infer the type of a case expression; add a type annotation."
+                        .to_string(),
+                ))
+            }
+        };
+
+        match &res {
+            Some(expected) if !convertible(0, expected, &ty)? => {
+                return Err(EvalError::TypeMismatch(format!(
+                    "Case arms have different types: {} and {}",
+                    expected, ty
+                )))
+            }
+            Some(_) => (),
+            None => res = Some(ty),
+        }
+    }
+
+    res.ok_or_else(|| {
+        EvalError::TypeMismatch("Cannot infer the type of an empty case expression".to_string())
+    })
+}
+
+/// Replaces every occurrence of `what` in `term` by `with`. Since `what` is closed with respect
+/// to bounded variables, nothing can be captured.
+fn replace_checked(
+    term: &CheckableTerm,
+    what: &CheckableTerm,
+    with: &CheckableTerm,
+) -> CheckableTerm {
+    stack::grow(move || {
+        if term == what {
+            return with.clone();
+        }
+
+        match term {
+            CheckableTerm::InfereableTerm { term } => CheckableTerm::InfereableTerm {
+                term: Node::new(replace(term, what, with)),
+            },
+            CheckableTerm::Lambda { name, term } => CheckableTerm::Lambda {
+                name: name.clone(),
+                term: Node::new(replace_checked(term, what, with)),
+            },
+            CheckableTerm::Succ { term } => CheckableTerm::Succ {
+                term: Node::new(replace_checked(term, what, with)),
+            },
+            CheckableTerm::Zero => CheckableTerm::Zero,
+            CheckableTerm::Record { fields } => CheckableTerm::Record {
+                fields: fields
+                    .iter()
+                    .map(|(name, e)| (name.clone(), replace_checked(e, what, with)))
+                    .collect(),
+            },
+        }
+    })
+}
+
+fn replace(term: &Term, what: &CheckableTerm, with: &CheckableTerm) -> Term {
+    stack::grow(move || {
+        if matches!(what, CheckableTerm::InfereableTerm { term: what } if what.as_ref() == term) {
+            return match with {
+                CheckableTerm::InfereableTerm { term } => (**term).clone(),
+                // Constructors are only checkable, so we annotate them.
+                _ => Term::AnnotatedTerm {
+                    term: Node::new(with.clone()),
+                    ty: Node::new(CheckableTerm::InfereableTerm {
+                        term: Node::new(Term::Nat),
+                    }),
+                },
+            };
+        }
+
+        match term {
+            Term::AnnotatedTerm { term, ty } => Term::AnnotatedTerm {
+                term: Node::new(replace_checked(term, what, with)),
+                ty: Node::new(replace_checked(ty, what, with)),
+            },
+            Term::App { clos, arg } => Term::App {
+                clos: Node::new(replace(clos, what, with)),
+                arg: Node::new(replace_checked(arg, what, with)),
+            },
+            Term::DependentFunctionSpace { name, arg, ret } => Term::DependentFunctionSpace {
+                name: name.clone(),
+                arg: Node::new(replace_checked(arg, what, with)),
+                ret: Node::new(replace_checked(ret, what, with)),
+            },
+            Term::Succ { pred } => Term::Succ {
+                pred: Node::new(replace(pred, what, with)),
+            },
+            Term::Case { scrutinee, arms } => Term::Case {
+                scrutinee: Node::new(replace_checked(scrutinee, what, with)),
+                arms: arms
+                    .iter()
+                    .map(|arm| Clause {
+                        patterns: arm.patterns.clone(),
+                        body: replace_checked(&arm.body, what, with),
+                    })
+                    .collect(),
+            },
+            Term::RecordType { fields } => Term::RecordType {
+                fields: fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), replace_checked(ty, what, with)))
+                    .collect(),
+            },
+            Term::Proj { term, field } => Term::Proj {
+                term: Node::new(replace(term, what, with)),
+                field: field.clone(),
+            },
+            _ => term.clone(),
+        }
+    })
+}
@@ -1,56 +1,121 @@
-//! Defines a trait for a closures.
+//! First-order closures.
+//!
+//! A closure is the body of a binder together with the context that it was evaluated in, rather
+//! than a Rust function, so it can be printed, compared, hashed and serialized like the rest of a
+//! value; calling it evaluates the body with the argument bound. Functions that are implemented
+//! in Rust can still be wrapped as [`Native`] closures.
 
-use std::{fmt, sync::Arc};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
 
-use crate::err::EvalResult;
+use crate::{
+    env::EvalCtx,
+    err::EvalResult,
+    eval::eval_checked,
+    fixpoint::{self, Fixpoint},
+    stack::Node,
+    term::{Binder, CheckableTerm, Value},
+};
 
-#[macro_export]
-macro_rules! clos {
-    (lambda $arg:tt -> $body:tt, $ctx:expr) => {
-        $crate::clos::Closure::new(Box::new(move |$arg| $body), $ctx)
-    };
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Closure {
+    /// The body of a `λ` or of a `Π`, whose own variable is at index 0.
+    Term {
+        name: Binder,
+        body: Node<CheckableTerm>,
+        ctx: EvalCtx,
+    },
+    /// A recursive definition that is applied to fewer arguments than it matches on.
+    Fixpoint {
+        fix: Arc<Fixpoint>,
+        args: Vec<Value>,
+        ctx: EvalCtx,
+    },
+    Native(Native),
 }
 
+/// A function implemented in Rust. Two native closures are equal only if they are the same
+/// function, and they are printed and serialized by their names.
 #[derive(Clone)]
-pub struct Closure<T, C, R>
-where
-    T: Clone,
-    C: Clone,
-    R: Clone,
-{
-    pub f: Arc<dyn Fn(T, C) -> EvalResult<R> + Send + Sync>,
-    pub ctx: C,
-}
-
-// Since closures are "hiding" the actual function, we cannot print them.
-//
-// One trick is to define a function that lifts back the closure into the
-// raw AST node. Sometimes it is useful to define it to debug the closure.
-//
-// Recall what we can do in Racket using quasiquote, quote, unquote magic.
-impl<T, C, R> fmt::Debug for Closure<T, C, R>
-where
-    T: Clone,
-    C: Clone,
-    R: Clone,
-{
+pub struct Native {
+    pub name: String,
+    pub f: Arc<dyn Fn(Value) -> EvalResult<Value> + Send + Sync>,
+}
+
+impl Closure {
+    pub fn new(name: Binder, body: Node<CheckableTerm>, ctx: EvalCtx) -> Self {
+        Self::Term { name, body, ctx }
+    }
+
+    /// The name of the variable that the closure binds, if it is known.
+    pub fn name(&self) -> Binder {
+        match self {
+            Closure::Term { name, .. } => name.clone(),
+            _ => Binder::default(),
+        }
+    }
+
+    pub fn native(
+        name: &str,
+        f: impl Fn(Value) -> EvalResult<Value> + Send + Sync + 'static,
+    ) -> Self {
+        Self::Native(Native {
+            name: name.to_string(),
+            f: Arc::new(f),
+        })
+    }
+
+    pub fn call(&self, x: Value) -> EvalResult<Value> {
+        match self {
+            Closure::Term { body, ctx, .. } => {
+                let mut ctx = ctx.clone();
+                ctx.1 = ctx.1.push(x);
+                eval_checked((**body).clone(), ctx)
+            }
+            Closure::Fixpoint { fix, args, ctx } => fixpoint::apply(fix, args, x, ctx),
+            Closure::Native(native) => (native.f)(x),
+        }
+    }
+}
+
+impl fmt::Debug for Closure {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Closure")
+        match self {
+            // The globals are referred to by name in the body, so only the locals are shown.
+            Closure::Term { body, ctx, .. } => write!(f, "λ . {:?} with {:?}", body, ctx.1),
+            Closure::Fixpoint { fix, args, .. } => write!(f, "{} applied to {:?}", fix.name, args),
+            Closure::Native(native) => write!(f, "{:?}", native),
+        }
+    }
+}
+
+impl PartialEq for Native {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && Arc::ptr_eq(&self.f, &other.f)
+    }
+}
+
+impl Eq for Native {}
+
+impl Hash for Native {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
     }
 }
 
-impl<T, C, R> Closure<T, C, R>
-where
-    T: Clone,
-    C: Clone,
-    R: Clone
-{
-    pub fn new(f: Arc<dyn Fn(T, C) -> EvalResult<R> + Send + Sync>, ctx: C) -> Self {
-        Self { f, ctx }
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}>", self.name)
     }
+}
 
-    pub fn call(&self, x: T) -> EvalResult<R> {
-        // We do not need to keep this closure: it is consumed.
-        (self.f)(x, self.ctx.clone())
+#[cfg(feature = "serde")]
+impl serde::Serialize for Native {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name)
     }
 }
@@ -0,0 +1,142 @@
+//! Exhaustiveness and redundancy checking for pattern matching.
+//!
+//! This follows Maranget's "Warnings for pattern matching": a list of clauses is viewed as a
+//! matrix of patterns whose rows are tried from top to bottom. A row is redundant if it is not
+//! useful with respect to the rows above it, and the clauses are exhaustive if a row of wildcards
+//! is not useful with respect to all of them.
+
+use crate::{
+    err::{EvalError, EvalResult},
+    pattern::{Constructor, Pattern},
+};
+
+type Row = Vec<Pattern>;
+
+/// Keeps the rows that may match a value built from `c` and exposes the arguments of `c`.
+fn specialize(rows: &[Row], c: Constructor) -> Vec<Row> {
+    rows.iter()
+        .filter_map(|row| {
+            let args = match row[0].head() {
+                Some((head, args)) if head == c => args,
+                Some(_) => return None,
+                None => vec![Pattern::wildcard(); c.arity()],
+            };
+
+            Some(args.into_iter().chain(row[1..].iter().cloned()).collect())
+        })
+        .collect()
+}
+
+/// Keeps the rows that match any constructor not mentioned in the first column.
+fn default(rows: &[Row]) -> Vec<Row> {
+    rows.iter()
+        .filter(|row| row[0].head().is_none())
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+/// The constructors in the first column and whether they are all the constructors of their type.
+fn heads(rows: &[Row]) -> (Vec<Constructor>, bool) {
+    let mut heads = vec![];
+    for row in rows {
+        if let Some((c, _)) = row[0].head() {
+            if !heads.contains(&c) {
+                heads.push(c);
+            }
+        }
+    }
+
+    let complete = heads
+        .first()
+        .is_some_and(|c| c.siblings().iter().all(|s| heads.contains(s)));
+    (heads, complete)
+}
+
+/// Whether some value matched by `row` is not matched by any of `rows`.
+fn useful(rows: &[Row], row: &[Pattern]) -> bool {
+    if row.is_empty() {
+        return rows.is_empty();
+    }
+
+    match row[0].head() {
+        Some((c, args)) => {
+            let row = args
+                .into_iter()
+                .chain(row[1..].iter().cloned())
+                .collect::<Row>();
+            useful(&specialize(rows, c), &row)
+        }
+        None => match heads(rows) {
+            (heads, true) => heads.into_iter().any(|c| {
+                let row = vec![Pattern::wildcard(); c.arity()]
+                    .into_iter()
+                    .chain(row[1..].iter().cloned())
+                    .collect::<Row>();
+                useful(&specialize(rows, c), &row)
+            }),
+            (_, false) => useful(&default(rows), &row[1..]),
+        },
+    }
+}
+
+/// Finds `width` patterns that no row matches, if any.
+fn missing(rows: &[Row], width: usize) -> Option<Row> {
+    if width == 0 {
+        return rows.is_empty().then(Vec::new);
+    }
+
+    match heads(rows) {
+        (heads, true) => heads.into_iter().find_map(|c| {
+            let mut witness = missing(&specialize(rows, c), c.arity() + width - 1)?;
+            let rest = witness.split_off(c.arity());
+            Some(std::iter::once(c.apply(witness)).chain(rest).collect())
+        }),
+        (heads, false) => {
+            let rest = missing(&default(rows), width - 1)?;
+            // Pick a constructor that no row mentions, or a wildcard if there is none at all.
+            let first = match heads.first() {
+                Some(c) => c
+                    .siblings()
+                    .iter()
+                    .find(|s| !heads.contains(s))
+                    .map(|s| s.apply(vec![Pattern::wildcard(); s.arity()]))
+                    .unwrap_or_else(Pattern::wildcard),
+                None => Pattern::wildcard(),
+            };
+            Some(std::iter::once(first).chain(rest).collect())
+        }
+    }
+}
+
+fn show_row(row: &[Pattern]) -> String {
+    row.iter()
+        .map(|p| match p {
+            Pattern::Succ(_) if row.len() > 1 => format!("({:?})", p),
+            _ => format!("{:?}", p),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Checks that the clauses of `what`, each matching on `width` values, are exhaustive and that
+/// none of them is unreachable.
+pub fn check_coverage(what: &str, width: usize, rows: &[Row]) -> EvalResult<()> {
+    for (i, row) in rows.iter().enumerate() {
+        if !useful(&rows[..i], row) {
+            return Err(EvalError::CoverageError(format!(
+                "The clause `{}` of {} is unreachable",
+                show_row(row),
+                what
+            )));
+        }
+    }
+
+    match missing(rows, width) {
+        Some(witness) => Err(EvalError::CoverageError(format!(
+            "The clauses of {} do not cover `{}`",
+            what,
+            show_row(&witness)
+        ))),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,457 @@
+//! Files that are being edited, e.g., in an editor through the language server.
+//!
+//! A document is checked again whenever it changes. Since the context after each statement is
+//! kept, the statements before the first one that changed are not checked again.
+
+use std::path::PathBuf;
+
+use crate::{
+    ast::{AstNode, Clause, Span, Statement},
+    env::TypeCtx,
+    err::EvalError,
+    lexer::after_docs,
+    module::{pattern_variables, resolve, segments},
+    parse::{check_statement, Checked, ProgramParser},
+    stack,
+    term::Type,
+};
+
+#[derive(Debug)]
+struct Entry {
+    /// The source of the statement.
+    text: String,
+    stmt: Statement,
+    checked: Checked,
+    /// The context after the statement.
+    after: TypeCtx,
+}
+
+/// A definition in a document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    /// The global name, e.g., `M.x` for `x` in `module M`.
+    pub name: String,
+    /// The keyword of the definition, e.g., `let`.
+    pub kind: &'static str,
+    /// The whole definition.
+    pub span: Span,
+    /// The name in the definition.
+    pub name_span: Span,
+    /// The definitions in a module.
+    pub children: Vec<Symbol>,
+}
+
+#[derive(Debug)]
+pub struct Document {
+    /// The file, which imports are relative to.
+    path: Option<PathBuf>,
+    /// The context the document is checked in, e.g., the prelude.
+    base: TypeCtx,
+    source: String,
+    entries: Vec<Entry>,
+    /// The syntax errors in the source.
+    syntax_errors: Vec<Checked>,
+}
+
+/// Finds the variable, or the qualified name `M.x`, at `offset` with the local variables that
+/// are bound there. Binders are not names that refer to something, so they are skipped.
+fn name_at<'a>(
+    ast: &'a AstNode,
+    offset: usize,
+    locals: &[String],
+) -> Option<(&'a AstNode, Vec<String>)> {
+    stack::grow(move || {
+        let contains = |span: Span| span.start <= offset && offset <= span.end;
+        let bind = |names: &[String]| [locals, names].concat();
+
+        match ast {
+            AstNode::Var(_, span) => contains(*span).then(|| (ast, locals.to_vec())),
+            AstNode::Proj { term, .. } => match segments(ast) {
+                Some((segs, span))
+                    if contains(Span {
+                        start: span.start,
+                        end: span.start + segs.join(".").len(),
+                    }) =>
+                {
+                    Some((ast, locals.to_vec()))
+                }
+                _ => name_at(term, offset, locals),
+            },
+            AstNode::AnnotatedTerm { term: e1, ty: e2 }
+            | AstNode::App { clos: e1, arg: e2 }
+            | AstNode::DependentFunctionSpace { arg: e1, ret: e2 } => {
+                name_at(e1, offset, locals).or_else(|| name_at(e2, offset, locals))
+            }
+            AstNode::Succ(pred) | AstNode::Paren(pred) => name_at(pred, offset, locals),
+            AstNode::Lambda { arg, body } => {
+                name_at(body, offset, &bind(std::slice::from_ref(arg)))
+            }
+            AstNode::Forall { args, ret } => {
+                let mut locals = locals.to_vec();
+                for binding in args.iter() {
+                    if let AstNode::AnnotatedTerm { term, ty } = binding.as_ref() {
+                        if let Some(res) = name_at(ty, offset, &locals) {
+                            return Some(res);
+                        }
+                        if let AstNode::Var(x, _) = term.as_ref() {
+                            locals.push(x.clone());
+                        }
+                    }
+                }
+                name_at(ret, offset, &locals)
+            }
+            AstNode::Case { scrutinee, arms } => name_at(scrutinee, offset, locals).or_else(|| {
+                arms.iter()
+                    .find_map(|arm| clause_name_at(arm, offset, locals))
+            }),
+            AstNode::RecordType { fields } => {
+                let mut locals = locals.to_vec();
+                fields.iter().find_map(|(name, ty)| {
+                    let res = name_at(ty, offset, &locals);
+                    locals.push(name.clone());
+                    res
+                })
+            }
+            AstNode::Record { fields } => {
+                fields.iter().find_map(|(_, e)| name_at(e, offset, locals))
+            }
+            AstNode::Hole(_)
+            | AstNode::Type(_)
+            | AstNode::Nat
+            | AstNode::Num(_)
+            | AstNode::Universe => None,
+        }
+    })
+}
+
+fn clause_name_at<'a>(
+    clause: &'a Clause,
+    offset: usize,
+    locals: &[String],
+) -> Option<(&'a AstNode, Vec<String>)> {
+    let mut locals = locals.to_vec();
+    for pattern in clause.patterns.iter() {
+        pattern_variables(pattern, &mut locals);
+    }
+    name_at(&clause.body, offset, &locals)
+}
+
+/// The span of the first whole word `ident` in `span`, which is the name of a definition since
+/// keywords are not identifiers.
+fn name_span(source: &str, span: Span, ident: &str) -> Span {
+    let text = &source[span.start..span.end];
+    let is_ident =
+        |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '\'');
+
+    text.match_indices(ident)
+        .find(|(i, _)| {
+            !is_ident(text[..*i].chars().next_back())
+                && !is_ident(text[i + ident.len()..].chars().next())
+        })
+        .map_or(span, |(i, _)| Span {
+            start: span.start + i,
+            end: span.start + i + ident.len(),
+        })
+}
+
+fn symbol(source: &str, span: Span, stmt: &Statement, path: &[String]) -> Option<Symbol> {
+    let qualify = |ident: &str| {
+        path.iter()
+            .map(String::as_str)
+            .chain(std::iter::once(ident))
+            .collect::<Vec<_>>()
+            .join(".")
+    };
+    let (kind, ident) = match stmt {
+        Statement::Declare(x, _) => ("def", x),
+        Statement::Let(x, _) => ("let", x),
+        Statement::Rec(x, _, _) => ("rec", x),
+        Statement::Record(x, _, _) => ("record", x),
+        Statement::Module(x, _) => ("module", x),
+        Statement::Private(stmt) => return symbol(source, span, stmt, path),
+        Statement::Documented(_, stmt) => {
+            // The name is looked for after the doc comment, which may mention it.
+            let start = span.start + after_docs(&source[span.start..span.end]);
+            return symbol(source, Span { start, ..span }, stmt, path);
+        }
+        Statement::Eval(_) | Statement::Check(_) | Statement::Import(_) | Statement::Fixity(..) => {
+            return None
+        }
+    };
+
+    let children = match stmt {
+        Statement::Module(x, body) => {
+            let path = [path, std::slice::from_ref(x)].concat();
+            body.iter()
+                .filter_map(|(span, stmt)| symbol(source, *span, stmt, &path))
+                .collect()
+        }
+        _ => vec![],
+    };
+
+    Some(Symbol {
+        name: qualify(ident),
+        kind,
+        span,
+        name_span: name_span(source, span, ident),
+        children,
+    })
+}
+
+impl Document {
+    pub fn new(path: Option<PathBuf>, base: TypeCtx) -> Self {
+        Self {
+            path,
+            base,
+            source: String::new(),
+            entries: vec![],
+            syntax_errors: vec![],
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Checks the new source of the document and returns how many statements were checked.
+    pub fn update(&mut self, source: String) -> usize {
+        self.source = source;
+
+        let (stmts, errors) = ProgramParser::new().parse_all(&self.source);
+        self.syntax_errors = errors
+            .into_iter()
+            .map(|e| Checked {
+                span: Some(e.span),
+                kind: "file",
+                name: None,
+                ty: None,
+                value: None,
+                error: Some(EvalError::ParseError(e.message)),
+                goals: vec![],
+                elapsed: Default::default(),
+            })
+            .collect();
+
+        // Keep the statements up to the first one that changed.
+        let unchanged = stmts
+            .iter()
+            .zip(self.entries.iter())
+            .take_while(|((span, _), entry)| {
+                entry.checked.span == Some(*span) && self.source[span.start..span.end] == entry.text
+            })
+            .count();
+        self.entries.truncate(unchanged);
+
+        let mut ctx = match self.entries.last() {
+            Some(entry) => entry.after.clone(),
+            None => self.base(),
+        };
+
+        let count = stmts.len() - unchanged;
+        for (span, stmt) in stmts.into_iter().skip(unchanged) {
+            let checked = check_statement(Some(span), stmt.clone(), &mut ctx);
+            self.entries.push(Entry {
+                text: self.source[span.start..span.end].to_string(),
+                stmt,
+                checked,
+                after: ctx.clone(),
+            });
+        }
+
+        count
+    }
+
+    /// The statements and the syntax errors in the order they appear.
+    pub fn statements(&self) -> impl Iterator<Item = &Checked> {
+        let mut res = self
+            .syntax_errors
+            .iter()
+            .chain(self.entries.iter().map(|entry| &entry.checked))
+            .collect::<Vec<_>>();
+        res.sort_by_key(|checked| checked.span.map(|span| span.start));
+        res.into_iter()
+    }
+
+    /// The context before the document, e.g., to check a new source of it.
+    pub fn base(&self) -> TypeCtx {
+        let mut ctx = self.base.clone();
+        ctx.2.file = self.path.clone();
+        ctx.2.path = vec![];
+        ctx
+    }
+
+    /// The context after the document.
+    pub fn ctx(&self) -> &TypeCtx {
+        self.entries.last().map_or(&self.base, |entry| &entry.after)
+    }
+
+    /// The definitions in the document.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        self.entries
+            .iter()
+            .filter_map(|entry| symbol(&self.source, entry.checked.span?, &entry.stmt, &[]))
+            .collect()
+    }
+
+    /// The global at `offset` and where its name is, either where it is referred to or where it
+    /// is defined.
+    pub fn global_at(&self, offset: usize) -> Option<(String, Span)> {
+        if let Some(s) = flatten(self.symbols())
+            .into_iter()
+            .find(|s| s.name_span.start <= offset && offset <= s.name_span.end)
+        {
+            return Some((s.name, s.name_span));
+        }
+
+        let entry = self.entries.iter().find(|entry| {
+            entry
+                .checked
+                .span
+                .is_some_and(|span| span.start <= offset && offset < span.end)
+        })?;
+        let mut ctx = entry.after.clone();
+        let (stmt, extra) = find(&entry.stmt, offset, &mut ctx)?;
+
+        let (ast, locals) = name_at_statement(stmt, offset)?;
+        let mut node = resolve(&ast, &locals, &ctx, extra.as_deref()).ok()?;
+        while let AstNode::Proj { term, .. } = node {
+            node = term.into_inner();
+        }
+
+        match node {
+            AstNode::Var(name, span) if !locals.contains(&name) => {
+                ctx.type_of(&name).map(|_| (name, span))
+            }
+            _ => None,
+        }
+    }
+
+    /// The type of the global at `offset` and where its name is.
+    pub fn hover(&self, offset: usize) -> Option<(String, Type, Span)> {
+        let (name, span) = self.global_at(offset)?;
+        let ty = self.ctx().type_of(&name)?;
+        Some((name, ty, span))
+    }
+
+    /// Where the global at `offset` is defined in the document.
+    pub fn definition(&self, offset: usize) -> Option<Symbol> {
+        let (name, _) = self.global_at(offset)?;
+        flatten(self.symbols())
+            .into_iter()
+            .rfind(|s| s.name == name)
+    }
+}
+
+/// The symbols and the symbols in them in the order they are defined.
+fn flatten(symbols: Vec<Symbol>) -> Vec<Symbol> {
+    symbols
+        .into_iter()
+        .flat_map(|s| {
+            let children = flatten(s.children.clone());
+            std::iter::once(s).chain(children)
+        })
+        .collect()
+}
+
+/// Finds the innermost statement in `stmt` at `offset`, entering modules in `ctx`. Returns it
+/// with the function it defines recursively, if any.
+fn find<'a>(
+    stmt: &'a Statement,
+    offset: usize,
+    ctx: &mut TypeCtx,
+) -> Option<(&'a Statement, Option<String>)> {
+    match stmt {
+        Statement::Module(x, body) => {
+            ctx.2.path.push(x.clone());
+            let (_, stmt) = body
+                .iter()
+                .find(|(span, _)| span.start <= offset && offset < span.end)?;
+            find(stmt, offset, ctx)
+        }
+        Statement::Private(stmt) | Statement::Documented(_, stmt) => find(stmt, offset, ctx),
+        Statement::Rec(x, _, _) => Some((stmt, Some(ctx.2.qualify(x)))),
+        _ => Some((stmt, None)),
+    }
+}
+
+/// Finds the name at `offset` in a statement that is not a module.
+fn name_at_statement(stmt: &Statement, offset: usize) -> Option<(AstNode, Vec<String>)> {
+    let res = match stmt {
+        Statement::Eval(e)
+        | Statement::Check(e)
+        | Statement::Declare(_, e)
+        | Statement::Let(_, e) => name_at(e, offset, &[]),
+        Statement::Rec(_, ty, clauses) => name_at(ty, offset, &[]).or_else(|| {
+            clauses
+                .iter()
+                .find_map(|clause| clause_name_at(clause, offset, &[]))
+        }),
+        Statement::Record(_, params, fields) => {
+            // The desugared definition keeps the spans of the names.
+            let def = Statement::record_definition(params, fields);
+            return name_at(&def, offset, &[]).map(|(ast, locals)| (ast.clone(), locals));
+        }
+        Statement::Module(..)
+        | Statement::Private(_)
+        | Statement::Documented(..)
+        | Statement::Import(_)
+        | Statement::Fixity(..) => None,
+    };
+
+    res.map(|(ast, locals)| (ast.clone(), locals))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{document::Document, env::TypeCtx};
+
+    #[test]
+    fn test_document() {
+        let src = "def x :: ℕ;\nlet y := S x;\nlet f := λ x -> x :: ℕ -> ℕ;\nmodule M where {\n    let z := y;\n}\neval M.z;\n";
+        let offset = |pat: &str| src.find(pat).unwrap();
+
+        let mut doc = Document::new(None, TypeCtx::new());
+        assert_eq!(doc.update(src.to_string()), 5);
+        assert!(doc.statements().all(|stmt| stmt.error.is_none()));
+
+        let (name, ty, span) = doc.hover(offset("S x") + 2).unwrap();
+        assert_eq!((name.as_str(), ty.to_string().as_str()), ("x", "ℕ"));
+        assert_eq!(&src[span.start..span.end], "x");
+
+        // `x` in the lambda is a local variable.
+        assert!(doc.hover(offset("-> x") + 3).is_none());
+
+        let def = doc.definition(offset("M.z") + 2).unwrap();
+        assert_eq!(def.name, "M.z");
+        assert_eq!(&src[def.name_span.start..def.name_span.end], "z");
+        assert_eq!(doc.hover(offset("z :=")).unwrap().0, "M.z");
+
+        let symbols = doc.symbols();
+        let names = symbols.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["x", "y", "f", "M"]);
+        assert_eq!(symbols[3].children[0].name, "M.z");
+
+        // Only the changed statement is checked again.
+        let src = src.replace("eval M.z;", "eval M.w;");
+        assert_eq!(doc.update(src), 1);
+        assert!(doc.statements().last().unwrap().error.is_some());
+
+        assert_eq!(doc.update("let a := ;".to_string()), 0);
+        let errors = doc.statements().collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span.map(|span| span.start), Some(9));
+
+        // The statements around a syntax error are still checked.
+        assert_eq!(doc.update("let a := ;\nlet b := 1;".to_string()), 1);
+        let kinds = doc.statements().map(|stmt| stmt.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, ["file", "let"]);
+        assert_eq!(doc.hover(15).unwrap().0, "b");
+
+        // The name of a documented definition is not looked for in its doc comment.
+        let src = "--| two is two.\nlet two := 2;\n";
+        doc.update(src.to_string());
+        let symbol = &doc.symbols()[0];
+        assert_eq!(symbol.name_span.start, src.find("two :=").unwrap());
+        assert_eq!(doc.ctx().doc("two"), Some("two is two."));
+    }
+}
@@ -1,34 +1,89 @@
 //! The typing environment.
 
 use std::{
+    collections::HashSet,
     fmt,
+    hash::{Hash, Hasher},
     ops::{Index, IndexMut},
 };
 
-use crate::term::{Type, Value, VariableName};
+use im::{HashMap, Vector};
+
+use crate::{
+    ast::Fixity,
+    fixity,
+    module::Scope,
+    term::{Type, Value, VariableName},
+};
 
 /// A context is a list of variables and their values and unamed values..
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct EvalCtx(
-    pub Ctx<(VariableName, Value)>, // Names to their values.
-    pub Ctx<Type>,                  // Names to their types.
+    pub Bindings<Value>, // Names to their values.
+    pub Ctx<Value>,      // The values of the bound variables.
 );
 
 #[derive(Clone, Debug)]
 pub struct TypeCtx(
-    pub Ctx<(VariableName, Value)>, // Names to their definitions.
-    pub Ctx<(VariableName, Type)>,  // Names to their types.
+    pub Bindings<Value>, // Names to their definitions.
+    pub Bindings<Type>,  // Names to their types.
+    pub Scope,           // The current module and imports.
 );
 
 impl EvalCtx {
     pub fn new() -> Self {
-        Self(Ctx::Nil, Ctx::Nil)
+        Self(Bindings::new(), Ctx::new())
     }
 }
 
 impl TypeCtx {
     pub fn new() -> Self {
-        Self(Ctx::Nil, Ctx::Nil)
+        Self(Bindings::new(), Bindings::new(), Scope::default())
+    }
+
+    /// The visible globals and their types in the order they are declared. A name that is
+    /// declared again is listed at its last declaration.
+    pub fn globals(&self) -> Vec<(String, Type)> {
+        let mut seen = HashSet::new();
+        let mut res = vec![];
+        for (name, ty) in self.1.iter() {
+            if let VariableName::Global(name) = name {
+                if self.2.is_visible(name) && seen.insert(name) {
+                    res.push((name.clone(), ty.clone()));
+                }
+            }
+        }
+
+        res.reverse();
+        res
+    }
+
+    /// The type of the global `name`.
+    pub fn type_of(&self, name: &str) -> Option<Type> {
+        self.1.get(&VariableName::Global(name.to_string())).cloned()
+    }
+
+    /// The doc comment of the global `name`, if it has one.
+    pub fn doc(&self, name: &str) -> Option<&str> {
+        self.2
+            .docs
+            .iter()
+            .rfind(|(x, _)| x == name)
+            .map(|(_, doc)| doc.as_str())
+    }
+
+    /// The fixity of the operator `name`.
+    pub fn fixity(&self, name: &str) -> Fixity {
+        self.2
+            .fixities
+            .iter()
+            .rfind(|(x, _)| x == name)
+            .map_or(fixity::DEFAULT, |(_, fixity)| *fixity)
+    }
+
+    /// The definition of a global, if it is not just a declaration.
+    pub fn definition(&self, name: &str) -> Option<Value> {
+        self.0.get(&VariableName::Global(name.to_string())).cloned()
     }
 }
 
@@ -44,121 +99,243 @@ impl Default for TypeCtx {
     }
 }
 
+// The globals are referred to by name in the terms that are evaluated under a context, so only
+// the locals are serialized; the globals would repeat the whole session in every closure.
+#[cfg(feature = "serde")]
+impl serde::Serialize for EvalCtx {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.1.iter())
+    }
+}
+
 impl From<TypeCtx> for EvalCtx {
     fn from(ctx: TypeCtx) -> Self {
-        let mut tctx = Self::default();
-        tctx.0 = ctx.0;
+        // Checked terms refer to their variables by name, so no variable is bound yet.
+        Self(ctx.0, Ctx::new())
+    }
+}
 
-        for i in ctx.1.into_iter() {
-            tctx.1 = tctx.1.push(i.1.clone());
-        }
+/// The values of the variables bound around a term, looked up by their de Bruijn indices: the
+/// innermost one is at index 0. It is a persistent vector, so that pushing onto a context that a
+/// closure holds and cloning it are cheap.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Ctx<T: Clone>(Vector<T>);
+
+impl<T: Clone> Ctx<T> {
+    pub fn new() -> Self {
+        Self(Vector::new())
+    }
+
+    pub fn push(&self, elem: T) -> Self {
+        let mut res = self.clone();
+        res.0.push_back(elem);
+        res
+    }
 
-        tctx
+    /// The variable at de Bruijn index `index`.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let len = self.0.len();
+        index
+            .checked_sub(len)
+            .map_or_else(|| self.0.get(len - index - 1), |_| None)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The variables from the innermost one out.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.0.iter().rev()
     }
 }
 
-/// This is a FP-like list.
-#[derive(Clone)]
-pub enum Ctx<T>
-where
-    T: Clone + fmt::Debug,
-{
-    Nil,
-    Cons { elem: T, rest: Box<Ctx<T>> },
-}
-
-impl<T> Index<usize> for Ctx<T>
-where
-    T: Clone + fmt::Debug,
-{
+impl<T: Clone> Default for Ctx<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Index<usize> for Ctx<T> {
     type Output = T;
 
     fn index(&self, index: usize) -> &Self::Output {
-        match self {
-            Ctx::Nil => panic!("Index out of bounds"),
-            Ctx::Cons { elem, rest } => {
-                if index == 0 {
-                    elem
-                } else {
-                    rest.index(index - 1)
-                }
-            }
-        }
+        self.get(index).expect("Index out of bounds")
     }
 }
 
-impl<T> IndexMut<usize> for Ctx<T>
-where
-    T: Clone + fmt::Debug,
-{
+impl<T: Clone> IndexMut<usize> for Ctx<T> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        match self {
-            Ctx::Nil => panic!("Index out of bounds"),
-            Ctx::Cons { elem, rest } => {
-                if index == 0 {
-                    elem
-                } else {
-                    rest.index_mut(index - 1)
-                }
-            }
-        }
+        let len = self.0.len();
+        index
+            .checked_sub(len)
+            .map_or_else(|| self.0.get_mut(len - index - 1), |_| None)
+            .expect("Index out of bounds")
     }
 }
 
-impl<T> fmt::Debug for Ctx<T>
-where
-    T: Clone + fmt::Debug,
-{
+/// Takes the variables from the innermost one out.
+impl<T: Clone> Iterator for Ctx<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+}
+
+impl<T: Clone + fmt::Debug> fmt::Debug for Ctx<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Ctx::Nil => write!(f, "[]"),
-            Ctx::Cons { elem, rest } => write!(f, "{:?} :: {:?}", elem, rest),
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Names and what they are bound to. Each name has a stack of bindings, the latest one on top,
+/// so that looking a name up takes constant time however many names there are; the names are
+/// also kept in the order they are bound, for listing them. Like [`Ctx`], it is persistent.
+#[derive(Clone)]
+pub struct Bindings<T: Clone> {
+    names: HashMap<VariableName, Vector<T>>,
+    order: Vector<VariableName>,
+}
+
+impl<T: Clone> Bindings<T> {
+    pub fn new() -> Self {
+        Self {
+            names: HashMap::new(),
+            order: Vector::new(),
         }
     }
+
+    /// Binds `name` to `value`, shadowing the bindings of `name` so far.
+    pub fn push(&self, (name, value): (VariableName, T)) -> Self {
+        let mut res = self.clone();
+        res.names.entry(name.clone()).or_default().push_back(value);
+        res.order.push_back(name);
+        res
+    }
+
+    /// The latest binding of `name`.
+    pub fn get(&self, name: &VariableName) -> Option<&T> {
+        self.names.get(name).and_then(Vector::last)
+    }
+
+    /// The number of bindings, including the shadowed ones.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// All the bindings from the latest one back, including the shadowed ones.
+    pub fn iter(&self) -> impl Iterator<Item = (&VariableName, &T)> {
+        let mut seen = std::collections::HashMap::<&VariableName, usize>::new();
+        self.order.iter().rev().map(move |name| {
+            let stack = &self.names[name];
+            let above = seen.entry(name).or_default();
+            *above += 1;
+            (name, &stack[stack.len() - *above])
+        })
+    }
 }
 
-impl<T> Iterator for Ctx<T>
-where
-    T: Clone + fmt::Debug,
-{
-    type Item = T;
+impl<T: Clone> Default for Bindings<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Takes the bindings from the latest one back.
+impl<T: Clone> Iterator for Bindings<T> {
+    type Item = (VariableName, T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self {
-            Ctx::Nil => None,
-            Ctx::Cons { elem, rest } => {
-                let elem = elem.clone();
-                *self = *rest.clone();
-                Some(elem)
-            }
+        let name = self.order.pop_back()?;
+        let stack = self.names.get_mut(&name)?;
+        let value = stack.pop_back()?;
+        if stack.is_empty() {
+            self.names.remove(&name);
         }
+
+        Some((name, value))
     }
 }
 
-impl<T> Ctx<T>
-where
-    T: Clone + fmt::Debug,
-{
-    pub fn lookup<F>(&self, pred: F) -> Option<T>
-    where
-        F: Fn(&T) -> bool,
-    {
-        match self {
-            Ctx::Nil => None,
-            Ctx::Cons { elem, rest } => {
-                if pred(elem) {
-                    Some(elem.clone())
-                } else {
-                    rest.lookup(pred)
-                }
-            }
-        }
+// Bindings are compared binding by binding, but copies of each other, e.g., the globals of the
+// closures made in the same session, share their storage and are equal at once.
+impl<T: Clone + PartialEq> PartialEq for Bindings<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && ((self.order.ptr_eq(&other.order) && self.names.ptr_eq(&other.names))
+                || (self.order == other.order && self.names == other.names))
     }
+}
 
-    pub fn push(&self, elem: T) -> Self {
-        Ctx::Cons {
-            elem,
-            rest: Box::new(self.clone()),
-        }
+impl<T: Clone + Eq> Eq for Bindings<T> {}
+
+// Only the number of bindings and the latest name are hashed, so that hashing a closure does not
+// hash the whole session; equal bindings still have equal hashes.
+impl<T: Clone> Hash for Bindings<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.order.len().hash(state);
+        self.order.back().hash(state);
+    }
+}
+
+impl<T: Clone + fmt::Debug> fmt::Debug for Bindings<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bindings, Ctx};
+    use crate::term::VariableName;
+
+    #[test]
+    fn test_bindings() {
+        let x = || VariableName::Global("x".to_string());
+        let y = || VariableName::Local(0);
+        let outer = Bindings::new().push((x(), 1)).push((y(), 2));
+        let inner = outer.push((x(), 3));
+
+        // The latest binding shadows the others, and `outer` is unchanged.
+        assert_eq!(inner.get(&x()), Some(&3));
+        assert_eq!(outer.get(&x()), Some(&1));
+        assert_eq!(inner.get(&VariableName::Local(1)), None);
+        assert_eq!(
+            inner.iter().collect::<Vec<_>>(),
+            [(&x(), &3), (&y(), &2), (&x(), &1)]
+        );
+        assert_eq!((inner.len(), outer.len()), (3, 2));
+
+        let ctx = Ctx::new().push('a').push('b');
+        assert_eq!((ctx[0], ctx[1], ctx.get(2)), ('b', 'a', None));
+
+        // Bindings are compared value by value, whether they are copies or not.
+        assert_eq!(inner, inner.clone());
+        assert_eq!(Bindings::<i32>::new(), Bindings::new());
+        assert_eq!(outer.push((x(), 3)), inner);
+        assert_ne!(outer.push((x(), 4)), inner);
+        assert_ne!(outer.push((y(), 3)), inner);
+    }
+
+    #[test]
+    fn test_list_api() {
+        let x = || VariableName::Global("x".to_string());
+        let mut ctx = Ctx::new().push('a').push('b');
+        ctx[1] = 'c';
+        assert_eq!(ctx.iter().find(|c| **c != 'b'), Some(&'c'));
+        assert_eq!(ctx.collect::<Vec<_>>(), ['b', 'c']);
+
+        let bindings = Bindings::new().push((x(), 1)).push((x(), 2));
+        assert_eq!(bindings.iter().find(|(_, v)| **v < 2), Some((&x(), &1)));
+        assert_eq!(bindings.collect::<Vec<_>>(), [(x(), 2), (x(), 1)]);
     }
 }
@@ -1,10 +1,19 @@
 use std::{error::Error, fmt, result::Result};
 
+use crate::fuel::Exhausted;
+
 pub enum EvalError {
     UnboundVariable(String),
     TypeMismatch(String),
     FileNotFound(String),
     ParseError(String),
+    TerminationError(String),
+    CoverageError(String),
+    ImportError(String),
+    RedefinitionError(String),
+    SessionError(String),
+    /// Evaluation or checking was stopped by the limits in [`crate::fuel`].
+    ResourceExhausted(Exhausted),
 }
 
 impl fmt::Debug for EvalError {
@@ -14,6 +23,12 @@ impl fmt::Debug for EvalError {
             EvalError::TypeMismatch(x) => write!(f, "Type mismatch: {}", x),
             EvalError::FileNotFound(x) => write!(f, "File not found: {}", x),
             EvalError::ParseError(x) => write!(f, "Parse error: {}", x),
+            EvalError::TerminationError(x) => write!(f, "Termination check failed: {}", x),
+            EvalError::CoverageError(x) => write!(f, "Coverage check failed: {}", x),
+            EvalError::ImportError(x) => write!(f, "Import failed: {}", x),
+            EvalError::RedefinitionError(x) => write!(f, "Redefinition failed: {}", x),
+            EvalError::SessionError(x) => write!(f, "Session error: {}", x),
+            EvalError::ResourceExhausted(x) => write!(f, "Resource exhausted: {}", x),
         }
     }
 }
@@ -1,77 +1,158 @@
 //! The evaluation rule for λΠ language.
 
-use std::sync::Arc;
-
 use crate::{
+    case::{check_case, eval_case, infer_case},
     clos::Closure,
-    env::{Ctx, EvalCtx, TypeCtx},
+    env::{Bindings, Ctx, EvalCtx, TypeCtx},
     err::{EvalError, EvalResult},
+    fuel::{self, Step},
+    hole,
+    pattern::Clause,
+    record::{check_record, check_record_type, eta_contract, infer_proj, lift_record_type, proj},
+    stack::{self, Node},
     term::{CheckableTerm, Neutral, Term, Type, Value, VariableName},
+    thunk::{self, Strategy, Thunk},
 };
 
-fn lift_neutral(de_brujin_index: usize, n: Neutral) -> Term {
-    match n {
-        Neutral::NApp(clos, arg) => Term::App {
-            clos: Box::new(lift_neutral(de_brujin_index, *clos)),
-            arg: Box::new(lift(de_brujin_index, *arg)),
-        },
+pub(crate) fn lift_neutral(de_brujin_index: usize, n: Neutral, unfold: bool) -> EvalResult<Term> {
+    stack::grow(move || match n {
+        Neutral::NApp(clos, arg) => Ok(Term::App {
+            clos: Node::new(lift_neutral(de_brujin_index, clos.into_inner(), unfold)?),
+            arg: Node::new(read_back(de_brujin_index, arg.into_inner(), unfold)?),
+        }),
         Neutral::NVar(name) => match name {
             // Bounded.
-            VariableName::Quote(idx) => Term::Bounded(de_brujin_index - idx - 1),
-            _ => Term::Var(name),
+            VariableName::Quote(idx) => Ok(Term::Bounded(de_brujin_index - idx - 1)),
+            _ => Ok(Term::Var(name)),
         },
-    }
+        Neutral::NProj(n, field) => Ok(Term::Proj {
+            term: Node::new(lift_neutral(de_brujin_index, n.into_inner(), unfold)?),
+            field,
+        }),
+        Neutral::NHole(span) => Ok(Term::Hole {
+            span,
+            names: vec![],
+        }),
+        Neutral::NCase {
+            scrutinee,
+            arms,
+            ctx,
+        } => Ok(Term::Case {
+            scrutinee: Node::new(read_back(de_brujin_index, scrutinee.into_inner(), unfold)?),
+            arms: arms
+                .into_iter()
+                .map(|arm| {
+                    // Evaluate the body under fresh variables for the pattern.
+                    let arity = arm.patterns[0].arity();
+                    let mut ctx = (*ctx).clone();
+                    for i in 0..arity {
+                        ctx.1 = ctx
+                            .1
+                            .push(Value::VNeutral(Neutral::NVar(VariableName::Quote(
+                                de_brujin_index + i,
+                            ))));
+                    }
+
+                    let body = eval_checked(arm.body, ctx)?;
+                    Ok(Clause {
+                        patterns: arm.patterns,
+                        body: read_back(de_brujin_index + arity, body, unfold)?,
+                    })
+                })
+                .collect::<EvalResult<_>>()?,
+        }),
+    })
+}
+
+/// Lift back a value into a term. Definitions are kept folded, so that, e.g., types are printed
+/// the way they are written. Reading back evaluates under binders, so it can fail or run out of
+/// fuel like evaluation.
+pub fn lift(de_brujin_index: usize, val: Value) -> EvalResult<CheckableTerm> {
+    read_back(de_brujin_index, val, false)
+}
+
+/// Lift back a value into its normal form, where every definition is unfolded.
+pub fn lift_unfolded(de_brujin_index: usize, val: Value) -> EvalResult<CheckableTerm> {
+    read_back(de_brujin_index, val, true)
 }
 
-/// Lift back a value into a term.
-pub(crate) fn lift(de_brujin_index: usize, val: Value) -> CheckableTerm {
-    match val {
+/// Reads a value back, which can fail, e.g., if evaluating the body of a closure runs out of fuel.
+pub(crate) fn read_back(
+    de_brujin_index: usize,
+    val: Value,
+    unfold: bool,
+) -> EvalResult<CheckableTerm> {
+    stack::grow(move || match val {
         Value::VAbs(clos) => {
-            let body = clos
-                .call(Value::VNeutral(Neutral::NVar(VariableName::Quote(
-                    de_brujin_index,
-                ))))
-                .expect("closure call failed");
-            CheckableTerm::Lambda {
-                term: Box::new(lift(de_brujin_index + 1, body)),
-            }
+            let body = clos.call(Value::VNeutral(Neutral::NVar(VariableName::Quote(
+                de_brujin_index,
+            ))))?;
+            Ok(CheckableTerm::Lambda {
+                name: clos.name(),
+                term: Node::new(read_back(de_brujin_index + 1, body, unfold)?),
+            })
         }
-        Value::VNeutral(n) => CheckableTerm::InfereableTerm {
-            term: Box::new(lift_neutral(de_brujin_index, n)),
-        },
-        Value::VUniverse => CheckableTerm::InfereableTerm {
-            term: Box::new(Term::Universe),
-        },
+        Value::VNeutral(n) => Ok(CheckableTerm::InfereableTerm {
+            term: Node::new(lift_neutral(de_brujin_index, n, unfold)?),
+        }),
+        Value::VUniverse => Ok(CheckableTerm::InfereableTerm {
+            term: Node::new(Term::Universe),
+        }),
         Value::VPi { val, body } => {
-            let arg = lift(de_brujin_index, *val);
-            let body = body
-                .call(Value::VNeutral(Neutral::NVar(VariableName::Quote(
-                    de_brujin_index,
-                ))))
-                .expect("closure call failed");
-            CheckableTerm::InfereableTerm {
-                term: Box::new(Term::DependentFunctionSpace {
-                    arg: Box::new(arg),
-                    ret: Box::new(lift(de_brujin_index + 1, body)),
+            let arg = read_back(de_brujin_index, val.into_inner(), unfold)?;
+            let name = body.name();
+            let body = body.call(Value::VNeutral(Neutral::NVar(VariableName::Quote(
+                de_brujin_index,
+            ))))?;
+            Ok(CheckableTerm::InfereableTerm {
+                term: Node::new(Term::DependentFunctionSpace {
+                    name,
+                    arg: Node::new(arg),
+                    ret: Node::new(read_back(de_brujin_index + 1, body, unfold)?),
                 }),
-            }
+            })
         }
-        Value::VZero => CheckableTerm::Zero,
-        Value::VSucc { pred } => CheckableTerm::Succ {
-            term: Box::new(lift(de_brujin_index, *pred)),
+        Value::VZero => Ok(CheckableTerm::Zero),
+        Value::VSucc { pred } => Ok(CheckableTerm::Succ {
+            term: Node::new(read_back(de_brujin_index, pred.into_inner(), unfold)?),
+        }),
+        Value::VNat => Ok(CheckableTerm::InfereableTerm {
+            term: Node::new(Term::Nat),
+        }),
+        Value::VRecordType { fields, ctx } => Ok(CheckableTerm::InfereableTerm {
+            term: Node::new(lift_record_type(de_brujin_index, &fields, &ctx, unfold)?),
+        }),
+        Value::VRecord(fields) => match eta_contract(de_brujin_index, &fields)? {
+            Some(n) => Ok(CheckableTerm::InfereableTerm {
+                term: Node::new(lift_neutral(de_brujin_index, n, unfold)?),
+            }),
+            None => Ok(CheckableTerm::Record {
+                fields: fields
+                    .into_iter()
+                    .map(|(name, val)| Ok((name, read_back(de_brujin_index, val, unfold)?)))
+                    .collect::<EvalResult<_>>()?,
+            }),
         },
-        Value::VNat => CheckableTerm::InfereableTerm {
-            term: Box::new(Term::Nat),
+        Value::VGlued { head, def } => match unfold {
+            true => read_back(de_brujin_index, force(Value::VGlued { head, def })?, unfold),
+            false => Ok(CheckableTerm::InfereableTerm {
+                term: Node::new(lift_neutral(de_brujin_index, head.into_inner(), unfold)?),
+            }),
         },
-    }
+        Value::VThunk(thunk) => read_back(de_brujin_index, thunk.force()?, unfold),
+    })
 }
 
 fn subst(de_brujin_index: usize, t_what: Term, t_for: Term) -> Term {
-    match t_for {
+    stack::grow(move || match t_for {
         Term::AnnotatedTerm { term, ty } => {
             // Subsitute all.
-            let term = Box::new(subst_checked(de_brujin_index, t_what.clone(), *term));
-            let ty = Box::new(subst_checked(de_brujin_index, t_what, *ty));
+            let term = Node::new(subst_checked(
+                de_brujin_index,
+                t_what.clone(),
+                term.into_inner(),
+            ));
+            let ty = Node::new(subst_checked(de_brujin_index, t_what, ty.into_inner()));
             Term::AnnotatedTerm { term, ty }
         }
         Term::Bounded(idx) => match idx == de_brujin_index {
@@ -80,38 +161,92 @@ fn subst(de_brujin_index: usize, t_what: Term, t_for: Term) -> Term {
         },
         Term::Var(name) => Term::Var(name),
         Term::App { clos, arg } => Term::App {
-            clos: Box::new(subst(de_brujin_index, t_what.clone(), *clos)),
-            arg: Box::new(subst_checked(de_brujin_index, t_what, *arg)),
+            clos: Node::new(subst(de_brujin_index, t_what.clone(), clos.into_inner())),
+            arg: Node::new(subst_checked(de_brujin_index, t_what, arg.into_inner())),
         },
         Term::Universe => Term::Universe,
-        Term::DependentFunctionSpace { arg, ret } => {
-            let arg = Box::new(subst_checked(de_brujin_index, t_what.clone(), *arg));
-            let ret = Box::new(subst_checked(de_brujin_index + 1, t_what, *ret));
-            Term::DependentFunctionSpace { arg, ret }
+        Term::DependentFunctionSpace { name, arg, ret } => {
+            let arg = Node::new(subst_checked(
+                de_brujin_index,
+                t_what.clone(),
+                arg.into_inner(),
+            ));
+            let ret = Node::new(subst_checked(de_brujin_index + 1, t_what, ret.into_inner()));
+            Term::DependentFunctionSpace { name, arg, ret }
         }
         Term::Zero => Term::Zero,
         Term::Nat => Term::Nat,
         Term::Succ { pred } => {
-            let pred = Box::new(subst(de_brujin_index, t_what, *pred));
+            let pred = Node::new(subst(de_brujin_index, t_what, pred.into_inner()));
             Term::Succ { pred }
         }
+        Term::Case { scrutinee, arms } => {
+            let scrutinee = Node::new(subst_checked(
+                de_brujin_index,
+                t_what.clone(),
+                scrutinee.into_inner(),
+            ));
+            let arms = arms
+                .into_iter()
+                .map(|arm| {
+                    // The pattern variables are bound in the body.
+                    let arity = arm.patterns[0].arity();
+                    Clause {
+                        patterns: arm.patterns,
+                        body: subst_checked(de_brujin_index + arity, t_what.clone(), arm.body),
+                    }
+                })
+                .collect();
+            Term::Case { scrutinee, arms }
+        }
+        Term::RecordType { fields } => {
+            // Each field is bound in the following ones.
+            let fields = fields
+                .into_iter()
+                .enumerate()
+                .map(|(i, (name, ty))| {
+                    (name, subst_checked(de_brujin_index + i, t_what.clone(), ty))
+                })
+                .collect();
+            Term::RecordType { fields }
+        }
+        Term::Proj { term, field } => Term::Proj {
+            term: Node::new(subst(de_brujin_index, t_what, term.into_inner())),
+            field,
+        },
+        Term::Hole { span, names } => Term::Hole { span, names },
         _ => todo!("not implemented yet for {t_for:?}"),
-    }
+    })
 }
 
-fn subst_checked(de_brujin_index: usize, t_what: Term, t_for: CheckableTerm) -> CheckableTerm {
-    match t_for {
+pub(crate) fn subst_checked(
+    de_brujin_index: usize,
+    t_what: Term,
+    t_for: CheckableTerm,
+) -> CheckableTerm {
+    stack::grow(move || match t_for {
         CheckableTerm::InfereableTerm { term } => CheckableTerm::InfereableTerm {
-            term: Box::new(subst(de_brujin_index, t_what, *term)),
+            term: Node::new(subst(de_brujin_index, t_what, term.into_inner())),
         },
-        CheckableTerm::Lambda { term } => CheckableTerm::Lambda {
-            term: Box::new(subst_checked(de_brujin_index + 1, t_what, *term)),
+        CheckableTerm::Lambda { name, term } => CheckableTerm::Lambda {
+            name,
+            term: Node::new(subst_checked(
+                de_brujin_index + 1,
+                t_what,
+                term.into_inner(),
+            )),
         },
         CheckableTerm::Succ { term } => CheckableTerm::Succ {
-            term: Box::new(subst_checked(de_brujin_index, t_what, *term)),
+            term: Node::new(subst_checked(de_brujin_index, t_what, term.into_inner())),
         },
         CheckableTerm::Zero => CheckableTerm::Zero,
-    }
+        CheckableTerm::Record { fields } => CheckableTerm::Record {
+            fields: fields
+                .into_iter()
+                .map(|(name, e)| (name, subst_checked(de_brujin_index, t_what.clone(), e)))
+                .collect(),
+        },
+    })
 }
 
 /// This is a special function that evaluates the lambda application at the value level.
@@ -119,81 +254,186 @@ fn val_app(clos: &Value, arg: &Value) -> EvalResult<Value> {
     match clos {
         Value::VAbs(clos) => clos.call(arg.clone()),
         Value::VNeutral(n) => Ok(Value::VNeutral(Neutral::NApp(
-            Box::new(n.clone()),
-            Box::new(arg.clone()),
+            Node::new(n.clone()),
+            Node::new(arg.clone()),
         ))),
+        Value::VThunk(thunk) => val_app(&thunk.force()?, arg),
+        // The definition is applied only once it is unfolded.
+        Value::VGlued { head, def } => Ok(Value::VGlued {
+            head: Node::new(Neutral::NApp(head.clone(), Node::new(arg.clone()))),
+            def: def.clone(),
+        }),
         _ => Err(EvalError::TypeMismatch(format!(
-            "Cannot apply a non-function value: {:?}",
+            "Cannot apply a non-function value: {}",
             clos
         ))),
     }
 }
 
+/// Unfolds the definitions at the head of a value and forces its thunks until it is neither glued
+/// nor delayed, so that its shape can be inspected.
+pub fn force(val: Value) -> EvalResult<Value> {
+    let mut val = val;
+    loop {
+        val = match val {
+            Value::VGlued { head, def } => apply_spine(head.into_inner(), def.into_inner())?,
+            Value::VThunk(thunk) => thunk.force()?,
+            val => return Ok(val),
+        };
+    }
+}
+
+/// Applies the unfolded definition of a glued head to the arguments in its spine.
+fn apply_spine(head: Neutral, def: Value) -> EvalResult<Value> {
+    stack::grow(move || match head {
+        Neutral::NApp(clos, arg) => val_app(&apply_spine(clos.into_inner(), def)?, &arg),
+        _ => Ok(def),
+    })
+}
+
+/// Rebuilds a value so that it reads back to its normal form even with the definitions folded,
+/// e.g., to show the result of `eval`.
+pub fn normalize(val: Value) -> EvalResult<Value> {
+    // A normal form has nothing left to compute, so evaluating it without the definitions only
+    // turns the globals in it into variables.
+    eval_checked(read_back(0, val, true)?, EvalCtx::new())
+}
+
+/// Whether two values are convertible. They are compared from the outside in, and definitions are
+/// unfolded and thunks forced only as far as it takes to tell, e.g., `Vec ℕ n` is convertible
+/// with itself without unfolding `Vec` or forcing `n`.
+pub(crate) fn convertible(de_brujin_index: usize, lhs: &Value, rhs: &Value) -> EvalResult<bool> {
+    conv(de_brujin_index, lhs, rhs)
+}
+
+fn conv(de_brujin_index: usize, lhs: &Value, rhs: &Value) -> EvalResult<bool> {
+    stack::grow(move || {
+        // Equal values, e.g., a thunk and its clone, are convertible without looking into them.
+        if lhs == rhs {
+            return Ok(true);
+        }
+
+        let fresh = || Value::VNeutral(Neutral::NVar(VariableName::Quote(de_brujin_index)));
+        match (lhs, rhs) {
+            (Value::VThunk(thunk), _) => conv(de_brujin_index, &thunk.force()?, rhs),
+            (_, Value::VThunk(thunk)) => conv(de_brujin_index, lhs, &thunk.force()?),
+            // The same definition applied to convertible arguments needs no unfolding.
+            (Value::VGlued { head: m, .. }, Value::VGlued { head: n, .. })
+                if conv_neutral(de_brujin_index, m, n)? =>
+            {
+                Ok(true)
+            }
+            (Value::VGlued { .. }, _) => conv(de_brujin_index, &force(lhs.clone())?, rhs),
+            (_, Value::VGlued { .. }) => conv(de_brujin_index, lhs, &force(rhs.clone())?),
+            (Value::VNeutral(m), Value::VNeutral(n)) => conv_neutral(de_brujin_index, m, n),
+            (Value::VAbs(f), Value::VAbs(g)) => {
+                conv(de_brujin_index + 1, &f.call(fresh())?, &g.call(fresh())?)
+            }
+            (Value::VPi { val: a, body: f }, Value::VPi { val: b, body: g }) => {
+                Ok(conv(de_brujin_index, a, b)?
+                    && conv(de_brujin_index + 1, &f.call(fresh())?, &g.call(fresh())?)?)
+            }
+            (Value::VSucc { pred: m }, Value::VSucc { pred: n }) => conv(de_brujin_index, m, n),
+            // Records are compared up to eta, which reading them back takes care of.
+            _ => Ok(read_back(de_brujin_index, lhs.clone(), true)?
+                == read_back(de_brujin_index, rhs.clone(), true)?),
+        }
+    })
+}
+
+fn conv_neutral(de_brujin_index: usize, lhs: &Neutral, rhs: &Neutral) -> EvalResult<bool> {
+    stack::grow(move || match (lhs, rhs) {
+        (Neutral::NVar(x), Neutral::NVar(y)) => Ok(x == y),
+        (Neutral::NHole(a), Neutral::NHole(b)) => Ok(a == b),
+        (Neutral::NApp(f, a), Neutral::NApp(g, b)) => {
+            Ok(conv_neutral(de_brujin_index, f, g)? && conv(de_brujin_index, a, b)?)
+        }
+        (Neutral::NProj(m, x), Neutral::NProj(n, y)) => {
+            Ok(x == y && conv_neutral(de_brujin_index, m, n)?)
+        }
+        _ => Ok(lift_neutral(de_brujin_index, lhs.clone(), true)?
+            == lift_neutral(de_brujin_index, rhs.clone(), true)?),
+    })
+}
+
+/// Delays an argument under the lazy strategy, unless evaluating it takes no work anyway.
+fn delay(arg: Node<CheckableTerm>, ctx: EvalCtx) -> EvalResult<Value> {
+    let cheap = match arg.as_ref() {
+        CheckableTerm::Lambda { .. } | CheckableTerm::Zero => true,
+        CheckableTerm::InfereableTerm { term } => matches!(
+            term.as_ref(),
+            Term::Var(_) | Term::Bounded(_) | Term::Universe | Term::Nat
+        ),
+        _ => false,
+    };
+
+    match cheap || thunk::strategy() == Strategy::Strict {
+        true => eval_checked(arg.into_inner(), ctx),
+        false => Ok(Value::VThunk(Box::new(Thunk::new(arg, ctx)))),
+    }
+}
+
 pub fn eval_checked(term: CheckableTerm, ctx: EvalCtx) -> EvalResult<Value> {
-    match term {
+    stack::grow(move || match term {
         // May cause some non-terminating loops.
-        CheckableTerm::InfereableTerm { term } => eval(*term, ctx),
-        CheckableTerm::Lambda { term } => {
-            // We move the contexts into the closure.
-            let f = move |x, mut ctx: EvalCtx| {
-                ctx.1 = ctx.1.push(x);
-                eval_checked(*term.clone(), ctx)
-            };
-
-            Ok(Value::VAbs(Box::new(Closure::new(Arc::new(f), ctx))))
+        CheckableTerm::InfereableTerm { term } => eval(term.into_inner(), ctx),
+        CheckableTerm::Lambda { name, term } => {
+            // The body is evaluated once the closure is applied.
+            Ok(Value::VAbs(Box::new(Closure::new(name, term, ctx))))
         }
         CheckableTerm::Succ { term } => {
-            let pred = eval_checked(*term, ctx)?;
+            let pred = eval_checked(term.into_inner(), ctx)?;
             Ok(Value::VSucc {
-                pred: Box::new(pred),
+                pred: Node::new(pred),
             })
         }
         CheckableTerm::Zero => Ok(Value::VZero),
-    }
+        CheckableTerm::Record { fields } => Ok(Value::VRecord(
+            fields
+                .into_iter()
+                .map(|(name, e)| Ok((name, eval_checked(e, ctx.clone())?)))
+                .collect::<EvalResult<Vec<_>>>()?,
+        )),
+    })
 }
 
 /// Evaluates a term: term -> context -> Result(Value)
 ///
-/// All the values are moved. The tricky part of the Rust ownership model is that
-/// we cannot make closures cloneable while keeping the closure type-erased. If we
-/// just borrow `term` and `ctx`, we will have to make sure that the closure does not
-/// outlive the `term` and `ctx` (which in fact will).
-///
-/// We simply clone everything to ensure that the closure is self-contained.
+/// All the values are moved. A binder evaluates to a [`Closure`] that holds its body and `ctx`
+/// until it is applied. Subterms are shared [`Node`]s and contexts are persistent, so this only
+/// copies the roots of the terms rather than the terms themselves.
 pub fn eval(term: Term, ctx: EvalCtx) -> EvalResult<Value> {
-    match term {
+    fuel::tick(|| Step::Eval(term.clone(), ctx.clone()))?;
+    stack::grow(move || match term {
         // Type erasure: we do not need to keep the annotation.
-        Term::AnnotatedTerm { term, .. } => eval_checked(*term, ctx),
-        Term::DependentFunctionSpace { arg, ret } => {
-            let val = eval_checked(*arg, ctx.clone())?;
-            // Let us move `ret` into the closure's evaluation context.
-            let body = move |x, mut ctx: EvalCtx| {
-                ctx.1 = ctx.1.push(x);
-                // A'
-                eval_checked(*ret.clone(), ctx)
-            };
-
+        Term::AnnotatedTerm { term, .. } => eval_checked(term.into_inner(), ctx),
+        Term::DependentFunctionSpace { name, arg, ret } => {
+            let val = eval_checked(arg.into_inner(), ctx.clone())?;
             Ok(Value::VPi {
-                val: Box::new(val),
-                body: Box::new(Closure::new(Arc::new(body), ctx)),
+                val: Node::new(val),
+                body: Box::new(Closure::new(name, ret, ctx)),
             })
         }
-        Term::Var(x) => match ctx.0.into_iter().find(|(n, _)| n == &x) {
-            Some((_, val)) => Ok(val),
+        // Definitions are glued to their names rather than substituted.
+        Term::Var(x) => match ctx.0.get(&x) {
+            Some(val) => Ok(Value::VGlued {
+                head: Node::new(Neutral::NVar(x)),
+                def: Node::new(val.clone()),
+            }),
             None => Ok(Value::VNeutral(Neutral::NVar(x))),
         },
 
         // Try to look up the context and get the result.
-        Term::Bounded(idx) => match ctx.1.into_iter().nth(idx) {
-            Some(val) => Ok(val),
+        Term::Bounded(idx) => match ctx.1.get(idx) {
+            Some(val) => Ok(val.clone()),
             None => Err(EvalError::UnboundVariable(format!(
                 "Variable at index {} is not found in the context",
                 idx
             ))),
         },
         Term::App { clos, arg } => {
-            let clos = eval(*clos, ctx.clone())?;
-            let arg = eval_checked(*arg, ctx.clone())?;
+            let clos = eval(clos.into_inner(), ctx.clone())?;
+            let arg = delay(arg, ctx)?;
 
             val_app(&clos, &arg)
         }
@@ -202,65 +442,87 @@ pub fn eval(term: Term, ctx: EvalCtx) -> EvalResult<Value> {
         Term::Zero => Ok(Value::VZero),
         Term::Nat => Ok(Value::VNat),
         Term::Succ { pred } => {
-            let pred = eval(*pred, ctx)?;
+            let pred = eval(pred.into_inner(), ctx)?;
             Ok(Value::VSucc {
-                pred: Box::new(pred),
+                pred: Node::new(pred),
             })
         }
+        Term::Case { scrutinee, arms } => eval_case(scrutinee.into_inner(), arms, ctx),
+        Term::RecordType { fields } => Ok(Value::VRecordType {
+            fields,
+            ctx: Box::new(ctx),
+        }),
+        Term::Proj { term, field } => proj(eval(term.into_inner(), ctx)?, &field),
+        // A hole is stuck until it is filled.
+        Term::Hole { span, .. } => Ok(Value::VNeutral(Neutral::NHole(span))),
         _ => unimplemented!("not implemented yet for {term:?}"),
-    }
+    })
 }
 
 /// Do a type check.
 pub fn type_check(de_brujin_index: usize, term: Term, mut ctx: TypeCtx) -> EvalResult<Type> {
-
-    match term {
+    fuel::tick(|| Step::Check(de_brujin_index, term.clone(), Box::new(ctx.clone())))?;
+    stack::grow(move || match term {
         Term::AnnotatedTerm { term, ty } => {
             // Ensure that the type is a universe.
-            sanity_check(de_brujin_index, *ty.clone(), ctx.clone(), Value::VUniverse)?;
+            sanity_check(
+                de_brujin_index,
+                (*ty).clone(),
+                ctx.clone(),
+                Value::VUniverse,
+            )?;
             // Evaluate that type.
-            let ty = eval_checked(*ty, EvalCtx(ctx.0.clone(), Ctx::Nil))?;
+            let ty = eval_checked(ty.into_inner(), EvalCtx(ctx.0.clone(), Ctx::new()))?;
             // Then do the type checking.
-            sanity_check(de_brujin_index, *term, ctx, ty.clone()).map(|_| ty)
+            sanity_check(de_brujin_index, term.into_inner(), ctx, ty.clone()).map(|_| ty)
         }
         Term::Universe => Ok(Value::VUniverse),
-        Term::DependentFunctionSpace { arg, ret } => {
+        Term::DependentFunctionSpace { arg, ret, .. } => {
             // This is a sanity check to ensure that the argument is really a type.
-            sanity_check(de_brujin_index, *arg.clone(), ctx.clone(), Value::VUniverse)?;
+            sanity_check(
+                de_brujin_index,
+                (*arg).clone(),
+                ctx.clone(),
+                Value::VUniverse,
+            )?;
             // We reduce the argument to a value.
-            let arg_ty = eval_checked(*arg, EvalCtx(ctx.0.clone(), Ctx::Nil))?;
+            let arg_ty = eval_checked(arg.into_inner(), EvalCtx(ctx.0.clone(), Ctx::new()))?;
 
             // We push the variable into the context.
             ctx.1 = ctx.1.push((VariableName::Local(de_brujin_index), arg_ty));
-            let substituted =
-                subst_checked(0, Term::Var(VariableName::Local(de_brujin_index)), *ret);
+            let substituted = subst_checked(
+                0,
+                Term::Var(VariableName::Local(de_brujin_index)),
+                ret.into_inner(),
+            );
             sanity_check(de_brujin_index + 1, substituted, ctx, Value::VUniverse)?;
             // Size ↑ ?
             Ok(Value::VUniverse)
         }
-        Term::Var(name) => match ctx.1.into_iter().find(|(n, _)| n == &name) {
-            Some((_, val)) => {
-                Ok(val)
-            }
+        Term::Var(name) => match ctx.1.get(&name) {
+            Some(val) => Ok(val.clone()),
             None => Err(EvalError::UnboundVariable(format!(
                 "Variable {:?} is not found in the context",
                 name
             ))),
         },
         Term::App { clos, arg } => {
+            let ty = type_check(de_brujin_index, clos.into_inner(), ctx.clone())?;
 
-            let ty = type_check(de_brujin_index, *clos.clone(), ctx.clone())?;
-
-
-            if let Value::VPi { val, body } = ty {
+            if let Value::VPi { val, body } = force(ty.clone())? {
                 // Let us check if the argument is of the right type.
-                sanity_check(de_brujin_index, *arg.clone(), ctx.clone(), *val)?;
+                sanity_check(
+                    de_brujin_index,
+                    (*arg).clone(),
+                    ctx.clone(),
+                    val.into_inner(),
+                )?;
 
-                let arg = eval_checked(*arg, ctx.clone().into())?;
+                let arg = eval_checked(arg.into_inner(), ctx.clone().into())?;
                 body.call(arg)
             } else {
                 Err(EvalError::TypeMismatch(format!(
-                    "App argument error: Expected a dependent function, found {:?}",
+                    "App argument error: Expected a dependent function, found {}",
                     ty
                 )))
             }
@@ -268,31 +530,40 @@ pub fn type_check(de_brujin_index: usize, term: Term, mut ctx: TypeCtx) -> EvalR
         Term::Nat => Ok(Value::VUniverse),
         Term::Zero => Ok(Value::VNat),
         Term::Succ { pred } => {
-            let pred_ty = type_check(de_brujin_index, *pred.clone(), ctx)?;
-            match pred_ty {
+            let pred_ty = type_check(de_brujin_index, pred.into_inner(), ctx)?;
+            match force(pred_ty.clone())? {
                 Value::VNat => Ok(Value::VNat),
                 _ => Err(EvalError::TypeMismatch(format!(
-                    "Expected a natural number, found {:?}",
+                    "Expected a natural number, found {}",
                     pred_ty
                 ))),
             }
         }
+        Term::Case { scrutinee, arms } => infer_case(de_brujin_index, &scrutinee, &arms, ctx),
+        Term::RecordType { fields } => check_record_type(de_brujin_index, &fields, ctx),
+        Term::Proj { term, field } => infer_proj(de_brujin_index, &term, &field, ctx),
+        Term::Hole { .. } => Err(EvalError::TypeMismatch(
+            "Cannot infer the type of a hole; annotate it with `::`.".to_string(),
+        )),
         _ => todo!("not implemented yet for {term:?}"),
-    }
+    })
 }
 
 /// Sometimes types are chained, meaning that there exists a type that is defined in terms of another type, and
 /// it typically occurs when we are defining a dependent type like `∀ a : A, b : a . whatever`.
 ///
 /// This function looks up the term in the context in a chained manner.
-fn lookup(term: Value, ctx: &Ctx<(VariableName, Type)>, mut attempt: usize) -> EvalResult<Value> {
-    let mut res = term;
+fn lookup(term: Value, ctx: &Bindings<Type>, mut attempt: usize) -> EvalResult<Value> {
+    let mut res = match term {
+        Value::VThunk(thunk) => thunk.force()?,
+        term => term,
+    };
 
     while attempt > 0 {
         match &res {
             Value::VNeutral(Neutral::NVar(name)) => {
-                if let Some((_, ty)) = ctx.clone().into_iter().find(|(n, _)| n == name) {
-                    res = ty;
+                if let Some(ty) = ctx.get(name) {
+                    res = ty.clone();
                     attempt -= 1;
                 } else {
                     return Ok(res);
@@ -312,39 +583,53 @@ pub fn sanity_check(
     mut ctx: TypeCtx,
     ty: Type,
 ) -> EvalResult<()> {
-
-    match term {
+    stack::grow(move || match term {
         CheckableTerm::Zero => Ok(()),
+        CheckableTerm::Record { fields } => check_record(de_brujin_index, &fields, ctx, ty),
+        CheckableTerm::InfereableTerm { term } if matches!(term.as_ref(), Term::Case { .. }) => {
+            match term.into_inner() {
+                Term::Case { scrutinee, arms } => {
+                    check_case(de_brujin_index, &scrutinee, &arms, ctx, ty)
+                }
+                _ => unreachable!(),
+            }
+        }
+        // A hole can have any type; we only remember what it should be.
+        CheckableTerm::InfereableTerm { term } if matches!(term.as_ref(), Term::Hole { .. }) => {
+            if let Term::Hole { span, names } = term.into_inner() {
+                hole::record(span, &names, &ctx, ty);
+            }
+            Ok(())
+        }
         CheckableTerm::InfereableTerm { term } => {
             let val = lookup(
-                type_check(de_brujin_index, *term, ctx.clone())?,
+                type_check(de_brujin_index, term.into_inner(), ctx.clone())?,
                 &ctx.1,
                 128,
             )?;
             let ty = lookup(ty, &ctx.1, 128)?;
 
-            let lhs = lift(0, val);
-            let rhs = lift(0, ty.clone());
-            if lhs != rhs {
+            if !convertible(0, &val, &ty)? {
                 Err(EvalError::TypeMismatch(format!(
-                    "Type mismatch: expected {:?}, found {:?}",
-                    rhs, lhs
+                    "Type mismatch: expected {ty}, found {val}"
                 )))
             } else {
                 Ok(())
             }
         }
-        CheckableTerm::Lambda { term } => {
-            match ty {
+        CheckableTerm::Lambda { term, .. } => {
+            match force(ty.clone())? {
                 Value::VPi { val, body } => {
                     let substituted = subst_checked(
                         0,
                         Term::Var(VariableName::Local(de_brujin_index)),
-                        *term.clone(),
+                        term.into_inner(),
                     );
 
                     // We push the variable into the context.
-                    ctx.1 = ctx.1.push((VariableName::Local(de_brujin_index), *val));
+                    ctx.1 = ctx
+                        .1
+                        .push((VariableName::Local(de_brujin_index), val.into_inner()));
                     let ty = body.call(Value::VNeutral(Neutral::NVar(VariableName::Local(
                         de_brujin_index,
                     ))))?;
@@ -352,23 +637,23 @@ pub fn sanity_check(
                     sanity_check(de_brujin_index + 1, substituted, ctx, ty)
                 }
                 _ => Err(EvalError::TypeMismatch(format!(
-                    "Expected a dependent function, found {:?}",
+                    "Expected a dependent function, found {}",
                     ty
                 ))),
             }
         }
         CheckableTerm::Succ { term } => {
-            let val = eval_checked(*term, Default::default())?;
+            let val = eval_checked(term.into_inner(), Default::default())?;
             match val {
                 Value::VZero => Ok(()),
                 Value::VSucc { pred } => {
-                    let predl = lift(de_brujin_index, *pred);
-                    let predr = lift(de_brujin_index, Value::VNat);
+                    let predl = lift(de_brujin_index, pred.into_inner())?;
+                    let predr = lift(de_brujin_index, Value::VNat)?;
                     if predl == predr {
                         Ok(())
                     } else {
                         Err(EvalError::TypeMismatch(format!(
-                            "Type mismatch: expected {:?}, found {:?}",
+                            "Type mismatch: expected {}, found {}",
                             predr, predl
                         )))
                     }
@@ -378,5 +663,5 @@ pub fn sanity_check(
                 )),
             }
         }
-    }
+    })
 }
@@ -0,0 +1,348 @@
+//! Operators and mixfix names.
+//!
+//! A name with a `_` for each of its arguments, e.g., `_+_`, `_!` or `if_then_else_`, can be used
+//! as an operator: `a + b` means `_+_ a b` and `if b then x else y` means `if_then_else_ b x y`.
+//! The grammar parses operators and their arguments as one application, so `a + b * c` is
+//! `a`, `+`, `b`, `*` and `c` applied to each other; [`reassociate`] turns such an application
+//! into the operators that it stands for by precedence climbing.
+//!
+//! `infixl 6 _+_;` declares that `_+_` is at level 6 and associates to the left. Higher levels bind
+//! tighter, and operators without a declaration are at level 20 and do not associate, so
+//! `a == b == c` needs parentheses. Applications bind tighter than every operator, i.e.,
+//! `f x + g y` is `(f x) + (g y)`.
+
+use crate::{
+    ast::{Assoc, AstNode, Fixity, Span},
+    env::TypeCtx,
+    err::{EvalError, EvalResult},
+    lexer::is_op,
+    module::{lookup_global, resolve},
+    stack::{self, Node},
+};
+
+/// The fixity of an operator that is not declared.
+pub const DEFAULT: Fixity = Fixity {
+    assoc: Assoc::Non,
+    level: 20,
+};
+
+/// A part of a mixfix name: an argument `_`, or a word or an operator between them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Hole,
+    Name(String),
+}
+
+fn parts(name: &str) -> Vec<Part> {
+    let mut res = vec![];
+    for (i, part) in name.split('_').enumerate() {
+        if i > 0 {
+            res.push(Part::Hole);
+        }
+        if !part.is_empty() {
+            res.push(Part::Name(part.to_string()));
+        }
+    }
+
+    res
+}
+
+/// Whether `name`, without its module, is a mixfix name like `_+_`, `_div_` or `if_then_else_`.
+/// Every argument `_` must be between the parts, and each part must be a word or an operator. So
+/// that snake case names are not operators, a name made only of words must start or end with an
+/// argument and, unless it is infix like `_div_`, have more than one word; e.g., `is_zero` and
+/// `_x` are not mixfix names.
+pub fn is_mixfix(name: &str) -> bool {
+    let parts = parts(name);
+    let names = parts
+        .iter()
+        .filter_map(|part| match part {
+            Part::Name(x) => Some(x.as_str()),
+            Part::Hole => None,
+        })
+        .collect::<Vec<_>>();
+    let valid = parts.windows(2).all(|w| w != [Part::Hole, Part::Hole])
+        && parts.contains(&Part::Hole)
+        && names.iter().all(|x| {
+            x.chars().all(is_op)
+                || x.chars().all(|c| c.is_alphanumeric() || c == '\'') && !x.starts_with('\'')
+        });
+    let (first, last) = (
+        parts.first() == Some(&Part::Hole),
+        parts.last() == Some(&Part::Hole),
+    );
+
+    valid
+        && !names.is_empty()
+        && (names.iter().any(|x| x.chars().all(is_op))
+            || first && last
+            || names.len() > 1 && (first || last))
+}
+
+/// An operator that is in scope.
+struct Operator {
+    /// The global that it stands for.
+    name: String,
+    parts: Vec<Part>,
+    fixity: Fixity,
+}
+
+/// The mixfix globals that can be referred to by their names from the current module, and
+/// `extra`, which is being defined.
+fn operators(ctx: &TypeCtx, extra: Option<&str>) -> Vec<Operator> {
+    ctx.globals()
+        .into_iter()
+        .map(|(name, _)| name)
+        .chain(extra.map(str::to_string))
+        .filter_map(|name| {
+            let short = name.rsplit('.').next().unwrap();
+            let visible = matches!(lookup_global(short, ctx, extra), Ok(Some(x)) if x == name);
+            (visible && is_mixfix(short)).then(|| Operator {
+                parts: parts(short),
+                fixity: ctx.fixity(&name),
+                name: name.clone(),
+            })
+        })
+        .collect()
+}
+
+/// An argument of an application, or a part of an operator.
+enum Item<'a> {
+    Operand(&'a AstNode),
+    Part(&'a str, Span),
+}
+
+/// The function and the arguments of an application.
+fn spine(ast: &AstNode) -> Vec<&AstNode> {
+    stack::grow(move || match ast {
+        AstNode::App { clos, arg } => {
+            let mut res = spine(clos);
+            res.push(arg);
+            res
+        }
+        _ => vec![ast],
+    })
+}
+
+struct Parser<'a> {
+    items: Vec<Item<'a>>,
+    pos: usize,
+    operators: &'a [Operator],
+    locals: &'a [String],
+    ctx: &'a TypeCtx,
+    extra: Option<&'a str>,
+}
+
+impl<'a> Parser<'a> {
+    fn part(&self) -> Option<(&'a str, Span)> {
+        match self.items.get(self.pos) {
+            Some(Item::Part(x, span)) => Some((x, *span)),
+            _ => None,
+        }
+    }
+
+    /// The operators that start with an argument and then `x`, if `leading`, or with `x`
+    /// otherwise. Longer ones are tried first.
+    fn candidates(&self, x: &str, leading: bool) -> Vec<&'a Operator> {
+        let i = leading as usize;
+        let mut res = self
+            .operators
+            .iter()
+            .filter(|op| {
+                (op.parts.first() == Some(&Part::Hole)) == leading
+                    && op.parts.get(i) == Some(&Part::Name(x.to_string()))
+            })
+            .collect::<Vec<_>>();
+        res.sort_by_key(|op| std::cmp::Reverse(op.parts.len()));
+        res
+    }
+
+    /// Parses an expression whose operators are at `min` or higher.
+    fn expr(&mut self, min: usize) -> EvalResult<AstNode> {
+        let mut lhs = self.primary()?;
+        let mut prev: Option<&'a Operator> = None;
+
+        while let Some((x, span)) = self.part() {
+            let candidates = self.candidates(x, true);
+            let Some(fixity) = candidates.first().map(|op| op.fixity) else {
+                break;
+            };
+            if fixity.level < min {
+                break;
+            }
+            if let Some(prev) = prev.filter(|prev| prev.fixity.level == fixity.level) {
+                if !(prev.fixity.assoc == Assoc::Left && fixity.assoc == Assoc::Left) {
+                    return Err(EvalError::ParseError(format!(
+                        "Cannot mix {} and {} at level {} without parentheses.",
+                        prev.name, candidates[0].name, fixity.level
+                    )));
+                }
+            }
+
+            let (op, args) = self.operator(&candidates, span, 1)?;
+            lhs = std::iter::once(lhs).chain(args).fold(op_var(op, span), app);
+            prev = Some(op);
+        }
+
+        Ok(lhs)
+    }
+
+    /// An application, or an operator that starts with a part.
+    fn primary(&mut self) -> EvalResult<AstNode> {
+        if let Some((x, span)) = self.part() {
+            let candidates = self.candidates(x, false);
+            if candidates.is_empty() {
+                return Err(EvalError::ParseError(format!("Unexpected {x}.")));
+            }
+
+            let (op, args) = self.operator(&candidates, span, 0)?;
+            return Ok(args.into_iter().fold(op_var(op, span), app));
+        }
+
+        let mut res: Option<AstNode> = None;
+        while let Some(Item::Operand(ast)) = self.items.get(self.pos) {
+            let ast = resolve(ast, self.locals, self.ctx, self.extra)?;
+            res = Some(match res {
+                Some(f) => app(f, ast),
+                None => ast,
+            });
+            self.pos += 1;
+        }
+
+        res.ok_or_else(|| EvalError::ParseError("An operator is missing an argument.".to_string()))
+    }
+
+    /// Parses the rest of the first of `candidates` that fits, whose part `from` is the current
+    /// one. Returns it with its arguments after the part.
+    fn operator(
+        &mut self,
+        candidates: &[&'a Operator],
+        span: Span,
+        from: usize,
+    ) -> EvalResult<(&'a Operator, Vec<AstNode>)> {
+        let start = self.pos;
+        let mut error = None;
+
+        for op in candidates {
+            self.pos = start + 1;
+            match self.rest(op, from + 1) {
+                Ok(args) => return Ok((op, args)),
+                Err(e) => _ = error.get_or_insert(e),
+            }
+        }
+
+        self.pos = start;
+        Err(error.unwrap_or_else(|| {
+            EvalError::ParseError(format!("Cannot parse the operator at {}.", span.start))
+        }))
+    }
+
+    /// Parses the parts of `op` from `from` on.
+    fn rest(&mut self, op: &Operator, from: usize) -> EvalResult<Vec<AstNode>> {
+        let mut args = vec![];
+        let mut parts = op.parts[from..].iter().peekable();
+
+        while let Some(part) = parts.next() {
+            match part {
+                // An argument between two parts can be anything.
+                Part::Hole if parts.peek().is_some() => args.push(self.expr(0)?),
+                Part::Hole => {
+                    let level = match op.fixity.assoc {
+                        Assoc::Right => op.fixity.level,
+                        Assoc::Left | Assoc::Non => op.fixity.level + 1,
+                    };
+                    args.push(self.expr(level)?);
+                }
+                Part::Name(x) => match self.part() {
+                    Some((y, _)) if x.as_str() == y => self.pos += 1,
+                    _ => {
+                        return Err(EvalError::ParseError(format!(
+                            "{} expects {x} here.",
+                            op.name
+                        )))
+                    }
+                },
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+fn app(clos: AstNode, arg: AstNode) -> AstNode {
+    AstNode::App {
+        clos: Node::new(clos),
+        arg: Node::new(arg),
+    }
+}
+
+fn op_var(op: &Operator, span: Span) -> AstNode {
+    AstNode::Var(op.name.clone(), span)
+}
+
+/// Resolves the operators in the application `ast`, whose names are bound as in
+/// [`resolve`]. Returns `None` if there are none.
+pub(crate) fn reassociate(
+    ast: &AstNode,
+    locals: &[String],
+    ctx: &TypeCtx,
+    extra: Option<&str>,
+) -> EvalResult<Option<AstNode>> {
+    let spine = spine(ast);
+    // Only names that are not variables can be parts of operators.
+    let free = |ast: &AstNode| match ast {
+        AstNode::Var(x, _) => {
+            !locals.contains(x) && matches!(lookup_global(x, ctx, extra), Ok(None))
+        }
+        _ => false,
+    };
+    if !spine.iter().any(|ast| free(ast)) {
+        return Ok(None);
+    }
+
+    let operators = operators(ctx, extra);
+    let is_part = |x: &str| {
+        operators
+            .iter()
+            .any(|op| op.parts.contains(&Part::Name(x.to_string())))
+    };
+    let items = spine
+        .into_iter()
+        .map(|ast| match ast {
+            AstNode::Var(x, span) if free(ast) && is_part(x) => Item::Part(x, *span),
+            _ => Item::Operand(ast),
+        })
+        .collect::<Vec<_>>();
+    if items.iter().all(|item| matches!(item, Item::Operand(_))) {
+        return Ok(None);
+    }
+
+    let mut parser = Parser {
+        items,
+        pos: 0,
+        operators: &operators,
+        locals,
+        ctx,
+        extra,
+    };
+    let res = parser.expr(0)?;
+    match parser.part() {
+        Some((x, _)) => Err(EvalError::ParseError(format!("Unexpected {x}."))),
+        None => Ok(Some(res)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_mixfix;
+
+    #[test]
+    fn test_is_mixfix() {
+        for name in ["_+_", "_!", "¬_", "_div_", "if_then_else_", "∣_∣"] {
+            assert!(is_mixfix(name), "{name}");
+        }
+        for name in ["is_zero", "_x", "x_", "___foobar", "_+x_", "+", "a__b_"] {
+            assert!(!is_mixfix(name), "{name}");
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Recursive definitions by pattern matching.
+//!
+//! A definition like
+//!
+//! ```
+//! rec plus :: ℕ -> ℕ -> ℕ
+//!     | O m = m
+//!     | (S n) m = S (plus n m);
+//! ```
+//!
+//! is evaluated as a guarded fixpoint: the function only unfolds once it has received all of its
+//! arguments and they are constructors that select a clause. Otherwise the call is neutral, so
+//! evaluating open terms (e.g., during type checking) always terminates.
+
+use std::sync::Arc;
+
+use crate::{
+    clos::Closure,
+    env::{EvalCtx, TypeCtx},
+    err::{EvalError, EvalResult},
+    eval::{eval_checked, force, sanity_check, subst_checked},
+    pattern::{check_pattern, match_clause, Clause, Match},
+    stack::Node,
+    term::{Neutral, Term, Type, Value, VariableName},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Fixpoint {
+    pub name: String,
+    /// The number of patterns in each clause.
+    pub arity: usize,
+    pub clauses: Vec<Clause>,
+}
+
+/// Evaluates a recursive definition into a curried function.
+pub fn eval_fixpoint(fix: Arc<Fixpoint>, ctx: EvalCtx) -> Value {
+    collect_args(fix, vec![], ctx)
+}
+
+fn collect_args(fix: Arc<Fixpoint>, args: Vec<Value>, ctx: EvalCtx) -> Value {
+    Value::VAbs(Box::new(Closure::Fixpoint { fix, args, ctx }))
+}
+
+/// Applies a recursive definition that has received `args` so far to one more argument.
+pub(crate) fn apply(
+    fix: &Arc<Fixpoint>,
+    args: &[Value],
+    x: Value,
+    ctx: &EvalCtx,
+) -> EvalResult<Value> {
+    let mut args = args.to_vec();
+    args.push(x);
+
+    match args.len() == fix.arity {
+        true => unfold(fix, args, ctx.clone()),
+        false => Ok(collect_args(fix.clone(), args, ctx.clone())),
+    }
+}
+
+/// Picks the first clause that matches the arguments and evaluates its body.
+fn unfold(fix: &Arc<Fixpoint>, args: Vec<Value>, ctx: EvalCtx) -> EvalResult<Value> {
+    for clause in fix.clauses.iter() {
+        match match_clause(&clause.patterns, &args)? {
+            Match::Matched(bindings) => {
+                let mut ctx = ctx.clone();
+                // Recursive calls refer to the function by its global name.
+                let name = VariableName::Global(fix.name.clone());
+                ctx.0 = ctx.0.push((name, eval_fixpoint(fix.clone(), ctx.clone())));
+                for val in bindings {
+                    ctx.1 = ctx.1.push(val);
+                }
+
+                return eval_checked(clause.body.clone(), ctx);
+            }
+            Match::Mismatch => continue,
+            // An earlier clause may still apply once the arguments are known.
+            Match::Stuck => break,
+        }
+    }
+
+    let head = Neutral::NVar(VariableName::Global(fix.name.clone()));
+    Ok(Value::VNeutral(args.into_iter().fold(head, |clos, arg| {
+        Neutral::NApp(Node::new(clos), Node::new(arg))
+    })))
+}
+
+/// Type checks every clause against the declared type `ty`. Inside the bodies the function
+/// itself is assumed to have type `ty`.
+pub fn check_fixpoint(fix: &Fixpoint, ty: &Type, ctx: &TypeCtx) -> EvalResult<()> {
+    let mut ctx = ctx.clone();
+    ctx.1 = ctx
+        .1
+        .push((VariableName::Global(fix.name.clone()), ty.clone()));
+
+    fix.clauses
+        .iter()
+        .try_for_each(|clause| check_clause(fix, clause, ty.clone(), ctx.clone()))
+}
+
+fn check_clause(fix: &Fixpoint, clause: &Clause, ty: Type, mut ctx: TypeCtx) -> EvalResult<()> {
+    let mut ty = ty;
+    let mut de_brujin_index = 0;
+
+    for pattern in clause.patterns.iter() {
+        match force(ty.clone())? {
+            Value::VPi { val, body } => {
+                let val = check_pattern(pattern, val.into_inner(), &mut ctx, &mut de_brujin_index)?;
+                // The expected type of the body is refined by the pattern.
+                ty = body.call(val)?;
+            }
+            _ => {
+                return Err(EvalError::TypeMismatch(format!(
+                    "{} matches on {} arguments but its type {} takes fewer",
+                    fix.name, fix.arity, ty
+                )))
+            }
+        }
+    }
+
+    // Replace the pattern variables in the body by their locals.
+    let body = (0..de_brujin_index).fold(clause.body.clone(), |body, i| {
+        subst_checked(
+            de_brujin_index - i - 1,
+            Term::Var(VariableName::Local(i)),
+            body,
+        )
+    });
+
+    sanity_check(de_brujin_index, body, ctx, ty)
+}
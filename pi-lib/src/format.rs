@@ -0,0 +1,639 @@
+//! A formatter that prints sources in one canonical style.
+//!
+//! Statements are printed from their syntax trees, so `λ`, `∀`, `ℕ`, `U` and `S` are used instead
+//! of their other spellings, `->`, `::` and `:=` are surrounded by single spaces and only the
+//! parentheses that the grammar needs are kept. A definition that does not fit on a line is broken
+//! before its type, and a `∀` or a function type that still does not fit after each binder or
+//! arrow. Comments between statements are kept where they are. Since syntax trees have no place for
+//! comments, a comment inside a statement is put back after the token it follows once the
+//! statement is printed, and a line comment starts a new line indented once more.
+
+use crate::{
+    ast::{Assoc, AstNode, Clause, Fixity, Pattern, Span, Statement, Type},
+    err::{EvalError, EvalResult},
+    lexer::{after_docs, comments, Lexer, Tok},
+    parse::ProgramParser,
+    stack::{self, Node},
+};
+
+/// The width that lines are broken at.
+const WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+/// The precedence levels of the grammar (see `Expr` in `lambda-pi.lalrpop`), from the tightest
+/// to the loosest.
+const ATOM: u8 = 0;
+const SUCC: u8 = 1;
+const APP: u8 = 2;
+const LAMBDA: u8 = 3;
+const ARROW: u8 = 4;
+const FORALL: u8 = 5;
+const ANNOTATION: u8 = 6;
+
+/// Formats `source`. Fails if it cannot be parsed.
+pub fn format(source: &str) -> EvalResult<String> {
+    let parse = |source| {
+        ProgramParser::new()
+            .parse(source)
+            .map_err(|e| EvalError::ParseError(e.to_string()))
+    };
+
+    let stmts = parse(source)?;
+    let res = Formatter { source }.items(&stmts, 0, source.len(), 0);
+
+    // Never hand back something that means a different program.
+    let same = parse(&res)?
+        .iter()
+        .map(|(_, stmt)| strip_statement(stmt))
+        .eq(stmts.iter().map(|(_, stmt)| strip_statement(stmt)));
+    match same {
+        true => Ok(res),
+        false => Err(EvalError::ParseError(
+            "The formatted source is a different program; this is a bug in the formatter."
+                .to_string(),
+        )),
+    }
+}
+
+/// Puts the comments of `source`, other than doc comments, into `formatted`, which is `source`
+/// printed again, after the tokens that they follow there. The line after a line comment is
+/// indented once more than the line the comment follows, which is indented by `indent` if it is
+/// the first.
+fn with_comments(source: &str, formatted: &str, indent: &str) -> String {
+    let spans = comments(source);
+    if spans.is_empty() {
+        return formatted.to_string();
+    }
+
+    // The tokens of both that are the same, i.e., their longest common subsequence. Formatting
+    // only changes parentheses and the spelling of numerals, where `O` is printed as `0`.
+    let same = |a: &Tok, b: &Tok| a == b || matches!((a, b), (Tok::Zero, Tok::Num(0)));
+    let tokens = |text| Lexer::new(text).flatten().collect::<Vec<_>>();
+    let (old, new) = (tokens(source), tokens(formatted));
+    let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match same(&old[i].1, &new[j].1) {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+    let mut matched = vec![None; old.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if same(&old[i].1, &new[j].1) {
+            matched[i] = Some(j);
+            (i, j) = (i + 1, j + 1);
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    let mut out = String::new();
+    let mut pos = 0;
+    for span in spans {
+        // After the last token before the comment that is still there.
+        let before = old
+            .iter()
+            .take_while(|(_, _, end)| *end <= span.start)
+            .count();
+        let at = matched[..before]
+            .iter()
+            .rev()
+            .find_map(|j| *j)
+            .map_or(0, |j| new[j].2);
+
+        out.push_str(&formatted[pos..at.max(pos)]);
+        pos = pos.max(at);
+        // A comment that follows a line comment is on a line of its own already.
+        let own_line = out.trim_end_matches(' ').ends_with('\n');
+        if !out.is_empty() && !out.ends_with(' ') {
+            out.push(' ');
+        }
+        let comment = source[span.start..span.end].trim_end();
+        out.push_str(comment);
+
+        if comment.starts_with("--") {
+            let rest = formatted[pos..].trim_start_matches(' ');
+            pos = formatted.len() - rest.len();
+            if !rest.is_empty() && !rest.starts_with('\n') {
+                let line = &out[out.rfind('\n').map_or(0, |i| i + 1)..];
+                let line_indent = match out.contains('\n') {
+                    true => line[..line.len() - line.trim_start().len()].to_string(),
+                    false => indent.to_string(),
+                };
+                out.push('\n');
+                out.push_str(&line_indent);
+                if !own_line {
+                    out.push_str(INDENT);
+                }
+            }
+        }
+    }
+    out.push_str(&formatted[pos..]);
+    out
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+}
+
+impl Formatter<'_> {
+    /// Prints the statements between `start` and `end` with the comments around them.
+    fn items(&self, stmts: &[(Span, Statement)], start: usize, end: usize, depth: usize) -> String {
+        let indent = INDENT.repeat(depth);
+        let mut out = String::new();
+        let mut pos = start;
+
+        for (span, stmt) in stmts {
+            let blank = self.comments(&self.source[pos..span.start], &indent, &mut out);
+            if blank && !out.is_empty() {
+                out.push('\n');
+            }
+
+            let text = &self.source[span.start..span.end];
+            let module = match stmt {
+                Statement::Documented(_, stmt) => matches!(**stmt, Statement::Module(..)),
+                stmt => matches!(stmt, Statement::Module(..)),
+            };
+            // The comments in a module are put back by the module itself.
+            let stmt = match module {
+                true => self.statement(*span, stmt, depth),
+                false => with_comments(text, &self.statement(*span, stmt, depth), &indent),
+            };
+            out.push_str(&indent);
+            out.push_str(&stmt);
+            out.push('\n');
+            pos = span.end;
+        }
+
+        self.comments(&self.source[pos..end], &indent, &mut out);
+        out
+    }
+
+    /// Prints the comments in `gap`, which is only whitespace and comments, and returns whether
+    /// there is a blank line after the last of them.
+    fn comments(&self, gap: &str, indent: &str, out: &mut String) -> bool {
+        let mut pos = 0;
+
+        for (i, span) in comments(gap).into_iter().enumerate() {
+            let newlines = gap[pos..span.start].matches('\n').count();
+            // A comment on the line of a statement stays there.
+            if i == 0 && newlines == 0 && out.ends_with('\n') {
+                out.pop();
+                out.push(' ');
+            } else {
+                if newlines > 1 && !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(indent);
+            }
+            out.push_str(gap[span.start..span.end].trim_end());
+            out.push('\n');
+            pos = span.end;
+        }
+
+        gap[pos..].matches('\n').count() > 1
+    }
+
+    fn statement(&self, span: Span, stmt: &Statement, depth: usize) -> String {
+        let indent = INDENT.repeat(depth);
+        let column = indent.len();
+
+        match stmt {
+            Statement::Eval(e) => format!("eval {};", expr(e, ATOM)),
+            Statement::Check(e) => format!(":type {}", expr(e, ANNOTATION)),
+            Statement::Declare(x, ty) => {
+                let head = format!("def {x} :: ");
+                let ty = broken(ty, ANNOTATION, column + head.chars().count(), depth + 1);
+                format!("{head}{ty};")
+            }
+            Statement::Let(x, AstNode::AnnotatedTerm { term, ty }) => {
+                let flat = format!(
+                    "let {x} := {} :: {};",
+                    expr(term, FORALL),
+                    expr(ty, ANNOTATION)
+                );
+                if fits(column, &flat) {
+                    return flat;
+                }
+
+                let head = format!("{indent}{INDENT}:: ");
+                let ty = broken(ty, ANNOTATION, head.chars().count(), depth + 1);
+                format!("let {x} := {}\n{head}{ty};", expr(term, FORALL))
+            }
+            Statement::Let(x, e) => format!("let {x} := {};", expr(e, ANNOTATION)),
+            Statement::Rec(x, ty, clauses) => {
+                let head = format!("rec {x} :: ");
+                let ty = broken(ty, ANNOTATION, column + head.chars().count(), depth + 1);
+                let clauses = clauses
+                    .iter()
+                    .map(|clause| {
+                        let patterns = clause.patterns.iter().map(pattern).collect::<Vec<_>>();
+                        format!(
+                            "\n{indent}{INDENT}| {} = {}",
+                            patterns.join(" "),
+                            expr(&clause.body, ANNOTATION)
+                        )
+                    })
+                    .collect::<String>();
+                format!("{head}{ty}{clauses};")
+            }
+            Statement::Record(x, params, fields) => {
+                let params = params
+                    .iter()
+                    .map(|param| format!(" {}", binding(param)))
+                    .collect::<String>();
+                let fields = fields
+                    .iter()
+                    .map(|(name, ty)| format!("{name} : {}", expr(ty, ANNOTATION)))
+                    .collect::<Vec<_>>();
+
+                let flat = format!("record {x}{params} := {{ {} }};", fields.join(", "));
+                match fits(column, &flat) {
+                    true => flat,
+                    false => format!(
+                        "record {x}{params} := {{\n{indent}{INDENT}{}\n{indent}}};",
+                        fields.join(&format!(",\n{indent}{INDENT}"))
+                    ),
+                }
+            }
+            Statement::Module(x, body) => {
+                // The body is between the braces.
+                let open = Lexer::new(&self.source[span.start..span.end])
+                    .flatten()
+                    .find(|(_, tok, _)| *tok == Tok::LBrace)
+                    .map(|(_, _, end)| span.start + end)
+                    .unwrap();
+                let head = with_comments(
+                    &self.source[span.start..open],
+                    &format!("module {x} where {{"),
+                    &indent,
+                );
+                let body = self.items(body, open, span.end - 1, depth + 1);
+                format!("{head}\n{body}{indent}}}")
+            }
+            Statement::Import(path) => format!("import \"{path}\";"),
+            Statement::Fixity(Fixity { assoc, level }, names) => {
+                let keyword = match assoc {
+                    Assoc::Non => "infix",
+                    Assoc::Left => "infixl",
+                    Assoc::Right => "infixr",
+                };
+                format!("{keyword} {level} {};", names.join(" "))
+            }
+            Statement::Private(stmt) => format!("private {}", self.statement(span, stmt, depth)),
+            Statement::Documented(doc, stmt) => {
+                let docs = doc
+                    .split('\n')
+                    .map(|line| match line {
+                        "" => format!("--|\n{indent}"),
+                        line => format!("--| {line}\n{indent}"),
+                    })
+                    .collect::<String>();
+                let start = span.start + after_docs(&self.source[span.start..span.end]);
+                format!(
+                    "{docs}{}",
+                    self.statement(Span { start, ..span }, stmt, depth)
+                )
+            }
+        }
+    }
+}
+
+fn fits(column: usize, text: &str) -> bool {
+    column + text.chars().count() <= WIDTH
+}
+
+/// Prints `ast` at `level` starting at `column`, breaking a `∀` after each binder and a function
+/// type after each arrow if it does not fit. The lines after the first are indented `depth` times.
+fn broken(ast: &AstNode, level: u8, column: usize, depth: usize) -> String {
+    stack::grow(move || {
+        let flat = expr(ast, level);
+        if fits(column, &flat) || precedence(ast) > level {
+            return flat;
+        }
+
+        let indent = INDENT.repeat(depth);
+        match ast {
+            AstNode::Forall { args, ret } => {
+                let args = args.iter().map(|arg| binding(arg)).collect::<Vec<_>>();
+                format!(
+                    "∀ {} .\n{indent}{}",
+                    args.join(&format!(",\n{indent}")),
+                    broken(ret, FORALL, indent.len(), depth)
+                )
+            }
+            AstNode::DependentFunctionSpace { arg, ret } => format!(
+                "{} ->\n{indent}{}",
+                expr(arg, LAMBDA),
+                broken(ret, ARROW, indent.len(), depth)
+            ),
+            _ => flat,
+        }
+    })
+}
+
+/// A binding `(x : A)` of a `∀` or a record.
+fn binding(ast: &AstNode) -> String {
+    match ast {
+        AstNode::AnnotatedTerm { term, ty } => {
+            format!("({} : {})", expr(term, ATOM), expr(ty, ANNOTATION))
+        }
+        _ => expr(ast, ATOM),
+    }
+}
+
+fn precedence(ast: &AstNode) -> u8 {
+    match ast {
+        AstNode::Succ(_) => SUCC,
+        AstNode::App { .. } => APP,
+        AstNode::Lambda { .. } => LAMBDA,
+        AstNode::DependentFunctionSpace { .. } => ARROW,
+        AstNode::Forall { .. } => FORALL,
+        AstNode::AnnotatedTerm { .. } => ANNOTATION,
+        _ => ATOM,
+    }
+}
+
+/// Prints `ast` so that it can be parsed at precedence `level`.
+fn expr(ast: &AstNode, level: u8) -> String {
+    stack::grow(move || {
+        let s = match ast {
+            AstNode::Var(x, _) => x.clone(),
+            AstNode::Num(n) => n.to_string(),
+            AstNode::Hole(_) => "?".to_string(),
+            AstNode::Nat => "ℕ".to_string(),
+            AstNode::Universe => "U".to_string(),
+            AstNode::Type(Type::Boolean) => "bool".to_string(),
+            AstNode::Type(Type::Integer) => "i64".to_string(),
+            AstNode::Type(Type::String) => "str".to_string(),
+            AstNode::Succ(pred) => format!("S {}", expr(pred, ATOM)),
+            AstNode::App { clos, arg } => format!("{} {}", expr(clos, APP), expr(arg, ATOM)),
+            // The parentheses around a function keep its operators apart from the arguments.
+            AstNode::Paren(e) if level == APP => format!("({})", expr(e, ANNOTATION)),
+            AstNode::Paren(e) => expr(e, level),
+            AstNode::Lambda { arg, body } => format!("λ {arg} -> {}", expr(body, LAMBDA)),
+            AstNode::DependentFunctionSpace { arg, ret } => {
+                format!("{} -> {}", expr(arg, LAMBDA), expr(ret, ARROW))
+            }
+            AstNode::Forall { args, ret } => {
+                let args = args.iter().map(|arg| binding(arg)).collect::<Vec<_>>();
+                format!("∀ {} . {}", args.join(", "), expr(ret, FORALL))
+            }
+            AstNode::AnnotatedTerm { term, ty } => {
+                format!("{} :: {}", expr(term, FORALL), expr(ty, ANNOTATION))
+            }
+            AstNode::Case { scrutinee, arms } => {
+                let arms = arms
+                    .iter()
+                    .map(|arm| {
+                        let p = match &arm.patterns[..] {
+                            [Pattern::Succ(p)] => format!("S {}", pattern(p)),
+                            patterns => patterns.iter().map(pattern).collect::<Vec<_>>().join(" "),
+                        };
+                        format!("{p} -> {}", expr(&arm.body, ANNOTATION))
+                    })
+                    .collect::<Vec<_>>();
+                format!(
+                    "case {} of {{ {} }}",
+                    expr(scrutinee, ANNOTATION),
+                    arms.join("; ")
+                )
+            }
+            AstNode::RecordType { fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, ty)| format!("{name} : {}", expr(ty, ANNOTATION)))
+                    .collect::<Vec<_>>();
+                format!("{{ {} }}", fields.join(", "))
+            }
+            AstNode::Record { fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, e)| format!("{name} = {}", expr(e, ANNOTATION)))
+                    .collect::<Vec<_>>();
+                format!("{{ {} }}", fields.join(", "))
+            }
+            AstNode::Proj { term, field } => format!("{}.{field}", expr(term, ATOM)),
+        };
+
+        match precedence(ast) > level {
+            true => format!("({s})"),
+            false => s,
+        }
+    })
+}
+
+/// Prints a pattern of a clause, where constructors need parentheses.
+fn pattern(p: &Pattern) -> String {
+    match p {
+        Pattern::Var(x) => x.clone(),
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Zero => "O".to_string(),
+        Pattern::Num(n) => n.to_string(),
+        Pattern::Succ(p) => format!("(S {})", pattern(p)),
+    }
+}
+
+/// `ast` without the spans, which formatting moves.
+fn strip(ast: &AstNode) -> AstNode {
+    stack::grow(move || {
+        let go = |ast: &AstNode| Node::new(strip(ast));
+        let fields = |fields: &[(String, AstNode)]| {
+            fields
+                .iter()
+                .map(|(name, e)| (name.clone(), strip(e)))
+                .collect()
+        };
+
+        match ast {
+            AstNode::Var(x, _) => AstNode::Var(x.clone(), Span::default()),
+            AstNode::Hole(_) => AstNode::Hole(Span::default()),
+            AstNode::Type(_) | AstNode::Nat | AstNode::Num(_) | AstNode::Universe => ast.clone(),
+            AstNode::Succ(pred) => AstNode::Succ(go(pred)),
+            AstNode::AnnotatedTerm { term, ty } => AstNode::AnnotatedTerm {
+                term: go(term),
+                ty: go(ty),
+            },
+            // Only the parentheses around a function matter.
+            AstNode::App { clos, arg } => AstNode::App {
+                clos: match clos.as_ref() {
+                    AstNode::Paren(e) => Node::new(AstNode::Paren(go(e))),
+                    clos => go(clos),
+                },
+                arg: go(arg),
+            },
+            AstNode::Paren(e) => strip(e),
+            AstNode::Lambda { arg, body } => AstNode::Lambda {
+                arg: arg.clone(),
+                body: go(body),
+            },
+            AstNode::DependentFunctionSpace { arg, ret } => AstNode::DependentFunctionSpace {
+                arg: go(arg),
+                ret: go(ret),
+            },
+            AstNode::Forall { args, ret } => AstNode::Forall {
+                args: args.iter().map(|arg| go(arg)).collect(),
+                ret: go(ret),
+            },
+            AstNode::Case { scrutinee, arms } => AstNode::Case {
+                scrutinee: go(scrutinee),
+                arms: arms.iter().map(strip_clause).collect(),
+            },
+            AstNode::RecordType { fields: fs } => AstNode::RecordType { fields: fields(fs) },
+            AstNode::Record { fields: fs } => AstNode::Record { fields: fields(fs) },
+            AstNode::Proj { term, field } => AstNode::Proj {
+                term: go(term),
+                field: field.clone(),
+            },
+        }
+    })
+}
+
+fn strip_clause(clause: &Clause) -> Clause {
+    Clause {
+        patterns: clause.patterns.clone(),
+        body: strip(&clause.body),
+    }
+}
+
+fn strip_statement(stmt: &Statement) -> Statement {
+    match stmt {
+        Statement::Eval(e) => Statement::Eval(strip(e)),
+        Statement::Check(e) => Statement::Check(strip(e)),
+        Statement::Declare(x, ty) => Statement::Declare(x.clone(), strip(ty)),
+        Statement::Let(x, e) => Statement::Let(x.clone(), strip(e)),
+        Statement::Rec(x, ty, clauses) => Statement::Rec(
+            x.clone(),
+            strip(ty),
+            clauses.iter().map(strip_clause).collect(),
+        ),
+        Statement::Record(x, params, fields) => Statement::Record(
+            x.clone(),
+            params.iter().map(|param| Node::new(strip(param))).collect(),
+            fields
+                .iter()
+                .map(|(name, ty)| (name.clone(), strip(ty)))
+                .collect(),
+        ),
+        Statement::Module(x, body) => Statement::Module(
+            x.clone(),
+            body.iter()
+                .map(|(_, stmt)| (Span::default(), strip_statement(stmt)))
+                .collect(),
+        ),
+        Statement::Import(path) => Statement::Import(path.clone()),
+        Statement::Fixity(fixity, names) => Statement::Fixity(*fixity, names.clone()),
+        Statement::Private(stmt) => Statement::Private(Box::new(strip_statement(stmt))),
+        Statement::Documented(doc, stmt) => {
+            Statement::Documented(doc.clone(), Box::new(strip_statement(stmt)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::prelude::PRELUDE;
+
+    use super::format;
+
+    #[test]
+    fn test_format() {
+        let source = "\
+-- Natural numbers.
+let  two:=S(S O)::Nat;   -- two
+def P::forall (n:Nat),(m : Nat).Type;
+
+
+rec half :: Nat -> Nat | O = Zero | (Succ O) = 0 | (S (S n)) = S (half n);
+module M where { let id := \\x -> x :: Nat -> Nat; -- the identity
+  private let k := case two of { O -> 1 ; Succ n -> (f n) } :: Nat;
+}
+eval (half (M.id 4));
+let long := λ A -> λ B -> λ C -> λ x -> x :: ∀ (A : U), (B : U), (C : U), (x : A), (y : B) . A -> A -> A;
+";
+        let expected = "\
+-- Natural numbers.
+let two := S (S 0) :: ℕ; -- two
+def P :: ∀ (n : ℕ), (m : ℕ) . U;
+
+rec half :: ℕ -> ℕ
+    | O = 0
+    | (S O) = 0
+    | (S (S n)) = S (half n);
+module M where {
+    let id := λ x -> x :: ℕ -> ℕ; -- the identity
+    private let k := case two of { O -> 1; S n -> f n } :: ℕ;
+}
+eval (half (M.id 4));
+let long := λ A -> λ B -> λ C -> λ x -> x
+    :: ∀ (A : U), (B : U), (C : U), (x : A), (y : B) . A -> A -> A;
+";
+        let formatted = format(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted).unwrap(), formatted);
+
+        // Comments inside a statement follow the same tokens.
+        let source = "\
+let x := -- one
+  -- two
+  1;
+let f := \\x -> -- c
+ x;
+let h := (\\y -> y) {- b -} 3;
+module N {- n -} where {
+  rec g :: Nat -> Nat | O = O -- zero
+   | (S n) = (n);
+}
+";
+        let expected = "\
+let x := -- one
+    -- two
+    1;
+let f := λ x -> -- c
+    x;
+let h := (λ y -> y) {- b -} 3;
+module N {- n -} where {
+    rec g :: ℕ -> ℕ
+        | O = 0 -- zero
+        | (S n) = n;
+}
+";
+        let formatted = format(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted).unwrap(), formatted);
+        assert!(format("let x := ;").is_err());
+
+        let source = "{- Block\n   comment -}\n--| A doc   \n--|\nlet  x := 1;  {- after -}\n";
+        let expected = "{- Block\n   comment -}\n--| A doc\n--|\nlet x := 1; {- after -}\n";
+        assert_eq!(format(source).unwrap(), expected);
+
+        // Parentheses around operators are kept.
+        let source = "infixl  6 _+_ _-_;\nlet x := (a+b) * (f c) ! :: ℕ;\n";
+        let expected = "infixl 6 _+_ _-_;\nlet x := (a + b) * (f c) ! :: ℕ;\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_files() {
+        let files = ["../test_file/test1.pi", "../test_file/modules/main.pi"]
+            .iter()
+            .map(|file| fs::read_to_string(file).unwrap())
+            .collect::<Vec<_>>();
+
+        // The prelude is in the canonical style already.
+        for (_, source) in PRELUDE {
+            assert_eq!(format(source).unwrap(), *source);
+        }
+        for source in files.iter() {
+            let formatted = format(source).unwrap();
+            assert_eq!(format(&formatted).unwrap(), formatted);
+        }
+    }
+}
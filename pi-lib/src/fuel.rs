@@ -0,0 +1,324 @@
+//! Limits on how much work evaluation and checking may do.
+//!
+//! With `U : U`, a term can loop forever, e.g., through Girard's paradox. Every step of evaluation
+//! and checking therefore spends one unit of fuel, and a statement, together with everything it
+//! loads or contains, stops with [`EvalError::ResourceExhausted`] once it has run out of fuel,
+//! once it has taken too long or once it is cancelled, e.g., by Ctrl-C.
+
+use std::{
+    cell::RefCell,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    env::{EvalCtx, TypeCtx},
+    err::{EvalError, EvalResult},
+    pretty::pretty_in,
+    term::Term,
+};
+
+/// How much work a statement may do. There is no limit by default.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// The number of steps.
+    pub fuel: Option<u64>,
+    /// How long it may take.
+    pub timeout: Option<Duration>,
+    /// A flag that stops the statement as soon as it is set, e.g., by a Ctrl-C handler. It is
+    /// cleared when a statement starts.
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+/// Which limit was hit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Fuel,
+    Timeout,
+    Cancelled,
+}
+
+/// How far a statement got before it was stopped.
+#[derive(Clone, Debug)]
+pub struct Exhausted {
+    pub limit: Limit,
+    pub steps: u64,
+    pub elapsed: Duration,
+    /// The step that was stopped.
+    pub during: Box<Step>,
+}
+
+/// A step of evaluation or checking, with the context it runs in, so that the term a statement
+/// was stopped on can be inspected or run again, e.g., with more fuel.
+#[derive(Clone, Debug)]
+pub enum Step {
+    /// `eval(term, ctx)`.
+    Eval(Term, EvalCtx),
+    /// `type_check(de_brujin_index, term, ctx)`.
+    Check(usize, Term, Box<TypeCtx>),
+}
+
+/// The budget of the running statement.
+struct Budget {
+    limits: Limits,
+    steps: u64,
+    start: Instant,
+}
+
+/// Puts a budget back as the budget of the running statement once it is dropped, so that it is
+/// restored even if the statement panics.
+struct Restore(Option<Budget>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        BUDGET.with(|budget| *budget.borrow_mut() = self.0.take());
+    }
+}
+
+thread_local! {
+    /// The limits of the statements on this thread.
+    static LIMITS: RefCell<Limits> = RefCell::new(Limits::default());
+    /// The budget of the running statement, if any.
+    static BUDGET: RefCell<Option<Budget>> = const { RefCell::new(None) };
+}
+
+/// The limits of the statements on this thread.
+pub fn limits() -> Limits {
+    LIMITS.with(|limits| limits.borrow().clone())
+}
+
+/// Sets the limits of the statements on this thread, e.g., for a whole run of the interpreter.
+pub fn set_limits(limits: Limits) {
+    LIMITS.with(|l| *l.borrow_mut() = limits);
+}
+
+/// Runs `f` with a new budget, unless it is part of a statement that has one already.
+pub(crate) fn metered<T>(f: impl FnOnce() -> EvalResult<T>) -> EvalResult<T> {
+    if BUDGET.with(|budget| budget.borrow().is_some()) {
+        return f();
+    }
+
+    let limits = limits();
+    if let Some(cancel) = limits.cancel.as_ref() {
+        cancel.store(false, Ordering::Relaxed);
+    }
+    BUDGET.with(|budget| {
+        *budget.borrow_mut() = Some(Budget {
+            limits,
+            steps: 0,
+            start: Instant::now(),
+        })
+    });
+
+    let _restore = Restore(None);
+    f()
+}
+
+/// Runs `f` outside of the budget of the running statement, e.g., to print a value in a
+/// diagnostic after the statement has run out of fuel.
+pub(crate) fn unmetered<T>(f: impl FnOnce() -> T) -> T {
+    let _restore = Restore(BUDGET.with(|budget| budget.borrow_mut().take()));
+    f()
+}
+
+/// Spends one step of the running statement. `during` builds the step, and is only called if the
+/// statement has to stop.
+pub(crate) fn tick(during: impl FnOnce() -> Step) -> EvalResult<()> {
+    let exhausted = BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        let budget = budget.as_mut()?;
+        budget.steps += 1;
+
+        let elapsed = budget.start.elapsed();
+        let limits = &budget.limits;
+        let limit = if limits.fuel.is_some_and(|fuel| budget.steps > fuel) {
+            Limit::Fuel
+        } else if limits.timeout.is_some_and(|timeout| elapsed > timeout) {
+            Limit::Timeout
+        } else if limits
+            .cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+        {
+            Limit::Cancelled
+        } else {
+            return None;
+        };
+
+        Some((limit, budget.steps - 1, elapsed))
+    });
+
+    match exhausted {
+        None => Ok(()),
+        Some((limit, steps, elapsed)) => Err(EvalError::ResourceExhausted(Exhausted {
+            limit,
+            steps,
+            elapsed,
+            during: Box::new(during()),
+        })),
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Eval(term, ctx) => write!(f, "evaluating {}", pretty_in(term, ctx)),
+            Step::Check(_, term, _) => write!(f, "checking {term}"),
+        }
+    }
+}
+
+impl fmt::Display for Exhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let why = match self.limit {
+            Limit::Fuel => "ran out of fuel",
+            Limit::Timeout => "timed out",
+            Limit::Cancelled => "cancelled",
+        };
+        write!(
+            f,
+            "{why} after {} step(s) in {:.2}s while {}",
+            self.steps,
+            self.elapsed.as_secs_f64(),
+            self.during
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use super::{metered, set_limits, tick, unmetered, Limit, Limits, Step};
+    use crate::{
+        env::{EvalCtx, TypeCtx},
+        err::{EvalError, EvalResult},
+        eval::eval,
+        parse::{handle_statement, CmdParser},
+        prelude::load_prelude,
+        term::Term,
+    };
+
+    fn limit(res: Result<(), EvalError>) -> Option<Limit> {
+        match res {
+            Err(EvalError::ResourceExhausted(e)) => Some(e.limit),
+            _ => None,
+        }
+    }
+
+    fn step() -> Step {
+        Step::Eval(Term::Nat, EvalCtx::new())
+    }
+
+    #[test]
+    fn test_fuel() {
+        let mut ctx = TypeCtx::new();
+        load_prelude(&mut ctx).unwrap();
+        let mut run = |input: &str| {
+            let stmt = CmdParser::new().parse(input).unwrap();
+            handle_statement(stmt, &mut ctx).map(|_| ())
+        };
+
+        set_limits(Limits {
+            fuel: Some(1000),
+            ..Default::default()
+        });
+        // Each statement has its own budget, and the step it stopped on can be run again.
+        let Err(EvalError::ResourceExhausted(exhausted)) = run("eval (mul 20 20);") else {
+            panic!("expected the statement to run out of fuel");
+        };
+        assert_eq!(exhausted.limit, Limit::Fuel);
+        // The bound variables of the step are printed as their values.
+        let msg = exhausted.to_string();
+        assert!(msg.contains("while evaluating natElim (λ _ -> ℕ)"), "{msg}");
+        assert!(
+            !msg.split(' ').any(|w| w.starts_with('_') && w.len() > 1),
+            "{msg}"
+        );
+        let Step::Eval(term, env) = *exhausted.during else {
+            panic!("expected to stop while evaluating");
+        };
+        set_limits(Limits::default());
+        assert!(metered(|| eval(term, env)).is_ok());
+        set_limits(Limits {
+            fuel: Some(1000),
+            ..Default::default()
+        });
+        assert!(run("eval (mul 2 2);").is_ok());
+        set_limits(Limits::default());
+        assert!(run("eval (mul 20 20);").is_ok());
+    }
+
+    #[test]
+    fn test_fuel_mismatch() {
+        let mut ctx = TypeCtx::new();
+        let mut run = |input: &str| {
+            let stmt = CmdParser::new().parse(input).unwrap();
+            handle_statement(stmt, &mut ctx).map(|_| ())
+        };
+        run("let f := \\ x -> x :: ℕ -> ℕ;").unwrap();
+
+        // Printing the mismatch does not spend the fuel of the statement, whatever is left.
+        for fuel in 0..40 {
+            set_limits(Limits {
+                fuel: Some(fuel),
+                ..Default::default()
+            });
+            match run("let g := f :: ℕ -> ℕ -> ℕ;") {
+                Err(EvalError::ResourceExhausted(_)) => {}
+                Err(EvalError::TypeMismatch(msg)) => assert!(msg.contains("ℕ -> ℕ -> ℕ"), "{msg}"),
+                res => panic!("unexpected result {res:?}"),
+            }
+        }
+        set_limits(Limits::default());
+    }
+
+    #[test]
+    fn test_panic() {
+        set_limits(Limits {
+            fuel: Some(0),
+            ..Default::default()
+        });
+
+        // The budget is dropped or restored even if the statement panics.
+        let res = panic::catch_unwind(|| metered(|| -> EvalResult<()> { panic!("stopped") }));
+        assert!(res.is_err());
+        assert!(tick(step).is_ok());
+        let res = metered(|| {
+            let res = panic::catch_unwind(|| unmetered(|| panic!("stopped")));
+            assert!(res.is_err());
+            tick(step)
+        });
+        assert_eq!(limit(res), Some(Limit::Fuel));
+        set_limits(Limits::default());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let cancel = Arc::new(AtomicBool::new(true));
+        set_limits(Limits {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        });
+
+        // Nothing is metered outside of a statement, and the flag is cleared when one starts.
+        assert!(tick(step).is_ok());
+        let res = metered(|| {
+            tick(step)?;
+            cancel.store(true, Ordering::Relaxed);
+            metered(|| tick(step))
+        });
+        assert_eq!(limit(res), Some(Limit::Cancelled));
+        set_limits(Limits::default());
+    }
+}
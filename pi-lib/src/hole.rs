@@ -0,0 +1,350 @@
+//! Typed holes.
+//!
+//! A hole `?` stands for a term that is not written yet. It checks against any type, and the type
+//! it should have is recorded as a goal together with the local variables in scope. The functions
+//! here work on the holes of a source: [`give`] fills one with a term, [`refine`] with a function
+//! applied to new holes, [`case_split`] with a case analysis on a natural number and [`intro`] with
+//! lambdas. Each of them returns the new source, in which the filled statement still checks.
+
+use std::{cell::RefCell, fmt};
+
+use crate::{
+    ast::Span,
+    env::TypeCtx,
+    err::{EvalError, EvalResult},
+    eval::force,
+    parse::{check_statement, Checked, ProgramParser},
+    pretty::pretty_with_locals,
+    term::{Neutral, Type, Value, VariableName},
+};
+
+/// How many new holes [`refine`] tries to apply the function to.
+const MAX_ARGS: usize = 8;
+
+thread_local! {
+    /// The goals found so far, if anyone is collecting them.
+    static GOALS: RefCell<Option<Vec<Goal>>> = const { RefCell::new(None) };
+}
+
+/// The type that a hole should have and what is in scope there.
+#[derive(Clone, Debug)]
+pub struct Goal {
+    pub span: Span,
+    pub ty: Type,
+    /// The local variables in scope and their types, the innermost one last.
+    pub locals: Vec<(String, Type)>,
+    /// The names of the locals `Local(i)` that the types refer to.
+    names: Vec<String>,
+}
+
+impl Goal {
+    /// Pretty prints a type in the scope of the hole.
+    pub fn show(&self, ty: &Type) -> String {
+        pretty_with_locals(ty, &self.names)
+    }
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "? : {}", self.show(&self.ty))?;
+        for (x, ty) in &self.locals {
+            write!(f, "\n  {x} : {}", self.show(ty))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Records the goal of the hole at `span`, whose locals are called `names`.
+pub(crate) fn record(span: Span, names: &[String], ctx: &TypeCtx, ty: Type) {
+    GOALS.with(|goals| {
+        // The same term may be checked more than once, e.g., a type that is inferred and then
+        // checked against the universe.
+        let mut goals = goals.borrow_mut();
+        let Some(goals) = goals.as_mut().filter(|g| g.iter().all(|g| g.span != span)) else {
+            return;
+        };
+
+        let locals = names
+            .iter()
+            .enumerate()
+            // Anonymous binders and shadowed variables cannot be referred to.
+            .filter(|(i, x)| !x.is_empty() && !names[i + 1..].contains(x))
+            .filter_map(|(i, x)| {
+                ctx.1
+                    .get(&VariableName::Local(i))
+                    .map(|ty| (x.clone(), ty.clone()))
+            })
+            .collect();
+        goals.push(Goal {
+            span,
+            ty,
+            locals,
+            names: names.to_vec(),
+        });
+    })
+}
+
+/// Runs `f` and returns the goals of the holes that it checks.
+pub fn collect_goals<T>(f: impl FnOnce() -> T) -> (T, Vec<Goal>) {
+    let outer = GOALS.with(|goals| goals.replace(Some(vec![])));
+    let res = f();
+    let goals = GOALS.with(|goals| goals.replace(outer));
+
+    (res, goals.unwrap_or_default())
+}
+
+/// Checks every statement of `source` in a copy of `ctx`.
+fn check(source: &str, ctx: &TypeCtx) -> EvalResult<Vec<Checked>> {
+    let stmts = ProgramParser::new()
+        .parse(source)
+        .map_err(|e| EvalError::ParseError(e.to_string()))?;
+    let mut ctx = ctx.clone();
+
+    Ok(stmts
+        .into_iter()
+        .map(|(span, stmt)| check_statement(Some(span), stmt, &mut ctx))
+        .collect())
+}
+
+/// The goals of all the holes in `source`, in the order they are checked.
+pub fn goals(source: &str, ctx: &TypeCtx) -> EvalResult<Vec<Goal>> {
+    Ok(check(source, ctx)?
+        .into_iter()
+        .flat_map(|checked| checked.goals)
+        .collect())
+}
+
+/// The goal of the hole at `offset` in `source`.
+pub fn goal_at(source: &str, ctx: &TypeCtx, offset: usize) -> EvalResult<Goal> {
+    goals(source, ctx)?
+        .into_iter()
+        .find(|goal| goal.span.start <= offset && offset <= goal.span.end)
+        .ok_or_else(|| EvalError::UnboundVariable(format!("There is no hole at offset {offset}.")))
+}
+
+/// Whether `text` can be put anywhere without parentheses.
+fn atomic(text: &str) -> bool {
+    if !text.contains(char::is_whitespace) {
+        return true;
+    }
+
+    // `(...)` is atomic if the first parenthesis is closed at the very end.
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => (),
+        }
+        if depth == 0 {
+            return c == ')' && i == text.len() - 1;
+        }
+    }
+
+    false
+}
+
+/// Replaces the hole of `goal` by `text` and checks the statement that contains it.
+fn fill(source: &str, ctx: &TypeCtx, goal: &Goal, text: &str) -> EvalResult<String> {
+    let text = match text.trim() {
+        text if atomic(text) => text.to_string(),
+        text => format!("({text})"),
+    };
+    let res = format!(
+        "{}{}{}",
+        &source[..goal.span.start],
+        text,
+        &source[goal.span.end..]
+    );
+
+    let filled = check(&res, ctx)?.into_iter().find(|checked| {
+        checked
+            .span
+            .is_some_and(|span| span.start <= goal.span.start && goal.span.start < span.end)
+    });
+    match filled.and_then(|checked| checked.error) {
+        Some(e) => Err(e),
+        None => Ok(res),
+    }
+}
+
+/// A name that is not in `taken`, preferring the `candidates`.
+fn fresh(candidates: &[&str], taken: &[String]) -> String {
+    (0..)
+        .flat_map(|i| {
+            candidates.iter().map(move |c| match i {
+                0 => c.to_string(),
+                _ => format!("{c}{i}"),
+            })
+        })
+        .find(|name| !taken.contains(name))
+        .unwrap()
+}
+
+/// The names that new binders at `goal` must not shadow.
+fn taken(goal: &Goal, ctx: &TypeCtx) -> Vec<String> {
+    goal.locals
+        .iter()
+        .map(|(x, _)| x.clone())
+        .chain(ctx.globals().into_iter().map(|(x, _)| x))
+        .collect()
+}
+
+/// Fills the hole at `offset` with `term`.
+pub fn give(source: &str, ctx: &TypeCtx, offset: usize, term: &str) -> EvalResult<String> {
+    let goal = goal_at(source, ctx, offset)?;
+    fill(source, ctx, &goal, term)
+}
+
+/// Fills the hole at `offset` with `f` applied to as few new holes as it takes to fit the goal.
+pub fn refine(source: &str, ctx: &TypeCtx, offset: usize, f: &str) -> EvalResult<String> {
+    let goal = goal_at(source, ctx, offset)?;
+    let f = match f.trim() {
+        f if atomic(f) => f.to_string(),
+        f => format!("({f})"),
+    };
+
+    let mut error = None;
+    for n in 0..=MAX_ARGS {
+        let text = std::iter::once(f.as_str())
+            .chain(std::iter::repeat_n("?", n))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match fill(source, ctx, &goal, &text) {
+            Ok(res) => return Ok(res),
+            // Applying `f` to nothing tells the most about why it does not fit.
+            Err(e) => _ = error.get_or_insert(e),
+        }
+    }
+
+    Err(error.unwrap())
+}
+
+/// Fills the hole at `offset` with a case analysis on the natural number `var`.
+pub fn case_split(source: &str, ctx: &TypeCtx, offset: usize, var: &str) -> EvalResult<String> {
+    let goal = goal_at(source, ctx, offset)?;
+    match goal.locals.iter().find(|(x, _)| x == var) {
+        Some((_, ty)) if matches!(force(ty.clone())?, Value::VNat) => (),
+        Some((_, ty)) => {
+            return Err(EvalError::TypeMismatch(format!(
+                "Cannot split on {var}: expected ℕ, found {}",
+                goal.show(ty)
+            )))
+        }
+        None => {
+            return Err(EvalError::UnboundVariable(format!(
+                "{var} is not a local variable at the hole."
+            )))
+        }
+    }
+
+    let pred = fresh(&["k", "n", "m"], &taken(&goal, ctx));
+    fill(
+        source,
+        ctx,
+        &goal,
+        &format!("case {var} of {{ O -> ?; S {pred} -> ? }}"),
+    )
+}
+
+/// Fills the hole at `offset`, whose goal is a dependent function type, with a lambda for each of
+/// its arguments.
+pub fn intro(source: &str, ctx: &TypeCtx, offset: usize) -> EvalResult<String> {
+    let goal = goal_at(source, ctx, offset)?;
+    let mut taken = taken(&goal, ctx);
+    let mut ty = goal.ty.clone();
+    let mut args = vec![];
+
+    while let Value::VPi { val, body } = force(ty)? {
+        let x = match *val {
+            Value::VUniverse => fresh(&["A", "B", "C"], &taken),
+            _ => fresh(&["x", "y", "z", "w"], &taken),
+        };
+        ty = body.call(Value::VNeutral(Neutral::NVar(VariableName::Global(
+            x.clone(),
+        ))))?;
+        taken.push(x.clone());
+        args.push(x);
+    }
+
+    if args.is_empty() {
+        return Err(EvalError::TypeMismatch(format!(
+            "Expected a dependent function, found {}",
+            goal.show(&goal.ty)
+        )));
+    }
+
+    let lambdas = args
+        .iter()
+        .map(|x| format!("λ {x} -> "))
+        .collect::<String>();
+    fill(source, ctx, &goal, &format!("{lambdas}?"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{env::TypeCtx, prelude::load_prelude};
+
+    use super::*;
+
+    fn ctx() -> TypeCtx {
+        let mut ctx = TypeCtx::new();
+        load_prelude(&mut ctx).unwrap();
+        ctx
+    }
+
+    #[test]
+    fn test_goals() {
+        let ctx = ctx();
+        let source = "let f := (λ x -> λ y -> ?) :: ℕ -> ℕ -> ℕ;\nlet g := add ? 1;";
+        let goals = goals(source, &ctx).unwrap();
+
+        assert_eq!(goals.len(), 2);
+        assert_eq!(goals[0].to_string(), "? : ℕ\n  x : ℕ\n  y : ℕ");
+        assert_eq!(&source[goals[0].span.start..goals[0].span.end], "?");
+        assert_eq!(goals[1].to_string(), "? : ℕ");
+    }
+
+    #[test]
+    fn test_distinct_holes() {
+        let ctx = ctx();
+        // Each hole stands for its own unknown, so they are not equal to each other.
+        let fails = |source| check(source, &ctx).unwrap()[0].error.is_some();
+        assert!(fails("let p := refl ℕ ? :: Eq ℕ ? ?;"));
+        assert!(fails("let p := refl ℕ 1 :: Eq ℕ ? 1;"));
+        assert!(!fails(
+            "let p := (λ n -> refl ℕ n) :: ∀ (n : ℕ) . Eq ℕ n n;"
+        ));
+    }
+
+    #[test]
+    fn test_commands() {
+        let ctx = ctx();
+        let source = "let f := ? :: ℕ -> ℕ -> ℕ;";
+        let offset = source.find('?').unwrap();
+
+        let source = intro(source, &ctx, offset).unwrap();
+        assert_eq!(source, "let f := (λ x -> λ y -> ?) :: ℕ -> ℕ -> ℕ;");
+
+        let offset = source.find('?').unwrap();
+        let split = case_split(&source, &ctx, offset, "x").unwrap();
+        assert_eq!(
+            split,
+            "let f := (λ x -> λ y -> (case x of { O -> ?; S k -> ? })) :: ℕ -> ℕ -> ℕ;"
+        );
+        assert!(case_split(&source, &ctx, offset, "z").is_err());
+
+        let refined = refine(&source, &ctx, offset, "add").unwrap();
+        assert_eq!(
+            refined,
+            "let f := (λ x -> λ y -> (add ? ?)) :: ℕ -> ℕ -> ℕ;"
+        );
+
+        let given = give(&source, &ctx, offset, "S y").unwrap();
+        assert_eq!(given, "let f := (λ x -> λ y -> (S y)) :: ℕ -> ℕ -> ℕ;");
+        assert!(give(&source, &ctx, offset, "λ z -> z").is_err());
+        assert!(give(&source, &ctx, 0, "y").is_err());
+        assert!(intro(&split, &ctx, split.find('?').unwrap()).is_err());
+    }
+}
@@ -0,0 +1,486 @@
+//! The lexer that the grammar in `lambda-pi.lalrpop` reads its tokens from.
+//!
+//! Identifiers are made of letters of any script, digits, `_` and primes, e.g., `x'` or `αβ`, and
+//! do not start with a digit or a prime. `λ`, `∀` and `ℕ` are symbols rather than letters, so `λx`
+//! is `λ` and then `x`. A word is a keyword only if the whole word is one: `Sum` and `S'` are
+//! identifiers while `S` is not.
+//!
+//! Operators are runs of symbols like `+`, `<=` or `≡`, except for `->`, `=` and `|`, which the
+//! grammar uses. A mixfix name is made of words and operators with a `_` for each argument, e.g.,
+//! `_+_` or `if_then_else_`; it is a single name (see `crate::fixity`).
+//!
+//! Comments run from `--` to the end of the line, or from `{-` to the matching `-}`, and the latter
+//! nest. As in Haskell, dashes that another symbol follows are an operator, e.g., `-->`, rather
+//! than a comment. A doc comment `--|` is a token, since it belongs to the definition after it.
+
+use std::fmt;
+
+use crate::ast::Span;
+
+/// The keywords with all their spellings, e.g., for completion.
+pub const KEYWORDS: &[&str] = &[
+    "def", "eval", "let", "rec", "case", "of", "record", "module", "where", "import", "private",
+    "infix", "infixl", "infixr", "forall", "∀", "lambda", "λ", "Type", "U", "Nat", "ℕ", "S",
+    "Succ", "O", "Zero",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tok<'input> {
+    Def,
+    Eval,
+    Let,
+    Rec,
+    Case,
+    Of,
+    Record,
+    Module,
+    Where,
+    Import,
+    Private,
+    Infix,
+    Infixl,
+    Infixr,
+    /// `forall` or `∀`.
+    Forall,
+    /// `lambda`, `λ` or `\`.
+    Lambda,
+    /// `Type` or `U`.
+    Universe,
+    /// `Nat` or `ℕ`.
+    Nat,
+    /// `Succ` or `S`.
+    Succ,
+    /// `Zero` or `O`.
+    Zero,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Semicolon,
+    Colon,
+    DoubleColon,
+    Assign,
+    Comma,
+    Dot,
+    Arrow,
+    Bar,
+    Equals,
+    Question,
+    Ident(&'input str),
+    /// An operator like `+`, which is a part of a mixfix name like `_+_`.
+    Op(&'input str),
+    Num(usize),
+    /// A string literal without the quotes.
+    Str(&'input str),
+    /// A line of a doc comment without the `--|`.
+    Doc(&'input str),
+}
+
+fn keyword(word: &str) -> Option<Tok<'static>> {
+    Some(match word {
+        "def" => Tok::Def,
+        "eval" => Tok::Eval,
+        "let" => Tok::Let,
+        "rec" => Tok::Rec,
+        "case" => Tok::Case,
+        "of" => Tok::Of,
+        "record" => Tok::Record,
+        "module" => Tok::Module,
+        "where" => Tok::Where,
+        "import" => Tok::Import,
+        "private" => Tok::Private,
+        "infix" => Tok::Infix,
+        "infixl" => Tok::Infixl,
+        "infixr" => Tok::Infixr,
+        "forall" => Tok::Forall,
+        "lambda" => Tok::Lambda,
+        "Type" | "U" => Tok::Universe,
+        "Nat" => Tok::Nat,
+        "Succ" | "S" => Tok::Succ,
+        "Zero" | "O" => Tok::Zero,
+        _ => return None,
+    })
+}
+
+/// The symbols; longer ones come first so that `::` is not read as two `:`.
+const SYMBOLS: &[(&str, Tok<'static>)] = &[
+    ("::", Tok::DoubleColon),
+    (":=", Tok::Assign),
+    ("->", Tok::Arrow),
+    ("(", Tok::LParen),
+    (")", Tok::RParen),
+    ("{", Tok::LBrace),
+    ("}", Tok::RBrace),
+    (";", Tok::Semicolon),
+    (":", Tok::Colon),
+    (",", Tok::Comma),
+    (".", Tok::Dot),
+    ("|", Tok::Bar),
+    ("=", Tok::Equals),
+    ("?", Tok::Question),
+    ("\\", Tok::Lambda),
+    ("λ", Tok::Lambda),
+    ("∀", Tok::Forall),
+    ("ℕ", Tok::Nat),
+];
+
+impl fmt::Display for Tok<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Tok::Def => "def",
+            Tok::Eval => "eval",
+            Tok::Let => "let",
+            Tok::Rec => "rec",
+            Tok::Case => "case",
+            Tok::Of => "of",
+            Tok::Record => "record",
+            Tok::Module => "module",
+            Tok::Where => "where",
+            Tok::Import => "import",
+            Tok::Private => "private",
+            Tok::Infix => "infix",
+            Tok::Infixl => "infixl",
+            Tok::Infixr => "infixr",
+            Tok::Forall => "∀",
+            Tok::Lambda => "λ",
+            Tok::Universe => "U",
+            Tok::Nat => "ℕ",
+            Tok::Succ => "S",
+            Tok::Zero => "O",
+            Tok::Ident(x) | Tok::Op(x) => x,
+            Tok::Num(n) => return write!(f, "{n}"),
+            Tok::Str(s) => return write!(f, "\"{s}\""),
+            Tok::Doc(s) => return write!(f, "--| {s}"),
+            tok => SYMBOLS.iter().find(|(_, t)| t == tok).unwrap().0,
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// A character that cannot start a token, or a comment or a string that is not closed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LexError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.span.start)
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    (c.is_alphabetic() || c == '_') && !matches!(c, 'λ' | 'ℕ')
+}
+
+fn is_ident(c: char) -> bool {
+    (c.is_alphanumeric() || c == '_' || c == '\'') && !matches!(c, 'λ' | 'ℕ')
+}
+
+pub(crate) fn is_op(c: char) -> bool {
+    matches!(
+        c,
+        '!' | '$'
+            | '%'
+            | '&'
+            | '*'
+            | '+'
+            | '-'
+            | '/'
+            | '<'
+            | '='
+            | '>'
+            | '@'
+            | '^'
+            | '|'
+            | '~'
+            | '¬'
+            | '×'
+            | '÷'
+            | '·'
+    ) || matches!(c, '\u{2190}'..='\u{21ff}' | '\u{2201}'..='\u{22ff}' | '\u{2a00}'..='\u{2aff}')
+}
+
+/// The operators that are symbols of the grammar.
+fn reserved(op: &str) -> Option<Tok<'static>> {
+    match op {
+        "->" => Some(Tok::Arrow),
+        "=" => Some(Tok::Equals),
+        "|" => Some(Tok::Bar),
+        _ => None,
+    }
+}
+
+/// The length of the name at the start of `s`, whose first part, a word or an operator, is
+/// `first` bytes long. An operator is a part of the name only if a `_` is next to it, e.g., in
+/// `_+_`, but not in `x_+y`, which is `x_`, `+` and `y`.
+fn name(s: &str, first: usize) -> usize {
+    let word = |i: usize| s[i..].find(|c| !is_ident(c)).unwrap_or(s.len() - i);
+    let op = |i: usize| s[i..].find(|c| !is_op(c)).unwrap_or(s.len() - i);
+    let mut len = first;
+
+    loop {
+        len += word(len);
+        let op = match s[..len].ends_with('_') {
+            true => op(len),
+            false => 0,
+        };
+        let next = s[len + op..].chars().next();
+        if op == 0 || next.is_some_and(|c| is_ident(c) && c != '_') {
+            return len;
+        }
+        len += op;
+    }
+}
+
+/// Whether `s` starts with a line comment: two dashes or more that no other symbol follows.
+fn line_comment(s: &str) -> bool {
+    let dashes = s.find(|c| c != '-').unwrap_or(s.len());
+    dashes >= 2 && !s[dashes..].chars().next().is_some_and(is_op)
+}
+
+/// The length of the nested comment at the start of `s`, if it is closed.
+fn block_comment(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < s.len() {
+        if s[i..].starts_with("{-") {
+            depth += 1;
+            i += 2;
+        } else if s[i..].starts_with("-}") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return Some(i);
+            }
+        } else {
+            i += s[i..].chars().next().unwrap().len_utf8();
+        }
+    }
+
+    None
+}
+
+enum Item<'input> {
+    Tok(Tok<'input>),
+    Comment,
+}
+
+pub type Spanned<'input> = Result<(usize, Tok<'input>, usize), LexError>;
+
+pub struct Lexer<'input> {
+    source: &'input str,
+    pos: usize,
+}
+
+impl<'input> Lexer<'input> {
+    pub fn new(source: &'input str) -> Self {
+        Self { source, pos: 0 }
+    }
+
+    /// The next token or comment.
+    fn item(&mut self) -> Option<Result<(usize, Item<'input>, usize), LexError>> {
+        let rest = &self.source[self.pos..];
+        let start = self.pos + rest.len() - rest.trim_start().len();
+        let rest = &self.source[start..];
+        let c = rest.chars().next()?;
+        let error = |end: usize, message: &str| {
+            Some(Err(LexError {
+                span: Span { start, end },
+                message: message.to_string(),
+            }))
+        };
+        let line = rest.find('\n').unwrap_or(rest.len());
+
+        let (item, len) = if let Some(doc) = rest[..line].strip_prefix("--|") {
+            let doc = doc.strip_prefix(' ').unwrap_or(doc).trim_end();
+            (Item::Tok(Tok::Doc(doc)), line)
+        } else if line_comment(rest) {
+            (Item::Comment, line)
+        } else if rest.starts_with("{-") {
+            match block_comment(rest) {
+                Some(len) => (Item::Comment, len),
+                None => return error(self.source.len(), "Unterminated comment"),
+            }
+        } else if c == '"' {
+            match rest[1..].find('"') {
+                Some(i) => (Item::Tok(Tok::Str(&rest[1..i + 1])), i + 2),
+                None => return error(self.source.len(), "Unterminated string"),
+            }
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            match rest[..len].parse() {
+                Ok(n) => (Item::Tok(Tok::Num(n)), len),
+                Err(_) => return error(start + len, "Number too large"),
+            }
+        } else if is_ident_start(c) {
+            let len = name(rest, 0);
+            let word = &rest[..len];
+            (Item::Tok(keyword(word).unwrap_or(Tok::Ident(word))), len)
+        } else if is_op(c) {
+            let len = rest.find(|c| !is_op(c)).unwrap_or(rest.len());
+            let op = &rest[..len];
+            match reserved(op) {
+                Some(tok) => (Item::Tok(tok), len),
+                // A name like `¬_` that starts with an operator.
+                None if rest[len..].starts_with('_') => {
+                    let len = name(rest, len);
+                    (Item::Tok(Tok::Ident(&rest[..len])), len)
+                }
+                None => (Item::Tok(Tok::Op(op)), len),
+            }
+        } else {
+            match SYMBOLS.iter().find(|(s, _)| rest.starts_with(s)) {
+                Some((s, tok)) => (Item::Tok(*tok), s.len()),
+                None => return error(start + c.len_utf8(), &format!("Unexpected character `{c}`")),
+            }
+        };
+
+        self.pos = start + len;
+        Some(Ok((start, item, start + len)))
+    }
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Spanned<'input>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.item()? {
+                Ok((_, Item::Comment, _)) => continue,
+                Ok((start, Item::Tok(tok), end)) => return Some(Ok((start, tok, end))),
+                Err(e) => {
+                    // Lexing goes on after the error, e.g., after an unexpected character.
+                    self.pos = e.span.end;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Where the comments in `source` are, not counting doc comments.
+pub fn comments(source: &str) -> Vec<Span> {
+    let mut lexer = Lexer::new(source);
+    let mut res = vec![];
+
+    while let Some(Ok((start, item, end))) = lexer.item() {
+        if let Item::Comment = item {
+            res.push(Span { start, end });
+        }
+    }
+
+    res
+}
+
+/// The offset of the first token of `source` after its doc comment, e.g., where the definition
+/// that it documents starts.
+pub fn after_docs(source: &str) -> usize {
+    Lexer::new(source)
+        .flatten()
+        .find(|(_, tok, _)| !matches!(tok, Tok::Doc(_)))
+        .map_or(0, |(start, _, _)| start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{comments, Lexer, Tok};
+
+    fn lex(source: &str) -> Vec<Tok<'_>> {
+        Lexer::new(source).map(|tok| tok.unwrap().1).collect()
+    }
+
+    #[test]
+    fn test_lexer() {
+        assert_eq!(
+            lex("let n' := λx -> S x :: Nat -> ℕ;"),
+            [
+                Tok::Let,
+                Tok::Ident("n'"),
+                Tok::Assign,
+                Tok::Lambda,
+                Tok::Ident("x"),
+                Tok::Arrow,
+                Tok::Succ,
+                Tok::Ident("x"),
+                Tok::DoubleColon,
+                Tok::Nat,
+                Tok::Arrow,
+                Tok::Nat,
+                Tok::Semicolon,
+            ]
+        );
+        assert_eq!(
+            lex("Sum S' i8 αβ _x2 Succ"),
+            [
+                Tok::Ident("Sum"),
+                Tok::Ident("S'"),
+                Tok::Ident("i8"),
+                Tok::Ident("αβ"),
+                Tok::Ident("_x2"),
+                Tok::Succ,
+            ]
+        );
+        assert_eq!(
+            lex("infixl 6 _+_; ¬_ x_+y a->b ≤ | ="),
+            [
+                Tok::Infixl,
+                Tok::Num(6),
+                Tok::Ident("_+_"),
+                Tok::Semicolon,
+                Tok::Ident("¬_"),
+                Tok::Ident("x_"),
+                Tok::Op("+"),
+                Tok::Ident("y"),
+                Tok::Ident("a"),
+                Tok::Arrow,
+                Tok::Ident("b"),
+                Tok::Op("≤"),
+                Tok::Bar,
+                Tok::Equals,
+            ]
+        );
+
+        let source = "--| The answer.\n--|\nlet x {- a {- nested -} comment -} := 42; -- done\n";
+        assert_eq!(
+            lex(source),
+            [
+                Tok::Doc("The answer."),
+                Tok::Doc(""),
+                Tok::Let,
+                Tok::Ident("x"),
+                Tok::Assign,
+                Tok::Num(42),
+                Tok::Semicolon,
+            ]
+        );
+        let spans = comments(source);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(
+            &source[spans[0].start..spans[0].end],
+            "{- a {- nested -} comment -}"
+        );
+        assert_eq!(&source[spans[1].start..spans[1].end], "-- done");
+
+        // Dashes are only a comment if no other symbol follows them.
+        assert_eq!(
+            lex("a --> b --- c\n_-->_ x ---\n-- d"),
+            [
+                Tok::Ident("a"),
+                Tok::Op("-->"),
+                Tok::Ident("b"),
+                Tok::Ident("_-->_"),
+                Tok::Ident("x"),
+            ]
+        );
+
+        let errors = ["let x := {- open", "import \"a;", "let x := #;"];
+        for source in errors {
+            assert!(Lexer::new(source).any(|tok| tok.is_err()));
+        }
+    }
+}
@@ -1,29 +1,47 @@
 //! A Simply Typed Lambda Calculus interpreter with Hindley-Milner type inference.
 
 pub mod ast;
+pub mod case;
 pub mod clos;
+pub mod coverage;
+pub mod document;
 pub mod env;
 pub mod err;
 pub mod eval;
+pub mod fixity;
+pub mod fixpoint;
+pub mod format;
+pub mod fuel;
+pub mod hole;
+pub mod lexer;
+pub mod module;
 pub mod parse;
+pub mod pattern;
+pub mod prelude;
+pub mod pretty;
+pub mod record;
+pub mod session;
+pub mod stack;
 pub mod term;
+pub mod termination;
+pub mod thunk;
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
-
     use crate::{
         clos::Closure,
         eval::{eval_checked, lift},
-        term::{CheckableTerm, Term, Value},
+        stack::Node,
+        term::{Binder, CheckableTerm, Term, Value},
     };
 
     #[test]
     fn test_id() {
         // \ x -> x
         let identity = CheckableTerm::Lambda {
-            term: Box::new(CheckableTerm::InfereableTerm {
-                term: Box::new(Term::Bounded(0)),
+            name: Binder::new("x"),
+            term: Node::new(CheckableTerm::InfereableTerm {
+                term: Node::new(Term::Bounded(0)),
             }),
         };
 
@@ -35,23 +53,46 @@ mod tests {
     #[test]
     fn test_lift() {
         // \ x -> \y -> \ x
-        let lambda = Value::VAbs(Box::new(Closure::new(
-            Arc::new(move |x, ctx| {
-                let inner = Box::new(Closure::new(Arc::new(move |_, _| Ok(x.clone())), ctx));
+        let lambda = Value::VAbs(Box::new(Closure::native("const", |x| {
+            let inner = Box::new(Closure::native("const'", move |_| Ok(x.clone())));
 
-                Ok(Value::VAbs(inner))
-            }),
-            Default::default(),
-        )));
+            Ok(Value::VAbs(inner))
+        })));
 
         let expected = CheckableTerm::Lambda {
-            term: Box::new(CheckableTerm::Lambda {
-                term: Box::new(CheckableTerm::InfereableTerm {
-                    term: Box::new(Term::Bounded(1)),
+            name: Binder::default(),
+            term: Node::new(CheckableTerm::Lambda {
+                name: Binder::default(),
+                term: Node::new(CheckableTerm::InfereableTerm {
+                    term: Node::new(Term::Bounded(1)),
                 }),
             }),
         };
-        let lambda = lift(0, lambda);
+        let lambda = lift(0, lambda).unwrap();
         assert_eq!(lambda, expected);
     }
+
+    #[test]
+    fn test_closure_eq() {
+        // \ x -> x and \ y -> y, which are equal up to the names of their variables.
+        let body = Node::new(CheckableTerm::InfereableTerm {
+            term: Node::new(Term::Bounded(0)),
+        });
+        let lhs = eval_checked(
+            CheckableTerm::Lambda {
+                name: Binder::new("x"),
+                term: body.clone(),
+            },
+            Default::default(),
+        );
+        let rhs = eval_checked(
+            CheckableTerm::Lambda {
+                name: Binder::new("y"),
+                term: body,
+            },
+            Default::default(),
+        );
+
+        assert_eq!(lhs.unwrap(), rhs.unwrap());
+    }
 }
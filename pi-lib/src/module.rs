@@ -0,0 +1,314 @@
+//! Modules, qualified names and imports.
+//!
+//! A definition `x` inside `module M where { ... }` is stored under the global name `M.x`.
+//! Inside `M` it can still be referred to as `x`; a name is looked up from the innermost module
+//! outwards, so inside `module N` nested in `M` the name `x` means `M.N.x`, `M.x` or `x`, in this
+//! order. Since `M.x` is parsed as a projection, this module also turns such projections back
+//! into qualified names when they name a global.
+
+use std::path::PathBuf;
+
+use crate::{
+    ast::{AstNode, Clause, Fixity, Pattern, Span},
+    env::TypeCtx,
+    err::{EvalError, EvalResult},
+    fixity::reassociate,
+    stack::{self, Node},
+    term::VariableName,
+};
+
+/// Where a definition is visible from: the file and the module that it is declared in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Owner {
+    pub file: Option<PathBuf>,
+    pub path: Vec<String>,
+}
+
+/// The module state of a session.
+#[derive(Clone, Debug, Default)]
+pub struct Scope {
+    /// The file being loaded; imports are resolved relative to it.
+    pub file: Option<PathBuf>,
+    /// The module we are in, e.g., `["M", "N"]` inside `module N` which is inside `module M`.
+    pub path: Vec<String>,
+    /// Private definitions and the file and module they are private to.
+    pub private: Vec<(String, Owner)>,
+    /// The files that are being loaded, used to detect import cycles.
+    pub loading: Vec<PathBuf>,
+    /// The files that have been loaded and are not loaded again.
+    pub loaded: Vec<PathBuf>,
+    /// The doc comments of the globals.
+    pub docs: Vec<(String, String)>,
+    /// The fixities declared for the operators.
+    pub fixities: Vec<(String, Fixity)>,
+}
+
+impl Owner {
+    /// Whether code at `scope` can see definitions owned by `self`.
+    fn contains(&self, scope: &Scope) -> bool {
+        self.file == scope.file && scope.path.starts_with(&self.path)
+    }
+}
+
+impl Scope {
+    /// The global name of a definition `ident` in the current module.
+    pub fn qualify(&self, ident: &str) -> String {
+        self.path
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(ident))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// Whether the global `name` can be referred to from the current module.
+    pub fn is_visible(&self, name: &str) -> bool {
+        self.private
+            .iter()
+            .all(|(x, owner)| x != name || owner.contains(self))
+    }
+
+    pub fn owner(&self) -> Owner {
+        Owner {
+            file: self.file.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+/// Finds the global that `name` refers to from the current module, if any.
+///
+/// `extra` is a global that is being defined and not yet in the context, i.e., a recursive
+/// definition referring to itself.
+pub(crate) fn lookup_global(
+    name: &str,
+    ctx: &TypeCtx,
+    extra: Option<&str>,
+) -> EvalResult<Option<String>> {
+    let scope = &ctx.2;
+
+    for i in (0..=scope.path.len()).rev() {
+        let candidate = scope.path[..i]
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(name))
+            .collect::<Vec<_>>()
+            .join(".");
+        let defined = extra == Some(candidate.as_str())
+            || ctx
+                .1
+                .get(&VariableName::Global(candidate.clone()))
+                .is_some();
+
+        if !defined {
+            continue;
+        }
+
+        return match scope
+            .private
+            .iter()
+            .find(|(x, owner)| *x == candidate && !owner.contains(scope))
+        {
+            Some((_, owner)) => Err(EvalError::UnboundVariable(format!(
+                "{candidate} is private to {}",
+                show_owner(owner)
+            ))),
+            None => Ok(Some(candidate)),
+        };
+    }
+
+    Ok(None)
+}
+
+/// Finds the global that `name` refers to from the current module; e.g., `x` refers to `M.x`
+/// inside `module M`.
+pub fn resolve_name(name: &str, ctx: &TypeCtx) -> EvalResult<Option<String>> {
+    lookup_global(name, ctx, None)
+}
+
+fn show_owner(owner: &Owner) -> String {
+    let file = owner
+        .file
+        .as_ref()
+        .map_or("<interactive>".to_string(), |f| f.display().to_string());
+
+    match owner.path.is_empty() {
+        true => file,
+        false => format!("module {} in {file}", owner.path.join(".")),
+    }
+}
+
+/// Collects `M.N.x` into `["M", "N", "x"]` if the projection chain starts with a variable, and
+/// returns where the variable is.
+pub(crate) fn segments(ast: &AstNode) -> Option<(Vec<String>, Span)> {
+    stack::grow(move || match ast {
+        AstNode::Var(name, span) => Some((vec![name.clone()], *span)),
+        AstNode::Proj { term, field } => {
+            let (mut res, span) = segments(term)?;
+            res.push(field.clone());
+            Some((res, span))
+        }
+        _ => None,
+    })
+}
+
+pub(crate) fn pattern_variables(pattern: &Pattern, res: &mut Vec<String>) {
+    match pattern {
+        Pattern::Var(name) => res.push(name.clone()),
+        Pattern::Succ(pred) => pattern_variables(pred, res),
+        Pattern::Wildcard | Pattern::Zero | Pattern::Num(_) => (),
+    }
+}
+
+/// Resolves the names in a clause whose pattern variables are bound in its body.
+pub(crate) fn resolve_clause(
+    clause: &Clause,
+    locals: &[String],
+    ctx: &TypeCtx,
+    extra: Option<&str>,
+) -> EvalResult<Clause> {
+    let mut locals = locals.to_vec();
+    for pattern in clause.patterns.iter() {
+        pattern_variables(pattern, &mut locals);
+    }
+
+    Ok(Clause {
+        patterns: clause.patterns.clone(),
+        body: resolve(&clause.body, &locals, ctx, extra)?,
+    })
+}
+
+/// Replaces every global name in `ast` by its fully qualified name and the operators by the
+/// applications that they stand for (see [`crate::fixity`]). Names that are bound by
+/// `locals` or by binders in `ast` are left alone, and so are unknown names so that they are
+/// reported as unbound by type checking.
+pub(crate) fn resolve(
+    ast: &AstNode,
+    locals: &[String],
+    ctx: &TypeCtx,
+    extra: Option<&str>,
+) -> EvalResult<AstNode> {
+    stack::grow(move || {
+        let go = |ast: &AstNode| resolve(ast, locals, ctx, extra).map(Node::new);
+
+        match ast {
+            AstNode::Var(name, span) if !locals.contains(name) => Ok(AstNode::Var(
+                lookup_global(name, ctx, extra)?.unwrap_or(name.clone()),
+                *span,
+            )),
+            AstNode::Var(..)
+            | AstNode::Hole(_)
+            | AstNode::Type(_)
+            | AstNode::Nat
+            | AstNode::Num(_)
+            | AstNode::Universe => Ok(ast.clone()),
+            AstNode::Proj { term, field } => {
+                // Try the longest qualified name first, e.g., `M.N.x` before `M.N`.
+                if let Some((segs, span)) =
+                    segments(ast).filter(|(segs, _)| !locals.contains(&segs[0]))
+                {
+                    for i in (2..=segs.len()).rev() {
+                        let qualified = segs[..i].join(".");
+                        if let Some(name) = lookup_global(&qualified, ctx, extra)? {
+                            // The variable now covers the whole qualified name.
+                            let span = Span {
+                                start: span.start,
+                                end: span.start + qualified.len(),
+                            };
+                            return Ok(segs[i..].iter().fold(
+                                AstNode::Var(name, span),
+                                |term, field| AstNode::Proj {
+                                    term: Node::new(term),
+                                    field: field.clone(),
+                                },
+                            ));
+                        }
+                    }
+                }
+
+                Ok(AstNode::Proj {
+                    term: go(term)?,
+                    field: field.clone(),
+                })
+            }
+            AstNode::AnnotatedTerm { term, ty } => Ok(AstNode::AnnotatedTerm {
+                term: go(term)?,
+                ty: go(ty)?,
+            }),
+            AstNode::App { clos, arg } => match reassociate(ast, locals, ctx, extra)? {
+                Some(ast) => Ok(ast),
+                None => Ok(AstNode::App {
+                    clos: go(clos)?,
+                    arg: go(arg)?,
+                }),
+            },
+            AstNode::Paren(e) => resolve(e, locals, ctx, extra),
+            AstNode::Succ(pred) => Ok(AstNode::Succ(go(pred)?)),
+            AstNode::Lambda { arg, body } => {
+                let mut locals = locals.to_vec();
+                locals.push(arg.clone());
+
+                Ok(AstNode::Lambda {
+                    arg: arg.clone(),
+                    body: Node::new(resolve(body, &locals, ctx, extra)?),
+                })
+            }
+            AstNode::DependentFunctionSpace { arg, ret } => Ok(AstNode::DependentFunctionSpace {
+                arg: go(arg)?,
+                ret: go(ret)?,
+            }),
+            AstNode::Forall { args, ret } => {
+                let mut locals = locals.to_vec();
+                let args = args
+                    .iter()
+                    .map(|binding| match binding.as_ref() {
+                        AstNode::AnnotatedTerm { term, ty } => {
+                            let ty = resolve(ty, &locals, ctx, extra)?;
+                            if let AstNode::Var(x, _) = term.as_ref() {
+                                locals.push(x.clone());
+                            }
+
+                            Ok(Node::new(AstNode::AnnotatedTerm {
+                                term: term.clone(),
+                                ty: Node::new(ty),
+                            }))
+                        }
+                        _ => Ok(binding.clone()),
+                    })
+                    .collect::<EvalResult<Vec<_>>>()?;
+
+                Ok(AstNode::Forall {
+                    args,
+                    ret: Node::new(resolve(ret, &locals, ctx, extra)?),
+                })
+            }
+            AstNode::Case { scrutinee, arms } => Ok(AstNode::Case {
+                scrutinee: go(scrutinee)?,
+                arms: arms
+                    .iter()
+                    .map(|arm| resolve_clause(arm, locals, ctx, extra))
+                    .collect::<EvalResult<Vec<_>>>()?,
+            }),
+            AstNode::RecordType { fields } => {
+                // Each field can refer to the fields before it.
+                let mut locals = locals.to_vec();
+                let fields = fields
+                    .iter()
+                    .map(|(name, ty)| {
+                        let ty = resolve(ty, &locals, ctx, extra)?;
+                        locals.push(name.clone());
+                        Ok((name.clone(), ty))
+                    })
+                    .collect::<EvalResult<Vec<_>>>()?;
+
+                Ok(AstNode::RecordType { fields })
+            }
+            AstNode::Record { fields } => Ok(AstNode::Record {
+                fields: fields
+                    .iter()
+                    .map(|(name, e)| Ok((name.clone(), resolve(e, locals, ctx, extra)?)))
+                    .collect::<EvalResult<Vec<_>>>()?,
+            }),
+        }
+    })
+}
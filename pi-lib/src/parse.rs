@@ -1,66 +1,698 @@
-use std::{fs, path::Path, vec};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+    vec,
+};
 
 use crate::{
+    ast::{ast_transform, ast_transform_fixpoint, Assoc, AstNode, Span, Statement},
+    coverage::check_coverage,
     env::TypeCtx,
-    err::EvalResult,
-    eval::{eval, sanity_check, type_check},
-    term::{CheckableTerm, Value, VariableName},
+    err::{EvalError, EvalResult},
+    eval::{eval, normalize, sanity_check, type_check},
+    fixity::is_mixfix,
+    fixpoint::{check_fixpoint, eval_fixpoint},
+    fuel,
+    hole::{collect_goals, Goal},
+    lexer::{LexError, Lexer, Tok},
+    module::{resolve, resolve_clause},
+    stack::Node,
+    term::{CheckableTerm, Type, Value, VariableName},
+    termination::check_termination,
 };
 
-include!(concat!(env!("CARGO_MANIFEST_DIR"), "/lang/lambda-pi.rs"));
+// The parser is generated by `lalrpop`, so we do not lint it.
+#[allow(clippy::all)]
+mod grammar {
+    include!(concat!(env!("CARGO_MANIFEST_DIR"), "/lang/lambda-pi.rs"));
+}
+
+pub use crate::lexer::KEYWORDS;
 
-pub fn eval_file<P: AsRef<Path>>(path: P) -> EvalResult<Value> {
-    let f = fs::read_to_string(path.as_ref())
-        .map_err(|e| crate::err::EvalError::FileNotFound(e.to_string()))?;
-    let res = CmdParser::new()
-        .parse(&f)
-        .map_err(|e| crate::err::EvalError::ParseError(e.to_string()))?;
+/// An error of the lexer or the parser.
+type ParseError<'input> = lalrpop_util::ParseError<usize, Tok<'input>, LexError>;
+
+/// A syntax error and where it is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The terminals that start a statement and an expression, as `lalrpop` names them. An expected
+/// list with all of them is described as "a statement" or "an expression".
+const STATEMENT: &[&str] = &[
+    "\"def\"",
+    "\"eval\"",
+    "\"let\"",
+    "\"rec\"",
+    "\"record\"",
+    "\"module\"",
+    "\"import\"",
+    "\"private\"",
+    "\"infix\"",
+    "\"infixl\"",
+    "\"infixr\"",
+    "DocComment",
+];
+const EXPRESSION: &[&str] = &[
+    "\"(\"",
+    "\"{\"",
+    "\"?\"",
+    "\"O\"",
+    "\"U\"",
+    "\"ℕ\"",
+    "\"case\"",
+    "Identifier",
+    "Operator",
+    "Num",
+];
+/// The terminals that start an expression but not an atom, e.g., an argument.
+const EXPRESSION_ONLY: &[&str] = &["\"S\"", "\"λ\"", "\"∀\""];
+
+/// Describes the terminals in `expected`, e.g., "a name, `(` or `?`".
+fn expected(expected: &[String]) -> String {
+    let has_all = |group: &[&str]| group.iter().all(|t| expected.iter().any(|e| e == t));
+    let mut res = vec![];
+    let mut skip = vec![];
+
+    if has_all(STATEMENT) {
+        res.push("a statement".to_string());
+        skip.extend(STATEMENT);
+    }
+    if has_all(EXPRESSION) {
+        res.push("an expression".to_string());
+        skip.extend(EXPRESSION.iter().chain(EXPRESSION_ONLY));
+    }
+    for name in expected.iter().filter(|e| !skip.contains(&e.as_str())) {
+        res.push(match name.as_str() {
+            "Identifier" => "a name".to_string(),
+            "Operator" => "an operator".to_string(),
+            "Num" => "a number".to_string(),
+            "Str" => "a string".to_string(),
+            "DocComment" => "a doc comment".to_string(),
+            name => format!("`{}`", name.trim_matches('"')),
+        });
+    }
+
+    match res.split_last() {
+        None => "nothing".to_string(),
+        Some((last, [])) => last.clone(),
+        Some((last, init)) => format!("{} or {last}", init.join(", ")),
+    }
+}
+
+impl From<ParseError<'_>> for SyntaxError {
+    fn from(e: ParseError) -> Self {
+        let (span, message) = match e {
+            ParseError::InvalidToken { location } => (
+                Span {
+                    start: location,
+                    end: location,
+                },
+                "Invalid token".to_string(),
+            ),
+            ParseError::UnrecognizedEof {
+                location,
+                expected: e,
+            } => (
+                Span {
+                    start: location,
+                    end: location,
+                },
+                format!("Unexpected end of input; expected {}", expected(&e)),
+            ),
+            ParseError::UnrecognizedToken {
+                token: (start, tok, end),
+                expected: e,
+            } => (
+                Span { start, end },
+                format!("Unexpected `{tok}`; expected {}", expected(&e)),
+            ),
+            ParseError::ExtraToken {
+                token: (start, tok, end),
+            } => (Span { start, end }, format!("Unexpected `{tok}`")),
+            ParseError::User { error } => (error.span, error.message),
+        };
+
+        Self { span, message }
+    }
+}
+
+macro_rules! parser {
+    ($(#[$doc:meta])* $name:ident, $ty:ty) => {
+        $(#[$doc])*
+        #[derive(Default)]
+        pub struct $name(grammar::$name);
+
+        impl $name {
+            pub fn new() -> Self {
+                Self(grammar::$name::new())
+            }
+
+            /// Parses `source`, failing at the first syntax error.
+            pub fn parse(&self, source: &str) -> Result<$ty, SyntaxError> {
+                let mut errors = vec![];
+                let res = self.0.parse(&mut errors, Lexer::new(source));
+                match errors.into_iter().next() {
+                    Some(e) => Err(e.error.into()),
+                    None => res.map_err(Into::into),
+                }
+            }
+        }
+    };
+}
+
+parser!(
+    /// Parses a whole file.
+    ProgramParser,
+    Vec<(Span, Statement)>
+);
+parser!(
+    /// Parses a single statement.
+    CmdParser,
+    Statement
+);
+parser!(
+    /// Parses an expression, e.g., in the REPL.
+    ExprParser,
+    Node<AstNode>
+);
 
+/// The offsets in `source` where a statement may start after an error: after a `;` or the `}` of
+/// a module outside of the braces of an expression, e.g., of a record or a case, and at a keyword
+/// that only starts a statement, which closes the braces that are still open.
+fn statement_ends(source: &str) -> Vec<usize> {
+    // Whether each brace that is open is the body of a module.
+    let mut braces = vec![];
+    let mut ends = vec![];
+    let mut prev = None;
+
+    for (start, tok, end) in Lexer::new(source).flatten() {
+        match tok {
+            Tok::Def
+            | Tok::Eval
+            | Tok::Let
+            | Tok::Rec
+            | Tok::Record
+            | Tok::Module
+            | Tok::Import
+            | Tok::Private
+            | Tok::Infix
+            | Tok::Infixl
+            | Tok::Infixr
+            | Tok::Doc(_) => {
+                while braces.last() == Some(&false) {
+                    braces.pop();
+                }
+                ends.push(start);
+            }
+            Tok::LBrace => braces.push(prev == Some(Tok::Where)),
+            // Every `}` closes a brace; only that of a module ends a statement.
+            Tok::RBrace if braces.pop() == Some(true) => ends.push(end),
+            Tok::Semicolon if braces.last() != Some(&false) => ends.push(end),
+            _ => (),
+        }
+        prev = Some(tok);
+    }
+
+    ends
+}
+
+impl ProgramParser {
+    /// Parses a whole file, skipping the statements with syntax errors up to the next `;` or the
+    /// next statement. Returns the statements that parse and all the syntax errors in the order
+    /// they appear; only the first error of a statement is reported.
+    pub fn parse_all(&self, source: &str) -> (Vec<(Span, Statement)>, Vec<SyntaxError>) {
+        let mut lex_errors = vec![];
+        let mut errors = vec![];
+        // The parser stops at an error of the lexer, so the erroneous tokens are left out.
+        let tokens = Lexer::new(source).filter_map(|tok| tok.map_err(|e| lex_errors.push(e)).ok());
+        let res = self.0.parse(&mut errors, tokens);
+
+        // An unterminated comment or string runs to the end, which the parser may not expect.
+        let truncated = lex_errors.iter().any(|e| e.span.end == source.len());
+        let mut errors = errors
+            .into_iter()
+            .map(|e| e.error)
+            .chain(res.as_ref().err().cloned())
+            .filter(|e| !(truncated && matches!(e, ParseError::UnrecognizedEof { .. })))
+            .map(SyntaxError::from)
+            .collect::<Vec<_>>();
+        errors.extend(lex_errors.iter().cloned().map(|e| SyntaxError {
+            span: e.span,
+            message: e.message,
+        }));
+        errors.sort_by_key(|e| e.span.start);
+
+        // The parser resumes at any `;`, even in the braces of a record or a case, where the rest
+        // of the statement only leads to more errors.
+        let ends = statement_ends(source);
+        let mut resume = 0;
+        errors.retain(|e| {
+            if e.span.start < resume {
+                return false;
+            }
+            resume = ends
+                .iter()
+                .copied()
+                .find(|end| *end > e.span.start)
+                .unwrap_or(source.len());
+            true
+        });
+
+        // A statement is not checked without the tokens that are left out.
+        let mut stmts = res.unwrap_or_default();
+        stmts.retain(|(span, _)| {
+            lex_errors
+                .iter()
+                .all(|e| e.span.start < span.start || span.end <= e.span.start)
+        });
+
+        (stmts, errors)
+    }
+}
+
+/// Evaluates all the statements in a file and returns the value of the last one.
+pub fn eval_file<P: AsRef<Path>>(path: P) -> EvalResult<Value> {
     let mut ctx = Default::default();
-    handle_statement(res, &mut ctx)
+    load_file(path, &mut ctx)?
+        .pop()
+        .ok_or_else(|| EvalError::ParseError("There is nothing to evaluate.".to_string()))
+}
+
+/// Loads a file into `ctx` and returns the values of its statements.
+///
+/// A file is only loaded once; loading it again does nothing. If anything in the file fails,
+/// none of its definitions are kept.
+pub fn load_file<P: AsRef<Path>>(path: P, ctx: &mut TypeCtx) -> EvalResult<Vec<Value>> {
+    let path = fs::canonicalize(path.as_ref())
+        .map_err(|e| EvalError::FileNotFound(format!("{}: {e}", path.as_ref().display())))?;
+
+    if let Some(i) = ctx.2.loading.iter().position(|f| *f == path) {
+        let cycle = ctx.2.loading[i..]
+            .iter()
+            .chain(std::iter::once(&path))
+            .map(|f| f.display().to_string())
+            .collect::<Vec<_>>();
+        return Err(EvalError::ImportError(format!(
+            "Import cycle: {}",
+            cycle.join(" -> ")
+        )));
+    }
+
+    if ctx.2.loaded.contains(&path) {
+        return Ok(vec![]);
+    }
+
+    let f = fs::read_to_string(&path).map_err(|e| EvalError::FileNotFound(e.to_string()))?;
+    let (stmts, errors) = ProgramParser::new().parse_all(&f);
+    if !errors.is_empty() {
+        let errors = errors
+            .iter()
+            .map(|e| {
+                let (line, col) = Span::line_col(&f, e.span.start);
+                format!("{}:{line}:{col}: {e}", path.display())
+            })
+            .collect::<Vec<_>>();
+        return Err(EvalError::ParseError(errors.join("\n")));
+    }
+
+    let saved = ctx.clone();
+    // Definitions in a file start at the top level no matter where it is imported.
+    ctx.2.file = Some(path.clone());
+    ctx.2.path = vec![];
+    ctx.2.loading.push(path.clone());
+
+    let res = stmts
+        .into_iter()
+        .map(|(_, stmt)| handle_statement(stmt, ctx))
+        .collect::<EvalResult<Vec<_>>>();
+
+    match res {
+        Ok(values) => {
+            ctx.2.file = saved.2.file;
+            ctx.2.path = saved.2.path;
+            ctx.2.loading.pop();
+            ctx.2.loaded.push(path);
+            Ok(values)
+        }
+        Err(e) => {
+            *ctx = saved;
+            Err(e)
+        }
+    }
 }
 
+/// What checking a statement found.
+#[derive(Debug)]
+pub struct Checked {
+    pub span: Option<Span>,
+    /// The keyword of the statement, e.g., `let`, or `file` if the file cannot be read or parsed.
+    pub kind: &'static str,
+    /// The global that the statement defines, the module or the imported file.
+    pub name: Option<String>,
+    /// The type of the expression or of the global.
+    pub ty: Option<Type>,
+    /// The normal form of the expression or the definition of the global.
+    pub value: Option<Value>,
+    pub error: Option<EvalError>,
+    /// The holes that are left in the statement.
+    pub goals: Vec<Goal>,
+    pub elapsed: Duration,
+}
+
+/// What checking a file found.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// The source of the file, e.g., to turn spans into lines and columns.
+    pub source: String,
+    /// The number of globals the file declares.
+    pub definitions: usize,
+    pub statements: Vec<Checked>,
+}
+
+impl Report {
+    pub fn errors(&self) -> impl Iterator<Item = &Checked> {
+        self.statements.iter().filter(|stmt| stmt.error.is_some())
+    }
+
+    pub fn goals(&self) -> impl Iterator<Item = &Goal> {
+        self.statements.iter().flat_map(|stmt| &stmt.goals)
+    }
+}
+
+fn kind_and_name(stmt: &Statement, ctx: &TypeCtx) -> (&'static str, Option<String>) {
+    match stmt {
+        Statement::Eval(_) => ("eval", None),
+        Statement::Check(_) => ("check", None),
+        Statement::Declare(x, _) => ("def", Some(ctx.2.qualify(x))),
+        Statement::Let(x, _) => ("let", Some(ctx.2.qualify(x))),
+        Statement::Rec(x, _, _) => ("rec", Some(ctx.2.qualify(x))),
+        Statement::Record(x, _, _) => ("record", Some(ctx.2.qualify(x))),
+        Statement::Module(x, _) => ("module", Some(ctx.2.qualify(x))),
+        Statement::Import(path) => ("import", Some(path.clone())),
+        Statement::Fixity(fixity, _) => match fixity.assoc {
+            Assoc::Non => ("infix", None),
+            Assoc::Left => ("infixl", None),
+            Assoc::Right => ("infixr", None),
+        },
+        Statement::Private(stmt) | Statement::Documented(_, stmt) => kind_and_name(stmt, ctx),
+    }
+}
+
+/// Runs a statement and collects its type and value. If it fails, the context is left as it
+/// was.
+pub fn check_statement(span: Option<Span>, stmt: Statement, ctx: &mut TypeCtx) -> Checked {
+    let (kind, name) = kind_and_name(&stmt, ctx);
+    let expr = match &stmt {
+        Statement::Eval(e) => Some(e.clone()),
+        _ => None,
+    };
+
+    let saved = ctx.clone();
+    let now = Instant::now();
+    let (res, mut goals) = collect_goals(|| handle_statement(stmt, ctx));
+    let elapsed = now.elapsed();
+
+    let (ty, value, error) = match res {
+        Err(e) => {
+            *ctx = saved;
+            goals.clear();
+            (None, None, Some(e))
+        }
+        Ok(val) => match (kind, expr) {
+            ("eval", Some(e)) => (
+                collect_goals(|| handle_statement(Statement::Check(e), ctx))
+                    .0
+                    .ok(),
+                Some(val),
+                None,
+            ),
+            ("check", _) => (Some(val), None, None),
+            ("module" | "import", _) => (None, None, None),
+            _ => {
+                let name = name.as_deref().unwrap_or_default();
+                let value = ctx.definition(name).and_then(|val| normalize(val).ok());
+                (ctx.type_of(name), value, None)
+            }
+        },
+    };
+
+    Checked {
+        span,
+        kind,
+        name,
+        ty,
+        value,
+        error,
+        goals,
+        elapsed,
+    }
+}
+
+/// Checks every statement of a file. Unlike [`load_file`], it goes on after a statement fails
+/// so that all the errors are reported; the context keeps the statements that succeed.
+pub fn check_file<P: AsRef<Path>>(path: P, ctx: &mut TypeCtx) -> Report {
+    let mut report = Report::default();
+    let file_error = |span, e| Checked {
+        span,
+        kind: "file",
+        name: Some(path.as_ref().display().to_string()),
+        ty: None,
+        value: None,
+        error: Some(e),
+        goals: vec![],
+        elapsed: Duration::ZERO,
+    };
+
+    let path = match fs::canonicalize(path.as_ref()).and_then(|path| {
+        report.source = fs::read_to_string(&path)?;
+        Ok(path)
+    }) {
+        Ok(path) => path,
+        Err(e) => {
+            let e = EvalError::FileNotFound(format!("{}: {e}", path.as_ref().display()));
+            report.statements.push(file_error(None, e));
+            return report;
+        }
+    };
+    let (stmts, errors) = ProgramParser::new().parse_all(&report.source);
+    let mut errors = errors.into_iter().peekable();
+    let syntax_error = |e: SyntaxError| file_error(Some(e.span), EvalError::ParseError(e.message));
+
+    let (file, module) = (ctx.2.file.take(), std::mem::take(&mut ctx.2.path));
+    let before = ctx.1.len();
+    ctx.2.file = Some(path.clone());
+    // The file may be imported again by one of its imports.
+    ctx.2.loading.push(path.clone());
+
+    for (span, stmt) in stmts {
+        while let Some(e) = errors.next_if(|e| e.span.start <= span.start) {
+            report.statements.push(syntax_error(e));
+        }
+        report
+            .statements
+            .push(check_statement(Some(span), stmt, ctx));
+    }
+    report.statements.extend(errors.map(syntax_error));
+
+    report.definitions = ctx.1.len() - before;
+    ctx.2.file = file;
+    ctx.2.path = module;
+    ctx.2.loading.pop();
+    ctx.2.loaded.push(path);
+    report
+}
+
+/// Runs a statement within the limits of [`fuel`]. The statements that it loads or contains share
+/// its budget.
 pub fn handle_statement(stmt: Statement, ctx: &mut TypeCtx) -> EvalResult<Value> {
+    fuel::metered(|| run_statement(stmt, ctx))
+}
+
+fn run_statement(stmt: Statement, ctx: &mut TypeCtx) -> EvalResult<Value> {
     match stmt {
-        Statement::Eval(e) | Statement::Check(e) => {
-            let term = ast_transform(&e, vec![])?;
+        Statement::Eval(e) => {
+            let term = ast_transform(&resolve(&e, &[], ctx, None)?, vec![])?;
             log::debug!("debug: parsed term {term:?} with context {ctx:?}");
 
             type_check(0, term.clone(), ctx.clone())?;
-            eval(term, ctx.clone().into())
+            normalize(eval(term, ctx.clone().into())?)
+        }
+        // Only infers the type.
+        Statement::Check(e) => {
+            let term = ast_transform(&resolve(&e, &[], ctx, None)?, vec![])?;
+            log::debug!("debug: parsed term {term:?} with context {ctx:?}");
+
+            type_check(0, term, ctx.clone())
         }
         Statement::Declare(ident, ty) => {
-            let term = ast_transform(&ty, vec![])?;
+            let term = ast_transform(&resolve(&ty, &[], ctx, None)?, vec![])?;
             log::debug!("debug: parsed term {term:?} with context {ctx:?}");
 
             type_check(0, term.clone(), ctx.clone())?;
 
             let ty = CheckableTerm::InfereableTerm {
-                term: Box::new(term.clone()),
+                term: Node::new(term.clone()),
             };
             sanity_check(0, ty, ctx.clone(), Value::VUniverse)?;
             let v = eval(term, ctx.clone().into())?;
-            ctx.1 = ctx.1.push((VariableName::Global(ident), v.clone()));
+            let name = ctx.2.qualify(&ident);
+            ctx.1 = ctx.1.push((VariableName::Global(name), v.clone()));
 
             Ok(v)
         }
-        Statement::Let(ident, def) => {
-            let term = ast_transform(&def, vec![])?;
+        Statement::Let(ident, def) => define(ident, &def, ctx),
+        Statement::Record(ident, params, fields) => {
+            define(ident, &Statement::record_definition(&params, &fields), ctx)
+        }
+        Statement::Rec(ident, ty, clauses) => {
+            let name = ctx.2.qualify(&ident);
+            let term = ast_transform(&resolve(&ty, &[], ctx, None)?, vec![])?;
             log::debug!("debug: parsed term {term:?} with context {ctx:?}");
 
-            let ty = type_check(0, term.clone(), ctx.clone())?;
-            let v = eval(term.clone(), ctx.clone().into())?;
-            ctx.0 = ctx.0.push((VariableName::Global(ident.clone()), v.clone()));
-            ctx.1 = ctx.1.push((VariableName::Global(ident), ty));
+            let ty = CheckableTerm::InfereableTerm {
+                term: Node::new(term.clone()),
+            };
+            sanity_check(0, ty, ctx.clone(), Value::VUniverse)?;
+            let ty = eval(term, ctx.clone().into())?;
+
+            // The clauses may refer to the function itself before it is defined.
+            let clauses = clauses
+                .iter()
+                .map(|clause| resolve_clause(clause, &[], ctx, Some(&name)))
+                .collect::<EvalResult<Vec<_>>>()?;
+            let fix = Arc::new(ast_transform_fixpoint(&name, &clauses)?);
+            log::debug!("debug: parsed clauses {:?}", fix.clauses);
+
+            check_termination(&fix)?;
+            let rows = fix
+                .clauses
+                .iter()
+                .map(|c| c.patterns.clone())
+                .collect::<Vec<_>>();
+            check_coverage(&name, fix.arity, &rows)?;
+            check_fixpoint(&fix, &ty, ctx)?;
+
+            let v = eval_fixpoint(fix, ctx.clone().into());
+            ctx.0 = ctx.0.push((VariableName::Global(name.clone()), v));
+            ctx.1 = ctx.1.push((VariableName::Global(name), ty.clone()));
+
+            Ok(ty)
+        }
+        // Modules and imports have no value of their own, so we return the value of the last
+        // statement in them.
+        Statement::Module(ident, body) => {
+            ctx.2.path.push(ident);
+            let res = body
+                .into_iter()
+                .try_fold(Value::VUniverse, |_, (_, stmt)| handle_statement(stmt, ctx));
+            ctx.2.path.pop();
+
+            res
+        }
+        Statement::Import(path) => {
+            // Imports are relative to the importing file, or to the working directory.
+            let path = match ctx.2.file.as_ref().and_then(|f| f.parent()) {
+                Some(dir) => dir.join(path),
+                None => PathBuf::from(path),
+            };
+
+            Ok(load_file(path, ctx)?.pop().unwrap_or(Value::VUniverse))
+        }
+        Statement::Private(stmt) => {
+            let ident = match stmt.as_ref() {
+                Statement::Declare(ident, _)
+                | Statement::Let(ident, _)
+                | Statement::Rec(ident, _, _)
+                | Statement::Record(ident, _, _) => ident.clone(),
+                _ => {
+                    return Err(EvalError::ParseError(
+                        "Only definitions can be private.".to_string(),
+                    ))
+                }
+            };
+
+            let v = handle_statement(*stmt, ctx)?;
+            let name = ctx.2.qualify(&ident);
+            let owner = ctx.2.owner();
+            ctx.2.private.push((name, owner));
+
+            Ok(v)
+        }
+        Statement::Fixity(fixity, names) => {
+            for name in names {
+                if !is_mixfix(&name) {
+                    return Err(EvalError::ParseError(format!(
+                        "{name} is not an operator; its name needs a `_` for each argument, e.g., `_+_`."
+                    )));
+                }
+                let name = ctx.2.qualify(&name);
+                ctx.2.fixities.push((name, fixity));
+            }
+
+            Ok(Value::VUniverse)
+        }
+        Statement::Documented(doc, stmt) => {
+            let ident = documented_name(&stmt).ok_or_else(|| {
+                EvalError::ParseError("Only definitions and modules can be documented.".to_string())
+            })?;
+            let name = ctx.2.qualify(ident);
+
+            let v = handle_statement(*stmt, ctx)?;
+            ctx.2.docs.push((name, doc));
 
             Ok(v)
         }
     }
 }
 
+/// The name of a statement that a doc comment can be attached to.
+fn documented_name(stmt: &Statement) -> Option<&str> {
+    match stmt {
+        Statement::Declare(ident, _)
+        | Statement::Let(ident, _)
+        | Statement::Rec(ident, _, _)
+        | Statement::Record(ident, _, _)
+        | Statement::Module(ident, _) => Some(ident),
+        Statement::Private(stmt) => documented_name(stmt),
+        _ => None,
+    }
+}
+
+/// Checks `def` and binds it to `ident` in the current module.
+fn define(ident: String, def: &AstNode, ctx: &mut TypeCtx) -> EvalResult<Value> {
+    let term = ast_transform(&resolve(def, &[], ctx, None)?, vec![])?;
+    log::debug!("debug: parsed term {term:?} with context {ctx:?}");
+
+    let ty = type_check(0, term.clone(), ctx.clone())?;
+    let v = eval(term.clone(), ctx.clone().into())?;
+    let name = ctx.2.qualify(&ident);
+    ctx.0 = ctx.0.push((VariableName::Global(name.clone()), v.clone()));
+    ctx.1 = ctx.1.push((VariableName::Global(name), ty));
+
+    // The definition is kept glued, but it is shown as its normal form.
+    normalize(v)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::parse;
+    use crate::{
+        ast::{AstNode, Span, Statement},
+        env::TypeCtx,
+        err::{EvalError, EvalResult},
+        parse::{self, handle_statement},
+        term::Value,
+    };
 
     #[test]
     fn test_parse() {
@@ -78,11 +710,421 @@ mod tests {
         assert!(res2.is_ok());
     }
 
+    fn run(input: &[&str]) -> EvalResult<Vec<Value>> {
+        let mut ctx = TypeCtx::new();
+        input
+            .iter()
+            .map(|stmt| {
+                let stmt = parse::CmdParser::new()
+                    .parse(stmt)
+                    .map_err(|e| EvalError::ParseError(e.to_string()))?;
+                handle_statement(stmt, &mut ctx)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rec_plus() {
+        let input = [
+            r#"rec plus :: ℕ -> ℕ -> ℕ
+                | O m = m
+                | (S n) m = S (plus n m);"#,
+            r#"eval (plus 2 3);"#,
+        ];
+
+        let res = run(&input).unwrap();
+        assert_eq!(format!("{:?}", res.last().unwrap()), "S(S(S(S(S(O)))))");
+    }
+
+    #[test]
+    fn test_rec_stuck() {
+        let input = [
+            r#"rec pred :: ℕ -> ℕ
+                | O = O
+                | (S n) = n;"#,
+            r#"def x :: ℕ;"#,
+            r#"eval (pred x);"#,
+            r#"eval (pred (S x));"#,
+        ];
+
+        let res = run(&input).unwrap();
+        assert_eq!(format!("{:?}", res[2]), "App (Global pred)(Global x)");
+        assert_eq!(format!("{:?}", res[3]), "Global x");
+    }
+
+    #[test]
+    fn test_rec_non_terminating() {
+        let input = [r#"rec loop :: ℕ -> ℕ
+                | n = loop (S n);"#];
+
+        assert!(matches!(run(&input), Err(EvalError::TerminationError(_))));
+    }
+
+    #[test]
+    fn test_rec_ill_typed() {
+        let input = [r#"rec bad :: ℕ -> ℕ
+                | O = U
+                | (S n) = bad n;"#];
+
+        assert!(matches!(run(&input), Err(EvalError::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn test_case() {
+        let input = [
+            r#"let pred := \ n -> case n of { O -> O; S m -> m } :: ℕ -> ℕ;"#,
+            r#"eval (pred 3);"#,
+        ];
+
+        let res = run(&input).unwrap();
+        assert_eq!(format!("{:?}", res[1]), "S(S(O))");
+    }
+
+    #[test]
+    fn test_case_motive() {
+        let input = [
+            r#"def P :: ℕ -> U;"#,
+            r#"def p0 :: P 0;"#,
+            r#"def ps :: forall (n : ℕ) . P (S n);"#,
+            r#"let f := \ n -> case n of { O -> p0; S m -> ps m } :: forall (n : ℕ) . P n;"#,
+            r#"let g := \ n -> case n of { O -> p0; S m -> p0 } :: forall (n : ℕ) . P n;"#,
+        ];
+
+        assert!(run(&input[..4]).is_ok());
+        assert!(matches!(run(&input), Err(EvalError::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn test_case_coverage() {
+        let missing = [r#"let f := \ n -> case n of { O -> O; S O -> O } :: ℕ -> ℕ;"#];
+        let redundant = [r#"let f := \ n -> case n of { m -> m; O -> O } :: ℕ -> ℕ;"#];
+        let rec_missing = [r#"rec f :: ℕ -> ℕ -> ℕ | O m = m | n O = n;"#];
+
+        assert!(matches!(run(&missing), Err(EvalError::CoverageError(_))));
+        assert!(matches!(run(&redundant), Err(EvalError::CoverageError(_))));
+        assert!(matches!(
+            run(&rec_missing),
+            Err(EvalError::CoverageError(_))
+        ));
+    }
+
+    #[test]
+    fn test_glued() {
+        let input = [
+            r#"let Endo := \ A -> (forall (x : A) . A) :: forall (A : U) . U;"#,
+            r#"let inc := \ n -> S n :: Endo ℕ;"#,
+            r#"eval (inc (inc 1));"#,
+            r#"let twice := \ f -> \ n -> f (f n) :: forall (f : Endo ℕ) . Endo ℕ;"#,
+            r#"let bad := inc :: Endo (Endo ℕ);"#,
+        ];
+
+        // Definitions are unfolded for computation and conversion, but not in messages.
+        let res = run(&input[..4]).unwrap();
+        assert_eq!(format!("{:?}", res[2]), "S(S(S(O)))");
+        match run(&input) {
+            Err(EvalError::TypeMismatch(e)) => {
+                assert!(e.ends_with("expected Endo (Endo ℕ), found Endo ℕ"), "{e}")
+            }
+            res => panic!("expected a type mismatch, found {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rec_case() {
+        let input = [
+            r#"rec half :: ℕ -> ℕ
+                | n = case n of { O -> O; S m -> case m of { O -> O; S k -> S (half k) } };"#,
+            r#"eval (half 5);"#,
+        ];
+
+        let res = run(&input).unwrap();
+        assert_eq!(format!("{:?}", res[1]), "S(S(O))");
+    }
+
+    #[test]
+    fn test_record() {
+        let input = [
+            r#"rec plus :: ℕ -> ℕ -> ℕ | O m = m | (S n) m = S (plus n m);"#,
+            r#"record Monoid := { carrier : U, unit : carrier, op : carrier -> carrier -> carrier };"#,
+            r#"let natPlus := { unit = 0, carrier = ℕ, op = plus } :: Monoid;"#,
+            r#"eval (natPlus.op 2 natPlus.unit);"#,
+            r#"let bad := { carrier = ℕ, unit = U, op = plus } :: Monoid;"#,
+        ];
+
+        let res = run(&input[..4]).unwrap();
+        assert_eq!(format!("{:?}", res[3]), "S(S(O))");
+        assert!(matches!(run(&input), Err(EvalError::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn test_record_eta() {
+        let input = [
+            r#"record Pair (A : U) (B : U) := { fst : A, snd : B };"#,
+            r#"def q :: Pair ℕ ℕ;"#,
+            r#"def P :: Pair ℕ ℕ -> U;"#,
+            r#"def pq :: P q;"#,
+            r#"let e := pq :: P { fst = q.fst, snd = q.snd };"#,
+            r#"let e := pq :: P { fst = q.snd, snd = q.fst };"#,
+        ];
+
+        assert!(run(&input[..5]).is_ok());
+        assert!(matches!(run(&input), Err(EvalError::TypeMismatch(_))));
+    }
+
     #[test]
     fn test_parse_file() {
-        let path = "./test_file/test1.pi";
+        let path = "../test_file/test1.pi";
         let res = parse::eval_file(path);
 
         assert!(res.is_ok());
     }
+
+    #[test]
+    fn test_module() {
+        let input = [
+            r#"module Arith where {
+                rec plus :: ℕ -> ℕ -> ℕ
+                    | O m = m
+                    | (S n) m = S (plus n m);
+                module Consts where {
+                    let two := plus 1 1;
+                }
+                let four := plus Consts.two Consts.two;
+            }"#,
+            r#"eval (Arith.plus Arith.Consts.two 1);"#,
+            r#"eval Arith.four;"#,
+        ];
+
+        let res = run(&input).unwrap();
+        assert_eq!(format!("{:?}", res[1]), "S(S(S(O)))");
+        assert_eq!(format!("{:?}", res[2]), "S(S(S(S(O))))");
+
+        // Names inside a module are only unqualified inside it.
+        let res = run(&[input[0], r#"eval (plus 1 1);"#]);
+        assert!(matches!(res, Err(EvalError::UnboundVariable(_))));
+    }
+
+    #[test]
+    fn test_module_private() {
+        let module = r#"module M where {
+            private let secret := 1;
+            let public := S secret;
+        }"#;
+
+        let res = run(&[module, r#"eval M.public;"#]).unwrap();
+        assert_eq!(format!("{:?}", res[1]), "S(S(O))");
+
+        let res = run(&[module, r#"eval M.secret;"#]);
+        assert!(matches!(res, Err(EvalError::UnboundVariable(_))));
+    }
+
+    #[test]
+    fn test_comments_and_names() {
+        let res = run(&[
+            "let n' := {- nested {- comments -} -} 2; -- the end",
+            "let i8 := λ x' -> S x' :: ℕ -> ℕ;",
+            "let Sum := i8 n';",
+            "let αβ := Sum;",
+            "eval αβ;",
+        ])
+        .unwrap();
+        assert_eq!(format!("{:?}", res[4]), "S(S(S(O)))");
+
+        // `S` is a keyword, so it cannot be defined.
+        assert!(run(&["let S := 1;"]).is_err());
+        assert!(run(&["let x := {- open 1;"]).is_err());
+    }
+
+    #[test]
+    fn test_doc_comment() {
+        let mut ctx = TypeCtx::new();
+        let source = "--| The successor.\n--|\n--| Adds one.\nlet succ := λ n -> S n :: ℕ -> ℕ;\n\
+            module M where { --| Two.\n private let two := 2; }";
+        for (_, stmt) in parse::ProgramParser::new().parse(source).unwrap() {
+            handle_statement(stmt, &mut ctx).unwrap();
+        }
+
+        assert_eq!(ctx.doc("succ"), Some("The successor.\n\nAdds one."));
+        assert_eq!(ctx.doc("M.two"), Some("Two."));
+        assert_eq!(ctx.doc("M"), None);
+
+        let res = run(&["--| Not a definition.\neval 1;"]);
+        assert!(matches!(res, Err(EvalError::ParseError(_))));
+        assert!(run(&["let x := 1; --| Nothing after."]).is_err());
+    }
+
+    #[test]
+    fn test_operators() {
+        let prelude = [
+            "infixl 6 _+_;",
+            "infixl 7 _*_;",
+            "infix 4 _==_;",
+            "rec _+_ :: ℕ -> ℕ -> ℕ | O m = m | (S n) m = S (n + m);",
+            "rec _*_ :: ℕ -> ℕ -> ℕ | O m = O | (S n) m = m + n * m;",
+            "rec _! :: ℕ -> ℕ | O = 1 | (S n) = S n * n !;",
+            "def _==_ :: ℕ -> ℕ -> U;",
+            "def if_then_else_ :: ℕ -> ℕ -> ℕ -> ℕ;",
+        ];
+        let eval = |expr: &str| {
+            let stmt = format!("eval ({expr});");
+            run(&[&prelude[..], &[&stmt]].concat()).map(|res| format!("{:?}", res.last().unwrap()))
+        };
+
+        assert_eq!(eval("1 + 2 * 2").unwrap(), "S(S(S(S(S(O)))))");
+        assert_eq!(eval("(1 + 1) * 2").unwrap(), "S(S(S(S(O))))");
+        assert_eq!(eval("S 1 * 2 + 1").unwrap(), "S(S(S(S(S(O)))))");
+        assert_eq!(eval("3 !").unwrap(), "S(S(S(S(S(S(O))))))");
+        assert!(eval("if 1 then 2 else 1 + 1").is_ok());
+        assert!(eval("_+_ 1").is_ok());
+
+        // `_==_` does not associate, and `_+_` is missing an argument.
+        assert!(matches!(eval("1 == 1 == 1"), Err(EvalError::ParseError(_))));
+        assert!(matches!(eval("1 +"), Err(EvalError::ParseError(_))));
+
+        assert!(run(&["infixl 6 plus;"]).is_err());
+    }
+
+    #[test]
+    fn test_import() {
+        let res = parse::eval_file("../test_file/modules/main.pi").unwrap();
+        assert_eq!(format!("{:?}", res), "S(S(S(O)))");
+
+        // `one` is private to `arith.pi`.
+        let res = parse::eval_file("../test_file/modules/private.pi");
+        assert!(matches!(res, Err(EvalError::UnboundVariable(_))));
+    }
+
+    #[test]
+    fn test_check_file() {
+        let mut ctx = TypeCtx::new();
+        let report = parse::check_file("../test_file/check/errors.pi", &mut ctx);
+        assert_eq!(report.definitions, 2);
+        let errors = report.errors().collect::<Vec<_>>();
+        assert_eq!(errors.len(), 2);
+
+        // `let y := x x;` is on the second line.
+        let span = errors[0].span.unwrap();
+        assert_eq!(Span::line_col(&report.source, span.start), (2, 1));
+        assert_eq!(&report.source[span.start..span.end], "let y := x x;");
+
+        let z = &report.statements[2];
+        assert_eq!((z.kind, z.name.as_deref()), ("let", Some("z")));
+        assert_eq!(z.ty.as_ref().map(ToString::to_string).as_deref(), Some("ℕ"));
+        assert_eq!(
+            z.value.as_ref().map(ToString::to_string).as_deref(),
+            Some("S x")
+        );
+
+        let report = parse::check_file("../test_file/check/missing.pi", &mut ctx);
+        assert!(matches!(
+            report.statements[..],
+            [parse::Checked {
+                error: Some(EvalError::FileNotFound(_)),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let mut ctx = TypeCtx::new();
+        let report = parse::check_file("../test_file/check/syntax.pi", &mut ctx);
+        assert_eq!(report.definitions, 2);
+
+        let errors = report
+            .errors()
+            .map(|e| {
+                let (line, col) = Span::line_col(&report.source, e.span.unwrap().start);
+                (line, col, e.error.as_ref().unwrap().to_string())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                (2, 10, "Parse error: Unexpected `;`; expected an expression"),
+                (
+                    4,
+                    1,
+                    "Parse error: Unexpected `module`; expected an expression, `->`, `.`, `::` or `;`"
+                ),
+                (5, 16, "Parse error: Unexpected `->`; expected a name"),
+                (8, 14, "Parse error: Unexpected character `#`"),
+            ]
+            .map(|(line, col, e)| (line, col, e.to_string()))
+        );
+        let last = report.statements.last().unwrap();
+        assert_eq!((last.kind, last.error.is_none()), ("eval", true));
+
+        // Loading fails with all the errors.
+        let res = parse::load_file("../test_file/check/syntax.pi", &mut TypeCtx::new());
+        match res {
+            Err(EvalError::ParseError(e)) => assert_eq!(e.lines().count(), 4),
+            res => panic!("unexpected {res:?}"),
+        }
+
+        let (stmts, errors) = parse::ProgramParser::new().parse_all("let x := 1; {- open");
+        assert_eq!((stmts.len(), errors.len()), (1, 1));
+        assert_eq!(errors[0].message, "Unterminated comment");
+
+        // An error in the braces of a case or a record is the only one of its statement.
+        let source = "let f := λ n -> case n of { O -> ); S m -> m; S m -> m } :: ℕ -> ℕ;\n\
+                      let r := { a = , b = 1 };\n\
+                      module M where { let z := (; let w := 1; }\n\
+                      let y := 2;";
+        let (stmts, errors) = parse::ProgramParser::new().parse_all(source);
+        let lines = errors
+            .iter()
+            .map(|e| Span::line_col(source, e.span.start).0)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [1, 2, 3]);
+        assert_eq!(stmts.len(), 2);
+        assert!(parse::CmdParser::new().parse("let x := 1 2 ) 3;").is_err());
+    }
+
+    #[test]
+    fn test_import_cycle() {
+        let res = parse::eval_file("../test_file/cycle/a.pi");
+        assert!(matches!(res, Err(EvalError::ImportError(_))));
+
+        // The cycle starts from the file that is checked.
+        let report = parse::check_file("../test_file/cycle/a.pi", &mut TypeCtx::new());
+        let Some(EvalError::ImportError(e)) = &report.statements[0].error else {
+            panic!("expected an import cycle, found {:?}", report.statements);
+        };
+        let files = e
+            .split(" -> ")
+            .map(|f| f.rsplit('/').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(files, ["a.pi", "b.pi", "a.pi"]);
+    }
+
+    #[test]
+    fn test_check_and_globals() {
+        let mut ctx = TypeCtx::new();
+        for stmt in [
+            "def x :: ℕ;",
+            "let y := S x;",
+            "module M where { private def z :: U; def w :: z; }",
+            "let x := 2;",
+        ] {
+            let stmt = parse::CmdParser::new().parse(stmt).unwrap();
+            handle_statement(stmt, &mut ctx).unwrap();
+        }
+
+        let ty = handle_statement(
+            Statement::Check(AstNode::Var("y".into(), Span::default())),
+            &mut ctx,
+        );
+        assert_eq!(format!("{:?}", ty.unwrap()), "ℕ");
+
+        // `x` is listed at its last declaration and the private `M.z` is hidden.
+        let names = ctx
+            .globals()
+            .into_iter()
+            .map(|(x, _)| x)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["y", "M.w", "x"]);
+        assert!(ctx.definition("y").is_some());
+        assert!(ctx.definition("M.w").is_none());
+    }
 }
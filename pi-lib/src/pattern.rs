@@ -0,0 +1,189 @@
+//! Patterns over natural numbers and how they are matched against values.
+
+use std::fmt;
+
+use crate::{
+    env::TypeCtx,
+    err::{EvalError, EvalResult},
+    eval::force,
+    stack::Node,
+    term::{Binder, CheckableTerm, Neutral, Type, Value, VariableName},
+};
+
+/// A nameless pattern. Variables are numbered from left to right by the order in which they
+/// occur, so a clause body refers to the rightmost variable as `_0`; their names are only kept to
+/// print them.
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Pattern {
+    /// Matches anything and binds it; wildcards are variables that are never referred to.
+    Var(Binder),
+    Zero,
+    Succ(Box<Pattern>),
+}
+
+/// A clause `| p1 ... pn = body` or a case arm `p -> body`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Clause {
+    pub patterns: Vec<Pattern>,
+    pub body: CheckableTerm,
+}
+
+/// The outcome of matching a list of values against a list of patterns.
+pub enum Match {
+    /// The values match and the bound variables are given from left to right.
+    Matched(Vec<Value>),
+    /// The values can never match.
+    Mismatch,
+    /// Some value is neutral, so we cannot decide yet.
+    Stuck,
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Var(_) => write!(f, "_"),
+            Pattern::Zero => write!(f, "O"),
+            Pattern::Succ(pred) => match pred.as_ref() {
+                Pattern::Succ(_) => write!(f, "S ({:?})", pred),
+                _ => write!(f, "S {:?}", pred),
+            },
+        }
+    }
+}
+
+/// The constructor at the head of a pattern.
+///
+/// Only `ℕ` has constructors for now; an inductive type is described by its constructors, their
+/// arities and their siblings, which is all that coverage checking needs to know.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Constructor {
+    Zero,
+    Succ,
+}
+
+impl Constructor {
+    /// The number of arguments the constructor takes.
+    pub fn arity(&self) -> usize {
+        match self {
+            Constructor::Zero => 0,
+            Constructor::Succ => 1,
+        }
+    }
+
+    /// All the constructors of the same type.
+    pub fn siblings(&self) -> &'static [Constructor] {
+        match self {
+            Constructor::Zero | Constructor::Succ => &[Constructor::Zero, Constructor::Succ],
+        }
+    }
+
+    /// Builds the pattern `c p1 ... pn`.
+    pub fn apply(&self, mut args: Vec<Pattern>) -> Pattern {
+        match self {
+            Constructor::Zero => Pattern::Zero,
+            Constructor::Succ => Pattern::Succ(Box::new(args.remove(0))),
+        }
+    }
+}
+
+impl Pattern {
+    /// A variable without a name, e.g., in a case that is missing.
+    pub fn wildcard() -> Self {
+        Pattern::Var(Binder::default())
+    }
+
+    /// Splits a pattern into its constructor and arguments; variables have no head.
+    pub fn head(&self) -> Option<(Constructor, Vec<Pattern>)> {
+        match self {
+            Pattern::Var(_) => None,
+            Pattern::Zero => Some((Constructor::Zero, vec![])),
+            Pattern::Succ(pred) => Some((Constructor::Succ, vec![*pred.clone()])),
+        }
+    }
+
+    /// The number of variables bound by this pattern.
+    pub fn arity(&self) -> usize {
+        match self {
+            Pattern::Var(_) => 1,
+            Pattern::Zero => 0,
+            Pattern::Succ(pred) => pred.arity(),
+        }
+    }
+}
+
+/// Returns `Some(true)` if `val` matches, `Some(false)` if it does not and `None` if it is stuck.
+/// Forcing the value may fail, e.g., when it runs out of fuel.
+fn match_pattern(
+    pattern: &Pattern,
+    val: &Value,
+    bindings: &mut Vec<Value>,
+) -> EvalResult<Option<bool>> {
+    match (pattern, val) {
+        (Pattern::Var(_), _) => {
+            bindings.push(val.clone());
+            Ok(Some(true))
+        }
+        (_, Value::VNeutral(_)) => Ok(None),
+        (_, Value::VGlued { .. } | Value::VThunk(_)) => {
+            match_pattern(pattern, &force(val.clone())?, bindings)
+        }
+        (Pattern::Zero, Value::VZero) => Ok(Some(true)),
+        (Pattern::Succ(pred), Value::VSucc { pred: val }) => match_pattern(pred, val, bindings),
+        _ => Ok(Some(false)),
+    }
+}
+
+/// Matches the values against the patterns. A mismatch anywhere wins over being stuck since the
+/// clause can never apply then.
+pub fn match_clause(patterns: &[Pattern], args: &[Value]) -> EvalResult<Match> {
+    let mut bindings = vec![];
+    let mut stuck = false;
+
+    for (pattern, arg) in patterns.iter().zip(args) {
+        match match_pattern(pattern, arg, &mut bindings)? {
+            Some(true) => (),
+            Some(false) => return Ok(Match::Mismatch),
+            None => stuck = true,
+        }
+    }
+
+    Ok(match stuck {
+        true => Match::Stuck,
+        false => Match::Matched(bindings),
+    })
+}
+
+/// Type checks a pattern against `ty`, pushing the variables it binds into the context as fresh
+/// locals starting from `de_brujin_index`. Returns the value the pattern stands for so that it
+/// can be substituted into the rest of the type.
+pub(crate) fn check_pattern(
+    pattern: &Pattern,
+    ty: Type,
+    ctx: &mut TypeCtx,
+    de_brujin_index: &mut usize,
+) -> EvalResult<Value> {
+    match pattern {
+        Pattern::Var(_) => {
+            let name = VariableName::Local(*de_brujin_index);
+            ctx.1 = ctx.1.push((name.clone(), ty));
+            *de_brujin_index += 1;
+
+            Ok(Value::VNeutral(Neutral::NVar(name)))
+        }
+        Pattern::Zero | Pattern::Succ(_) if !matches!(force(ty.clone())?, Value::VNat) => {
+            Err(EvalError::TypeMismatch(format!(
+                "Pattern {:?} expects a natural number, found {}",
+                pattern, ty
+            )))
+        }
+        Pattern::Zero => Ok(Value::VZero),
+        Pattern::Succ(pred) => {
+            let pred = check_pattern(pred, Value::VNat, ctx, de_brujin_index)?;
+            Ok(Value::VSucc {
+                pred: Node::new(pred),
+            })
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! The standard prelude.
+//!
+//! The prelude is a handful of `.pi` files that are embedded in the crate so that sessions do not
+//! have to start from scratch: functions (`id`, `const`, `compose`), natural numbers (`natElim`,
+//! `add`, `mul`), Church-encoded booleans (`Bool`, `true`, `false`, `cond`, `not`, `and`, `or`)
+//! and Leibniz equality (`Eq`, `refl`, `sym`, `trans`, `cong`).
+
+use crate::{
+    env::TypeCtx,
+    err::{EvalError, EvalResult},
+    parse::{handle_statement, ProgramParser},
+};
+
+/// The files of the prelude with their names, in the order they are loaded.
+pub const PRELUDE: &[(&str, &str)] = &[
+    ("function.pi", include_str!("../prelude/function.pi")),
+    ("nat.pi", include_str!("../prelude/nat.pi")),
+    ("bool.pi", include_str!("../prelude/bool.pi")),
+    ("eq.pi", include_str!("../prelude/eq.pi")),
+];
+
+/// Loads the prelude into `ctx`.
+pub fn load_prelude(ctx: &mut TypeCtx) -> EvalResult<()> {
+    let parser = ProgramParser::new();
+
+    for (name, src) in PRELUDE {
+        let stmts = parser
+            .parse(src)
+            .map_err(|e| EvalError::ParseError(format!("prelude/{name}: {e}")))?;
+
+        for (_, stmt) in stmts {
+            handle_statement(stmt, ctx)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::TypeCtx,
+        err::EvalError,
+        parse::{handle_statement, CmdParser},
+        prelude::load_prelude,
+    };
+
+    #[test]
+    fn test_prelude() {
+        let mut ctx = TypeCtx::new();
+        load_prelude(&mut ctx).unwrap();
+
+        let mut run = |input: &str| {
+            let stmt = CmdParser::new()
+                .parse(input)
+                .map_err(|e| EvalError::ParseError(e.to_string()))?;
+            handle_statement(stmt, &mut ctx).map(|v| format!("{v:?}"))
+        };
+
+        assert_eq!(run("eval (mul 2 3);").unwrap(), "S(S(S(S(S(S(O))))))");
+        assert_eq!(run("eval (id ℕ 1);").unwrap(), "S(O)");
+        assert_eq!(
+            run("eval (cond ℕ (and true (not false)) 1 0);").unwrap(),
+            "S(O)"
+        );
+        // Closed arithmetic is decided by computation.
+        assert!(run("let p := refl ℕ 4 :: Eq ℕ (add 2 2) 4;").is_ok());
+        assert!(run("let q := refl ℕ 5 :: Eq ℕ (add 2 2) 5;").is_err());
+        assert!(run("let r := sym ℕ (add 2 2) 4 p :: Eq ℕ 4 (add 2 2);").is_ok());
+    }
+}
@@ -0,0 +1,598 @@
+//! A pretty printer that turns terms back into the surface syntax.
+//!
+//! Bound variables get names again (`x`, `y`, ... for terms, `A`, `B`, ... for types and `P`,
+//! `Q`, ... for type families), numerals are printed as numbers, non-dependent function types as
+//! `A -> B`, and parentheses are only inserted where the grammar needs them. For example, the type
+//! of the identity function is printed as `∀ (A : U) . A -> A`.
+
+use std::{collections::HashSet, fmt};
+
+use crate::{
+    env::EvalCtx,
+    eval::lift,
+    fuel,
+    pattern::{Clause, Pattern},
+    stack::{self, Node},
+    term::{Binder, CheckableTerm, Term, Value, VariableName},
+};
+
+/// The precedence levels of the grammar, from the tightest to the loosest.
+const ATOM: u8 = 0;
+const APP: u8 = 1;
+const LAMBDA: u8 = 2;
+const ARROW: u8 = 3;
+const FORALL: u8 = 4;
+const ANNOTATION: u8 = 5;
+
+const TERM_NAMES: &[&str] = &["x", "y", "z", "w", "u", "v"];
+const TYPE_NAMES: &[&str] = &["A", "B", "C", "D", "E", "F"];
+const FAMILY_NAMES: &[&str] = &["P", "Q", "R"];
+
+/// Calls `f` on every variable in `term` together with the number of binders around it.
+fn visit<F: FnMut(&Term, usize)>(term: &Term, depth: usize, f: &mut F) {
+    stack::grow(move || match term {
+        Term::Var(_) | Term::Bounded(_) => f(term, depth),
+        Term::AnnotatedTerm { term, ty } => {
+            visit_checked(term, depth, f);
+            visit_checked(ty, depth, f);
+        }
+        Term::App { clos, arg } => {
+            visit(clos, depth, f);
+            visit_checked(arg, depth, f);
+        }
+        Term::DependentFunctionSpace { arg, ret, .. } => {
+            visit_checked(arg, depth, f);
+            visit_checked(ret, depth + 1, f);
+        }
+        Term::IfElse { cond, conseq, alt } => {
+            visit(cond, depth, f);
+            visit(conseq, depth, f);
+            visit(alt, depth, f);
+        }
+        Term::Binary(e) => {
+            let (lhs, rhs) = e.extract_operands();
+            visit(&lhs, depth, f);
+            visit(&rhs, depth, f);
+        }
+        Term::Unary(e) => visit(&e.extract_operand(), depth, f),
+        Term::Succ { pred } => visit(pred, depth, f),
+        Term::Case { scrutinee, arms } => {
+            visit_checked(scrutinee, depth, f);
+            for arm in arms {
+                let arity = arm.patterns.iter().map(Pattern::arity).sum::<usize>();
+                visit_checked(&arm.body, depth + arity, f);
+            }
+        }
+        Term::RecordType { fields } => {
+            for (i, (_, ty)) in fields.iter().enumerate() {
+                visit_checked(ty, depth + i, f);
+            }
+        }
+        Term::Proj { term, .. } => visit(term, depth, f),
+        Term::Universe | Term::Nat | Term::Zero | Term::Hole { .. } => (),
+    })
+}
+
+fn visit_checked<F: FnMut(&Term, usize)>(term: &CheckableTerm, depth: usize, f: &mut F) {
+    stack::grow(move || match term {
+        CheckableTerm::InfereableTerm { term } => visit(term, depth, f),
+        CheckableTerm::Lambda { term, .. } => visit_checked(term, depth + 1, f),
+        CheckableTerm::Succ { term } => visit_checked(term, depth, f),
+        CheckableTerm::Record { fields } => {
+            for (_, e) in fields {
+                visit_checked(e, depth, f);
+            }
+        }
+        CheckableTerm::Zero => (),
+    })
+}
+
+/// Whether the variable bound `index` binders up occurs in `term`.
+fn occurs(term: &CheckableTerm, index: usize) -> bool {
+    let mut res = false;
+    visit_checked(term, 0, &mut |term, depth| {
+        res |= matches!(term, Term::Bounded(i) if *i == depth + index)
+    });
+    res
+}
+
+fn num(mut term: &Term) -> Option<usize> {
+    let mut n = 0;
+    loop {
+        match term {
+            Term::Zero => return Some(n),
+            Term::Succ { pred } => term = pred,
+            Term::AnnotatedTerm { term, .. } => {
+                return stack::grow(|| num_checked(term)).map(|m| m + n)
+            }
+            _ => return None,
+        }
+        n += 1;
+    }
+}
+
+fn num_checked(mut term: &CheckableTerm) -> Option<usize> {
+    let mut n = 0;
+    loop {
+        match term {
+            CheckableTerm::Zero => return Some(n),
+            CheckableTerm::Succ { term: pred } => term = pred,
+            CheckableTerm::InfereableTerm { term } => {
+                return stack::grow(|| num(term)).map(|m| m + n)
+            }
+            _ => return None,
+        }
+        n += 1;
+    }
+}
+
+/// Picks the names of a variable of type `ty`.
+fn names_for(ty: &CheckableTerm) -> &'static [&'static str] {
+    match ty {
+        CheckableTerm::InfereableTerm { term } => match term.as_ref() {
+            Term::Universe => TYPE_NAMES,
+            Term::DependentFunctionSpace { ret, .. } if names_for(ret) != TERM_NAMES => {
+                FAMILY_NAMES
+            }
+            _ => TERM_NAMES,
+        },
+        _ => TERM_NAMES,
+    }
+}
+
+struct Printer {
+    /// The names of the bound variables; the innermost one is the last.
+    names: Vec<String>,
+    /// The globals that occur in the term, which bound variables must not shadow.
+    globals: HashSet<String>,
+    /// The names of the free local variables `Local(i)`.
+    locals: Vec<String>,
+    /// The printed values of the free bound variables, the innermost one first, and their
+    /// precedences.
+    free: Vec<(String, u8)>,
+}
+
+impl Printer {
+    fn new(globals: HashSet<String>) -> Self {
+        Self {
+            names: vec![],
+            globals,
+            locals: vec![],
+            free: vec![],
+        }
+    }
+
+    /// A name that is not bound yet, not a global and not `taken`.
+    fn fresh(&self, candidates: &[&str], taken: impl Fn(&str) -> bool) -> String {
+        (0..)
+            .flat_map(|i| {
+                candidates.iter().map(move |c| match i {
+                    0 => c.to_string(),
+                    _ => format!("{c}{i}"),
+                })
+            })
+            .find(|name| !self.names.contains(name) && !self.globals.contains(name) && !taken(name))
+            .unwrap()
+    }
+
+    /// The name of a variable bound over `body`: the one it is written with, unless it would
+    /// capture a variable that `body` refers to, and otherwise a fresh one.
+    fn name(&self, binder: &Binder, body: &CheckableTerm, candidates: &[&str]) -> String {
+        match &binder.0 {
+            Some(name) if !self.captures(name, body, 1) => name.clone(),
+            _ => self.fresh(candidates, |_| false),
+        }
+    }
+
+    /// Whether a variable called `name` bound over `body` together with `arity - 1` others hides
+    /// another variable of `body`.
+    fn captures(&self, name: &str, body: &CheckableTerm, arity: usize) -> bool {
+        let mut res = false;
+        visit_checked(body, 0, &mut |term, depth| {
+            res |= match term {
+                // The variables bound around `body`, the innermost one first.
+                Term::Bounded(i) => i
+                    .checked_sub(depth + arity)
+                    .and_then(|i| self.names.len().checked_sub(i + 1))
+                    .is_some_and(|i| self.names[i] == name),
+                Term::Var(x) => self.var(x) == name,
+                _ => false,
+            }
+        });
+        res
+    }
+
+    fn bind<T>(&mut self, name: String, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.names.push(name);
+        let res = f(self);
+        self.names.pop();
+        res
+    }
+
+    /// Prints `term` so that it can be parsed at precedence `level`.
+    fn term(&mut self, term: &Term, level: u8) -> String {
+        let (s, prec) = self.term_prec(term);
+        paren(s, prec, level)
+    }
+
+    fn checked(&mut self, term: &CheckableTerm, level: u8) -> String {
+        let (s, prec) = self.checked_prec(term);
+        paren(s, prec, level)
+    }
+
+    fn var(&self, name: &VariableName) -> String {
+        match name {
+            VariableName::Global(x) => x.clone(),
+            VariableName::Local(i) => match self.locals.get(*i) {
+                Some(x) if !x.is_empty() => x.clone(),
+                _ => format!("_{i}"),
+            },
+            VariableName::Quote(i) => format!("_{i}"),
+        }
+    }
+
+    fn term_prec(&mut self, term: &Term) -> (String, u8) {
+        stack::grow(move || {
+            if let Some(n) = num(term) {
+                return (n.to_string(), ATOM);
+            }
+
+            match term {
+                Term::Var(name) => (self.var(name), ATOM),
+                Term::Bounded(i) => match self.names.len().checked_sub(i + 1) {
+                    Some(i) => (self.names[i].clone(), ATOM),
+                    None => match self.free.get(i - self.names.len()) {
+                        Some(val) => val.clone(),
+                        None => (format!("_{i}"), ATOM),
+                    },
+                },
+                Term::Universe => ("U".to_string(), ATOM),
+                Term::Nat => ("ℕ".to_string(), ATOM),
+                Term::Hole { .. } => ("?".to_string(), ATOM),
+                Term::Zero => ("0".to_string(), ATOM),
+                Term::Succ { pred } => (format!("S {}", self.term(pred, ATOM)), APP),
+                Term::AnnotatedTerm { term, ty } => (
+                    format!(
+                        "{} :: {}",
+                        self.checked(term, FORALL),
+                        self.checked(ty, ANNOTATION)
+                    ),
+                    ANNOTATION,
+                ),
+                Term::App { clos, arg } => (
+                    format!("{} {}", self.term(clos, APP), self.checked(arg, ATOM)),
+                    APP,
+                ),
+                Term::DependentFunctionSpace { arg, ret, .. } if !occurs(ret, 0) => {
+                    let arg = self.checked(arg, APP);
+                    // The argument is not referred to but it still shifts the indices.
+                    let ret = self.bind(String::new(), |p| p.checked(ret, ARROW));
+                    (format!("{arg} -> {ret}"), ARROW)
+                }
+                Term::DependentFunctionSpace { .. } => self.forall(term),
+                Term::Case { scrutinee, arms } => {
+                    let scrutinee = self.checked(scrutinee, ANNOTATION);
+                    let arms = arms
+                        .iter()
+                        .map(|arm| self.arm(arm))
+                        .collect::<Vec<_>>()
+                        .join("; ");
+                    (format!("case {scrutinee} of {{ {arms} }}"), ATOM)
+                }
+                Term::RecordType { fields } => {
+                    let depth = self.names.len();
+                    let fields = fields
+                        .iter()
+                        .map(|(name, ty)| {
+                            let ty = self.checked(ty, ANNOTATION);
+                            self.names.push(name.clone());
+                            format!("{name} : {ty}")
+                        })
+                        .collect::<Vec<_>>();
+                    self.names.truncate(depth);
+                    (format!("{{ {} }}", fields.join(", ")), ATOM)
+                }
+                Term::Proj { term, field } => (format!("{}.{field}", self.term(term, ATOM)), ATOM),
+                Term::IfElse { cond, conseq, alt } => (
+                    format!(
+                        "if {} then {} else {}",
+                        self.term(cond, ANNOTATION),
+                        self.term(conseq, ANNOTATION),
+                        self.term(alt, ANNOTATION)
+                    ),
+                    ANNOTATION,
+                ),
+                Term::Binary(_) | Term::Unary(_) => (format!("{:?}", term), ANNOTATION),
+            }
+        })
+    }
+
+    /// Prints `∀ (x : A), (y : B) . C`, merging the dependent binders that follow each other.
+    fn forall(&mut self, term: &Term) -> (String, u8) {
+        let depth = self.names.len();
+        let mut bindings = vec![];
+        let mut term = term.clone();
+
+        while let Term::DependentFunctionSpace { name, arg, ret } = &term {
+            if !occurs(ret, 0) {
+                break;
+            }
+
+            let name = self.name(name, ret, names_for(arg));
+            bindings.push(format!("({name} : {})", self.checked(arg, ANNOTATION)));
+            self.names.push(name);
+
+            term = match ret.as_ref() {
+                CheckableTerm::InfereableTerm { term } => (**term).clone(),
+                _ => {
+                    let ret = self.checked(ret, FORALL);
+                    self.names.truncate(depth);
+                    return (format!("∀ {} . {ret}", bindings.join(", ")), FORALL);
+                }
+            };
+        }
+
+        let ret = self.term(&term, FORALL);
+        self.names.truncate(depth);
+        (format!("∀ {} . {ret}", bindings.join(", ")), FORALL)
+    }
+
+    fn arm(&mut self, arm: &Clause) -> String {
+        let depth = self.names.len();
+        let arity = arm.patterns.iter().map(Pattern::arity).sum::<usize>();
+        let mut binders = vec![];
+        for pattern in &arm.patterns {
+            collect_binders(pattern, &mut binders);
+        }
+
+        // The variables are named from left to right, so that none hides another.
+        let mut names: Vec<String> = vec![];
+        for (i, binder) in binders.iter().enumerate() {
+            let taken = |name: &str| {
+                names.iter().any(|x| x == name)
+                    || binders[i + 1..]
+                        .iter()
+                        .any(|b| b.0.as_deref() == Some(name))
+            };
+            let name = match &binder.0 {
+                Some(name) if !taken(name) && !self.captures(name, &arm.body, arity) => {
+                    name.clone()
+                }
+                _ => self.fresh(TERM_NAMES, taken),
+            };
+            names.push(name);
+        }
+
+        let mut next = 0;
+        let patterns = arm
+            .patterns
+            .iter()
+            .map(|p| self.pattern(p, &arm.body, &names, &mut next, ATOM))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let body = self.checked(&arm.body, ANNOTATION);
+        self.names.truncate(depth);
+
+        format!("{patterns} -> {body}")
+    }
+
+    /// Prints a pattern and binds its variables to `names`; unused ones are printed as `_`.
+    fn pattern(
+        &mut self,
+        pattern: &Pattern,
+        body: &CheckableTerm,
+        names: &[String],
+        next: &mut usize,
+        level: u8,
+    ) -> String {
+        match pattern {
+            Pattern::Var(_) => {
+                let used = occurs(body, names.len() - *next - 1);
+                let name = names[*next].clone();
+                *next += 1;
+                self.names.push(name.clone());
+                match used {
+                    true => name,
+                    false => "_".to_string(),
+                }
+            }
+            Pattern::Zero => "O".to_string(),
+            Pattern::Succ(pred) => {
+                let pred = self.pattern(pred, body, names, next, ATOM);
+                paren(format!("S {pred}"), APP, level.max(APP))
+            }
+        }
+    }
+
+    fn checked_prec(&mut self, term: &CheckableTerm) -> (String, u8) {
+        stack::grow(move || {
+            if let Some(n) = num_checked(term) {
+                return (n.to_string(), ATOM);
+            }
+
+            match term {
+                CheckableTerm::InfereableTerm { term } => self.term_prec(term),
+                CheckableTerm::Lambda { name, term } => {
+                    let name = self.name(name, term, TERM_NAMES);
+                    let body = self.bind(name.clone(), |p| p.checked(term, LAMBDA));
+                    (format!("λ {name} -> {body}"), LAMBDA)
+                }
+                CheckableTerm::Succ { term } => (format!("S {}", self.checked(term, ATOM)), APP),
+                CheckableTerm::Zero => ("0".to_string(), ATOM),
+                CheckableTerm::Record { fields } => {
+                    let fields = fields
+                        .iter()
+                        .map(|(name, e)| format!("{name} = {}", self.checked(e, ANNOTATION)))
+                        .collect::<Vec<_>>();
+                    (format!("{{ {} }}", fields.join(", ")), ATOM)
+                }
+            }
+        })
+    }
+}
+
+/// The binders of the variables of `pattern`, from left to right.
+fn collect_binders<'a>(pattern: &'a Pattern, binders: &mut Vec<&'a Binder>) {
+    match pattern {
+        Pattern::Var(binder) => binders.push(binder),
+        Pattern::Zero => (),
+        Pattern::Succ(pred) => collect_binders(pred, binders),
+    }
+}
+
+fn paren(s: String, prec: u8, level: u8) -> String {
+    match prec > level {
+        true => format!("({s})"),
+        false => s,
+    }
+}
+
+fn globals(term: &CheckableTerm) -> HashSet<String> {
+    let mut res = HashSet::new();
+    visit_checked(term, 0, &mut |term, _| {
+        if let Term::Var(VariableName::Global(x)) = term {
+            res.insert(x.clone());
+        }
+    });
+    res
+}
+
+/// Pretty prints a term.
+pub fn pretty_term(term: &Term) -> String {
+    let term = CheckableTerm::InfereableTerm {
+        term: Node::new(term.clone()),
+    };
+    pretty_checkable(&term)
+}
+
+/// Pretty prints a checkable term.
+pub fn pretty_checkable(term: &CheckableTerm) -> String {
+    Printer::new(globals(term)).checked(term, ANNOTATION)
+}
+
+/// Reads a value back to print it, which does not count against the limits of the statement.
+fn quote(val: &Value) -> Result<CheckableTerm, String> {
+    fuel::unmetered(|| lift(0, val.clone())).map_err(|e| format!("<{e}>"))
+}
+
+/// Pretty prints a value by reading it back into a term.
+pub fn pretty(val: &Value) -> String {
+    quote(val).map_or_else(|e| e, |term| pretty_checkable(&term))
+}
+
+/// Pretty prints a value whose free local variables `Local(i)` are called `locals[i]`.
+pub fn pretty_with_locals(val: &Value, locals: &[String]) -> String {
+    let term = match quote(val) {
+        Ok(term) => term,
+        Err(e) => return e,
+    };
+    let mut printer = Printer::new(globals(&term));
+    printer.globals.extend(locals.iter().cloned());
+    printer.locals = locals.to_vec();
+    printer.checked(&term, ANNOTATION)
+}
+
+/// Pretty prints a term that is evaluated under `ctx`, with the values of its free bound variables
+/// in their places.
+pub fn pretty_in(term: &Term, ctx: &EvalCtx) -> String {
+    let term = CheckableTerm::InfereableTerm {
+        term: Node::new(term.clone()),
+    };
+    let vals = ctx.1.iter().map(quote).collect::<Vec<_>>();
+
+    let mut printer = Printer::new(globals(&term));
+    for val in vals.iter().flatten() {
+        printer.globals.extend(globals(val));
+    }
+    let free = vals
+        .iter()
+        .map(|val| match val {
+            Ok(val) => printer.checked_prec(val),
+            Err(e) => (e.clone(), ATOM),
+        })
+        .collect();
+    printer.free = free;
+    printer.checked(&term, ANNOTATION)
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", pretty_term(self))
+    }
+}
+
+impl fmt::Display for CheckableTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", pretty_checkable(self))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", pretty(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::TypeCtx,
+        parse::{handle_statement, CmdParser},
+        prelude::load_prelude,
+    };
+
+    fn show(ctx: &mut TypeCtx, input: &str) -> String {
+        let stmt = CmdParser::new().parse(input).unwrap();
+        handle_statement(stmt, ctx).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_pretty() {
+        let mut ctx = TypeCtx::new();
+        load_prelude(&mut ctx).unwrap();
+
+        let ty = |ctx: &TypeCtx, name: &str| {
+            let (_, ty) = ctx.globals().into_iter().find(|(x, _)| x == name).unwrap();
+            ty.to_string()
+        };
+
+        assert_eq!(ty(&ctx, "id"), "∀ (A : U) . A -> A");
+        assert_eq!(ty(&ctx, "add"), "ℕ -> ℕ -> ℕ");
+        assert_eq!(
+            ty(&ctx, "natElim"),
+            "∀ (P : ℕ -> U) . P 0 -> (∀ (k : ℕ) . P k -> P (S k)) -> (∀ (n : ℕ) . P n)"
+        );
+        assert_eq!(
+            ty(&ctx, "cong"),
+            "∀ (A : U), (B : U), (f : A -> B), (x : A), (y : A) . Eq A x y -> Eq B (f x) (f y)"
+        );
+        assert_eq!(show(&mut ctx, "eval id;"), "λ A -> λ x -> x");
+        // A name is only replaced if it would capture a variable.
+        assert_eq!(
+            show(&mut ctx, "let k := λ x -> λ x -> x :: ℕ -> ℕ -> ℕ;"),
+            "λ x -> λ x -> x"
+        );
+        assert_eq!(
+            show(
+                &mut ctx,
+                "let k' := λ x -> (λ y -> λ x -> y :: ℕ -> ℕ -> ℕ) x :: ℕ -> ℕ -> ℕ;"
+            ),
+            "λ x -> λ y -> x"
+        );
+        assert_eq!(show(&mut ctx, "eval (mul 2 3);"), "6");
+        assert_eq!(
+            show(
+                &mut ctx,
+                "let f := λ n -> case n of { O -> 1; S m -> S (S m) } :: ℕ -> ℕ;"
+            ),
+            "λ n -> case n of { O -> 1; S m -> S (S m) }"
+        );
+        assert_eq!(
+            show(
+                &mut ctx,
+                "record Pair (A : U) (B : U) := { fst : A, snd : B };"
+            ),
+            "λ A -> λ B -> { fst : A, snd : B }"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Records with dependent fields.
+//!
+//! A record type `{ x : A, y : B x }` is a telescope where each field can refer to the fields
+//! before it. Record literals `{ x = a, y = b }` are checked against such a type, and a record
+//! `r` is convertible with `{ x = r.x, y = r.y }` (eta).
+
+use crate::{
+    env::{Ctx, EvalCtx, TypeCtx},
+    err::{EvalError, EvalResult},
+    eval::{
+        eval, eval_checked, force, lift_neutral, read_back, sanity_check, subst_checked, type_check,
+    },
+    stack::Node,
+    term::{CheckableTerm, Neutral, Term, Type, Value, VariableName},
+};
+
+/// Projects `field` out of a record value.
+pub(crate) fn proj(val: Value, field: &str) -> EvalResult<Value> {
+    match val {
+        Value::VRecord(fields) => fields
+            .into_iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, val)| val)
+            .ok_or_else(|| EvalError::TypeMismatch(format!("Record has no field {field}"))),
+        Value::VNeutral(n) => Ok(Value::VNeutral(Neutral::NProj(
+            Node::new(n),
+            field.to_string(),
+        ))),
+        Value::VGlued { .. } | Value::VThunk(_) => proj(force(val)?, field),
+        _ => Err(EvalError::TypeMismatch(format!(
+            "Cannot project {field} out of a non-record value: {val:?}"
+        ))),
+    }
+}
+
+/// Evaluates the field types of a record type one after another. `value_of` gives the value of
+/// each field, which the following field types may depend on.
+pub(crate) fn field_types<F>(
+    fields: &[(String, CheckableTerm)],
+    ctx: &EvalCtx,
+    mut value_of: F,
+) -> EvalResult<Vec<(String, Type)>>
+where
+    F: FnMut(&str, &Type) -> EvalResult<Value>,
+{
+    let mut ctx = ctx.clone();
+    let mut res = vec![];
+
+    for (name, ty) in fields {
+        let ty = eval_checked(ty.clone(), ctx.clone())?;
+        ctx.1 = ctx.1.push(value_of(name, &ty)?);
+        res.push((name.clone(), ty));
+    }
+
+    Ok(res)
+}
+
+/// Lifts a record type back by introducing a fresh variable for each field.
+pub(crate) fn lift_record_type(
+    de_brujin_index: usize,
+    fields: &[(String, CheckableTerm)],
+    ctx: &EvalCtx,
+    unfold: bool,
+) -> EvalResult<Term> {
+    let mut next = de_brujin_index;
+    let types = field_types(fields, ctx, |_, _| {
+        next += 1;
+        Ok(Value::VNeutral(Neutral::NVar(VariableName::Quote(
+            next - 1,
+        ))))
+    })?;
+
+    Ok(Term::RecordType {
+        fields: types
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, ty))| Ok((name, read_back(de_brujin_index + i, ty, unfold)?)))
+            .collect::<EvalResult<_>>()?,
+    })
+}
+
+/// Returns `r` if the record is `{ x = r.x, y = r.y, ... }`.
+pub(crate) fn eta_contract(
+    de_brujin_index: usize,
+    fields: &[(String, Value)],
+) -> EvalResult<Option<Neutral>> {
+    let mut res: Option<Neutral> = None;
+
+    for (name, val) in fields {
+        let val = match val {
+            Value::VThunk(thunk) => thunk.force()?,
+            val => val.clone(),
+        };
+        match val {
+            Value::VNeutral(Neutral::NProj(n, field)) if field == *name => match &res {
+                None => res = Some(n.into_inner()),
+                Some(m) => {
+                    if lift_neutral(de_brujin_index, m.clone(), false)?
+                        != lift_neutral(de_brujin_index, n.into_inner(), false)?
+                    {
+                        return Ok(None);
+                    }
+                }
+            },
+            _ => return Ok(None),
+        }
+    }
+
+    Ok(res)
+}
+
+/// Type checks a record type; each field type is checked with the previous fields in scope.
+pub(crate) fn check_record_type(
+    de_brujin_index: usize,
+    fields: &[(String, CheckableTerm)],
+    mut ctx: TypeCtx,
+) -> EvalResult<Type> {
+    for (i, (_, ty)) in fields.iter().enumerate() {
+        // Replace the previous fields by their locals.
+        let ty = (0..i).fold(ty.clone(), |ty, j| {
+            subst_checked(
+                i - j - 1,
+                Term::Var(VariableName::Local(de_brujin_index + j)),
+                ty,
+            )
+        });
+
+        sanity_check(
+            de_brujin_index + i,
+            ty.clone(),
+            ctx.clone(),
+            Value::VUniverse,
+        )?;
+        let ty = eval_checked(ty, EvalCtx(ctx.0.clone(), Ctx::new()))?;
+        ctx.1 = ctx.1.push((VariableName::Local(de_brujin_index + i), ty));
+    }
+
+    Ok(Value::VUniverse)
+}
+
+/// Checks a record literal against `ty`.
+pub(crate) fn check_record(
+    de_brujin_index: usize,
+    fields: &[(String, CheckableTerm)],
+    ctx: TypeCtx,
+    ty: Type,
+) -> EvalResult<()> {
+    let (expected, rctx) = match force(ty.clone())? {
+        Value::VRecordType { fields, ctx } => (fields, ctx),
+        _ => {
+            return Err(EvalError::TypeMismatch(format!(
+                "Expected a record type, found {}",
+                ty
+            )))
+        }
+    };
+
+    if let Some((name, _)) = fields
+        .iter()
+        .find(|(name, _)| expected.iter().all(|(n, _)| n != name))
+    {
+        return Err(EvalError::TypeMismatch(format!(
+            "Record type has no field {name}"
+        )));
+    }
+
+    field_types(&expected, &rctx, |name, ty| {
+        let term = fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, term)| term.clone())
+            .ok_or_else(|| EvalError::TypeMismatch(format!("Missing field {name}")))?;
+
+        sanity_check(de_brujin_index, term.clone(), ctx.clone(), ty.clone())?;
+        eval_checked(term, EvalCtx(ctx.0.clone(), Ctx::new()))
+    })
+    .map(|_| ())
+}
+
+/// Infers the type of the projection `term.field`.
+pub(crate) fn infer_proj(
+    de_brujin_index: usize,
+    term: &Term,
+    field: &str,
+    ctx: TypeCtx,
+) -> EvalResult<Type> {
+    let ty = type_check(de_brujin_index, term.clone(), ctx.clone())?;
+
+    match force(ty.clone())? {
+        Value::VRecordType { fields, ctx: rctx } => {
+            let val = eval(term.clone(), EvalCtx(ctx.0.clone(), Ctx::new()))?;
+
+            field_types(&fields, &rctx, |name, _| proj(val.clone(), name))?
+                .into_iter()
+                .find(|(name, _)| name == field)
+                .map(|(_, ty)| ty)
+                .ok_or_else(|| EvalError::TypeMismatch(format!("Record has no field {field}")))
+        }
+        _ => Err(EvalError::TypeMismatch(format!(
+            "Cannot project {field} out of a term of type {}",
+            ty
+        ))),
+    }
+}
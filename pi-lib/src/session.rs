@@ -0,0 +1,340 @@
+//! Interactive sessions.
+//!
+//! A session remembers every step that changed the context, i.e., the definitions and the files
+//! it loaded, together with the context before each step, and the state before every change,
+//! e.g., a step or a redefinition. Since contexts share their tails, these snapshots are cheap,
+//! and they let us undo changes, redefine a name (checking everything that came after it again)
+//! and go back to named save points.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{
+    ast::Statement,
+    env::TypeCtx,
+    err::{EvalError, EvalResult},
+    parse::{handle_statement, load_file},
+    term::{Value, VariableName},
+};
+
+/// Something that changes the context.
+#[derive(Clone, Debug)]
+pub enum Action {
+    Statement(Statement),
+    Load(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    action: Action,
+    /// The context before the step.
+    before: TypeCtx,
+    /// The globals that the step defines.
+    defines: Vec<String>,
+}
+
+/// The state before a change, which undoing the change goes back to.
+#[derive(Clone, Debug)]
+struct Snapshot {
+    ctx: TypeCtx,
+    steps: Vec<Step>,
+    /// The globals that the change defined.
+    defines: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub ctx: TypeCtx,
+    /// The context the session started with, e.g., the prelude.
+    base: TypeCtx,
+    steps: Vec<Step>,
+    save_points: HashMap<String, (TypeCtx, Vec<Step>)>,
+    history: Vec<Snapshot>,
+}
+
+impl Action {
+    fn describe(&self) -> String {
+        match self {
+            Action::Statement(_) => "a statement".to_string(),
+            Action::Load(path) => format!("loading {}", path.display()),
+        }
+    }
+}
+
+/// The name that a statement defines, if it is a single definition.
+fn defined_name(stmt: &Statement) -> Option<&str> {
+    match stmt {
+        Statement::Declare(ident, _)
+        | Statement::Let(ident, _)
+        | Statement::Rec(ident, _, _)
+        | Statement::Record(ident, _, _) => Some(ident),
+        Statement::Private(stmt) | Statement::Documented(_, stmt) => defined_name(stmt),
+        _ => None,
+    }
+}
+
+/// The globals declared in `after` but not in `before`, which `after` extends.
+fn new_globals(before: &TypeCtx, after: &TypeCtx) -> Vec<String> {
+    let count = after.1.len() - before.1.len();
+    let mut res = after
+        .1
+        .iter()
+        .take(count)
+        .filter_map(|(name, _)| match name {
+            VariableName::Global(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    res.reverse();
+    res
+}
+
+impl Session {
+    pub fn new(ctx: TypeCtx) -> Self {
+        Self {
+            base: ctx.clone(),
+            ctx,
+            steps: vec![],
+            save_points: HashMap::new(),
+            history: vec![],
+        }
+    }
+
+    /// Runs a statement. Defining a name that an earlier step of the session defined replaces
+    /// that definition, and every step after it is checked again.
+    pub fn run(&mut self, stmt: Statement) -> EvalResult<Value> {
+        if matches!(stmt, Statement::Eval(_) | Statement::Check(_)) {
+            return handle_statement(stmt, &mut self.ctx);
+        }
+
+        let (ctx, steps) = (self.ctx.clone(), self.steps.clone());
+        let redefines = defined_name(&stmt)
+            .map(|ident| self.ctx.2.qualify(ident))
+            .and_then(|name| {
+                let i = self.steps.iter().rposition(|s| s.defines.contains(&name))?;
+                Some((i, name))
+            });
+
+        let value = match redefines {
+            Some((i, name)) => {
+                let value = self.redefine(i, stmt)?;
+                self.remember(ctx, steps, vec![name]);
+                value
+            }
+            None => {
+                let mut values = self.step(Action::Statement(stmt))?;
+                let defines = self.steps.last().map(|s| s.defines.clone());
+                self.remember(ctx, steps, defines.unwrap_or_default());
+                values.pop().unwrap_or(Value::VUniverse)
+            }
+        };
+        Ok(value)
+    }
+
+    /// Loads a file.
+    pub fn load(&mut self, path: PathBuf) -> EvalResult<Vec<Value>> {
+        let (ctx, steps) = (self.ctx.clone(), self.steps.clone());
+        let values = self.step(Action::Load(path))?;
+        let defines = self.steps.last().map(|s| s.defines.clone());
+        self.remember(ctx, steps, defines.unwrap_or_default());
+        Ok(values)
+    }
+
+    /// Records the state before a change so that it can be undone.
+    fn remember(&mut self, ctx: TypeCtx, steps: Vec<Step>, defines: Vec<String>) {
+        self.history.push(Snapshot {
+            ctx,
+            steps,
+            defines,
+        });
+    }
+
+    fn step(&mut self, action: Action) -> EvalResult<Vec<Value>> {
+        let before = self.ctx.clone();
+        let values = match &action {
+            Action::Statement(stmt) => vec![handle_statement(stmt.clone(), &mut self.ctx)?],
+            Action::Load(path) => load_file(path, &mut self.ctx)?,
+        };
+
+        self.steps.push(Step {
+            defines: new_globals(&before, &self.ctx),
+            action,
+            before,
+        });
+        Ok(values)
+    }
+
+    /// Replaces the definition made by the `i`-th step and replays the steps after it. If that
+    /// step loaded a file, the file is loaded again and the statement shadows the one definition,
+    /// so that the file's other definitions stay. If any step fails, nothing changes and the
+    /// error lists the steps.
+    fn redefine(&mut self, i: usize, stmt: Statement) -> EvalResult<Value> {
+        let (ctx, steps) = (self.ctx.clone(), self.steps.clone());
+        let mut later = self.steps.split_off(i).into_iter();
+        let old = later.next().expect("the step that is redefined");
+        self.ctx = old.before;
+
+        let res = match old.action {
+            Action::Load(_) => self.step(old.action).map(drop),
+            Action::Statement(_) => Ok(()),
+        }
+        .and_then(|_| self.step(Action::Statement(stmt)));
+        let mut failed = vec![];
+        if res.is_ok() {
+            for step in later {
+                if let Err(e) = self.step(step.action.clone()) {
+                    let what = match step.defines.is_empty() {
+                        true => step.action.describe(),
+                        false => step.defines.join(", "),
+                    };
+                    failed.push(format!("{what} ({e})"));
+                }
+            }
+        }
+
+        if res.is_err() || !failed.is_empty() {
+            self.ctx = ctx;
+            self.steps = steps;
+        }
+
+        match failed.is_empty() {
+            true => res.map(|mut values| values.pop().unwrap_or(Value::VUniverse)),
+            false => Err(EvalError::RedefinitionError(format!(
+                "the following definitions no longer type check: {}",
+                failed.join("; ")
+            ))),
+        }
+    }
+
+    /// Undoes the last change, i.e., a definition, redefinition, load, reload or restore, and
+    /// returns the globals it defined.
+    pub fn undo(&mut self) -> Option<Vec<String>> {
+        let snapshot = self.history.pop()?;
+        self.ctx = snapshot.ctx;
+        self.steps = snapshot.steps;
+        Some(snapshot.defines)
+    }
+
+    /// Starts over and replays every step, e.g., after the loaded files changed. Returns the
+    /// errors of the steps that failed, which are dropped.
+    pub fn reload(&mut self) -> Vec<EvalError> {
+        let (ctx, steps) = (self.ctx.clone(), std::mem::take(&mut self.steps));
+        self.ctx = self.base.clone();
+
+        let errors = steps
+            .iter()
+            .filter_map(|step| self.step(step.action.clone()).err())
+            .collect();
+        self.remember(ctx, steps, vec![]);
+        errors
+    }
+
+    pub fn save_point(&mut self, name: &str) {
+        self.save_points
+            .insert(name.to_string(), (self.ctx.clone(), self.steps.clone()));
+    }
+
+    pub fn restore(&mut self, name: &str) -> EvalResult<()> {
+        let (ctx, steps) = self
+            .save_points
+            .get(name)
+            .cloned()
+            .ok_or_else(|| EvalError::SessionError(format!("No save point named {name}")))?;
+
+        let ctx = std::mem::replace(&mut self.ctx, ctx);
+        let steps = std::mem::replace(&mut self.steps, steps);
+        self.remember(ctx, steps, vec![]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::TypeCtx,
+        err::{EvalError, EvalResult},
+        parse::CmdParser,
+        session::Session,
+        term::Value,
+    };
+
+    fn run(session: &mut Session, input: &str) -> EvalResult<Value> {
+        let stmt = CmdParser::new()
+            .parse(input)
+            .map_err(|e| EvalError::ParseError(e.to_string()))?;
+        session.run(stmt)
+    }
+
+    #[test]
+    fn test_undo_and_restore() {
+        let mut session = Session::new(TypeCtx::new());
+        run(&mut session, "def x :: ℕ;").unwrap();
+        session.save_point("start");
+        run(&mut session, "let y := S x;").unwrap();
+
+        assert_eq!(session.undo(), Some(vec!["y".to_string()]));
+        assert!(run(&mut session, "eval y;").is_err());
+
+        run(&mut session, "let z := x;").unwrap();
+        session.restore("start").unwrap();
+        assert!(run(&mut session, "eval z;").is_err());
+        assert!(run(&mut session, "eval x;").is_ok());
+        assert!(matches!(
+            session.restore("nowhere"),
+            Err(EvalError::SessionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_redefine() {
+        let mut session = Session::new(TypeCtx::new());
+        run(&mut session, "let x := 1;").unwrap();
+        run(&mut session, "let y := S x;").unwrap();
+
+        // `y` is checked again and sees the new `x`.
+        run(&mut session, "let x := 2;").unwrap();
+        assert_eq!(run(&mut session, "eval y;").unwrap().to_string(), "3");
+
+        // `y` does not type check with a type for `x`, so nothing changes.
+        let res = run(&mut session, "let x := ℕ;");
+        assert!(matches!(res, Err(EvalError::RedefinitionError(e)) if e.contains("y (")));
+        assert_eq!(run(&mut session, "eval y;").unwrap().to_string(), "3");
+    }
+
+    #[test]
+    fn test_undo_redefine() {
+        let mut session = Session::new(TypeCtx::new());
+        run(&mut session, "let x := 3;").unwrap();
+        run(&mut session, "let y := x;").unwrap();
+        run(&mut session, "let x := 5;").unwrap();
+        assert_eq!(run(&mut session, "eval y;").unwrap().to_string(), "5");
+
+        // Undoing the redefinition brings back the old `x` and keeps `y`.
+        assert_eq!(session.undo(), Some(vec!["x".to_string()]));
+        assert_eq!(run(&mut session, "eval x;").unwrap().to_string(), "3");
+        assert_eq!(run(&mut session, "eval y;").unwrap().to_string(), "3");
+
+        assert_eq!(session.undo(), Some(vec!["y".to_string()]));
+        assert!(run(&mut session, "eval y;").is_err());
+    }
+
+    #[test]
+    fn test_redefine_loaded() {
+        let mut session = Session::new(TypeCtx::new());
+        session.load("../test_file/check/ok.pi".into()).unwrap();
+        run(&mut session, "let four := S three;").unwrap();
+
+        // The file's other definitions stay.
+        run(&mut session, "let three := 5;").unwrap();
+        assert_eq!(run(&mut session, "eval two;").unwrap().to_string(), "2");
+        assert_eq!(run(&mut session, "eval four;").unwrap().to_string(), "6");
+
+        session.undo().unwrap();
+        assert_eq!(run(&mut session, "eval four;").unwrap().to_string(), "4");
+        assert_eq!(session.undo(), Some(vec!["four".to_string()]));
+        assert_eq!(
+            session.undo(),
+            Some(vec!["two".to_string(), "three".to_string()])
+        );
+        assert!(run(&mut session, "eval two;").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! Deep recursion without stack overflows.
+//!
+//! Terms and values are trees, and evaluation, checking, readback and substitution recurse on
+//! their subtrees, so a generated term that is deep enough would overflow the stack. These
+//! functions run their bodies through [`grow`], which moves the rest of the recursion onto a new
+//! segment on the heap whenever the current stack runs low; the depth of a term is then bounded by
+//! memory only.
+//!
+//! The children of syntax trees, terms and values are [`Node`]s rather than `Box`es, so that
+//! dropping, comparing, hashing and printing them, which the compiler derives as recursions too,
+//! are safe as well.
+
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::Arc,
+};
+
+/// The stack that is left before a new segment is needed; every recursive step must fit in it.
+const RED_ZONE: usize = 256 * 1024;
+/// The size of a new segment.
+const SEGMENT: usize = 4 * 1024 * 1024;
+
+/// Runs `f`, on a new stack segment if the current one is almost used up.
+#[inline]
+pub fn grow<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, SEGMENT, f)
+}
+
+/// A shared pointer to a subtree. Cloning a term only copies its root, so closures and contexts
+/// hold on to the bodies that they share rather than to copies of them. Dropping, comparing,
+/// hashing and printing a subtree run through [`grow`].
+pub struct Node<T>(Option<Arc<T>>);
+
+impl<T> Node<T> {
+    pub fn new(x: T) -> Self {
+        Self(Some(Arc::new(x)))
+    }
+}
+
+impl<T: Clone> Node<T> {
+    /// Moves the subtree out, or copies its root if it is shared.
+    pub fn into_inner(mut self) -> T {
+        Arc::unwrap_or_clone(self.0.take().unwrap())
+    }
+}
+
+impl<T> Deref for Node<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0.as_deref().unwrap()
+    }
+}
+
+impl<T> AsRef<T> for Node<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        if let Some(x) = self.0.take() {
+            grow(move || drop(x));
+        }
+    }
+}
+
+impl<T> Clone for Node<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: PartialEq> PartialEq for Node<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let same = matches!((&self.0, &other.0), (Some(x), Some(y)) if Arc::ptr_eq(x, y));
+        same || grow(|| T::eq(self, other))
+    }
+}
+
+impl<T: Eq> Eq for Node<T> {}
+
+impl<T: Hash> Hash for Node<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        grow(|| T::hash(self, state))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Node<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        grow(|| T::fmt(self, f))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Node<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        grow(|| T::serialize(self, serializer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Node;
+    use crate::{
+        env::{Ctx, TypeCtx},
+        parse::{handle_statement, CmdParser},
+        term::Term,
+    };
+
+    #[test]
+    fn test_deep() {
+        // Far deeper than the stack of a test thread allows for plain recursion.
+        let mut ctx = TypeCtx::new();
+        let res = ["let n := 100000 :: ℕ;", "eval (S n);"]
+            .map(|stmt| handle_statement(CmdParser::new().parse(stmt).unwrap(), &mut ctx).unwrap());
+        assert_eq!(res[1].to_string(), "100001");
+
+        // The syntax tree of a deep source is dropped, compared and resolved without recursion.
+        let depth = 30_000;
+        let source = format!(
+            "let m := {}O{} :: ℕ;",
+            "S (".repeat(depth),
+            ")".repeat(depth)
+        );
+        let stmt = CmdParser::new().parse(&source).unwrap();
+        assert_eq!(stmt, stmt.clone());
+        handle_statement(stmt, &mut ctx).unwrap();
+        assert_eq!(ctx.definition("m").unwrap().to_string(), "30000");
+
+        let mut long = Ctx::new();
+        for i in 0..1_000_000 {
+            long = long.push(i);
+        }
+        assert_eq!(long[999_999], 0);
+        assert_eq!(long.get(10), Some(&999_989));
+    }
+
+    #[test]
+    fn test_shared() {
+        let node = Node::new(Term::Succ {
+            pred: Node::new(Term::Zero),
+        });
+        let copy = node.clone();
+        assert!(std::ptr::eq(&*node, &*copy));
+
+        // Taking a shared subtree apart copies its root and leaves the other owner intact.
+        let Term::Succ { pred } = copy.into_inner() else {
+            unreachable!()
+        };
+        assert_eq!(*pred, Term::Zero);
+        assert!(matches!(&*node, Term::Succ { pred: p } if std::ptr::eq(&**p, &*pred)));
+    }
+}
@@ -1,18 +1,50 @@
 //! The module for expressions for the STLC.
 
-use std::fmt;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
 
-use crate::{clos::Closure, env::EvalCtx, eval::lift};
+use crate::{
+    ast::Span, clos::Closure, env::EvalCtx, eval::lift, fuel, pattern::Clause, stack::Node,
+    thunk::Thunk,
+};
 
 pub type Type = Value;
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum VariableName {
     Global(String),
     Local(usize),
     Quote(usize),
 }
 
+/// The name that a bound variable is written with, which is only kept to print the variable the
+/// same way. Terms are equal up to the names of their bound variables, so it is ignored by
+/// equality and hashing.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Binder(pub Option<String>);
+
+impl Binder {
+    pub fn new(name: &str) -> Self {
+        Self(Some(name.to_string()))
+    }
+}
+
+impl PartialEq for Binder {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Binder {}
+
+impl Hash for Binder {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
 /// This represents the term in our core lambda calculus.
 ///
 /// Note that since our type system is dependently typed, there is no longer a syntactic
@@ -30,12 +62,13 @@ pub enum VariableName {
 ///
 /// Now suppose we have `a: Vec (1 + 2 + 3) Nat` and `b: Vec 6 Nat`, to derive the type
 /// `a ≡ b` (requires `eq_rect`), we must allow computation to occur inside types.
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Term {
     /// x: ρ
     AnnotatedTerm {
-        term: Box<CheckableTerm>,
-        ty: Box<CheckableTerm>,
+        term: Node<CheckableTerm>,
+        ty: Node<CheckableTerm>,
     },
     /// Variable: `x`, `y`, `z`, etc. used to look up the evaluation environment.
     Var(VariableName),
@@ -43,23 +76,24 @@ pub enum Term {
     Bounded(usize),
     /// Application: `e1 e2`.
     App {
-        clos: Box<Term>,
-        arg: Box<CheckableTerm>,
+        clos: Node<Term>,
+        arg: Node<CheckableTerm>,
     },
     /// For example, polymorphism functions like `∀x:*. x -> x`
     /// or `∀(A: *). A -> A` must be declared this way.
     DependentFunctionSpace {
-        arg: Box<CheckableTerm>,
-        ret: Box<CheckableTerm>,
+        name: Binder,
+        arg: Node<CheckableTerm>,
+        ret: Node<CheckableTerm>,
     },
     // TODO: Determine the level of type universe?
     // This will happen if we try to incorporate type into types.
     Universe,
     /// Equivalent to `if e1 then e2 else e3`.
     IfElse {
-        cond: Box<Term>,
-        conseq: Box<Term>,
-        alt: Box<Term>,
+        cond: Node<Term>,
+        conseq: Node<Term>,
+        alt: Node<Term>,
     },
     /// Binary expression: `e1 + e2`, `e1 - e2`, etc.
     Binary(BinaryTerm),
@@ -71,92 +105,155 @@ pub enum Term {
     /// Literal zero.
     Zero,
     Succ {
-        pred: Box<Term>,
+        pred: Node<Term>,
+    },
+    /// Pattern matching: `case e of { p1 -> e1; ... }`.
+    Case {
+        scrutinee: Node<CheckableTerm>,
+        arms: Vec<Clause>,
+    },
+    /// Record types `{ x : A, y : B }` where each field is bound in the following ones.
+    RecordType {
+        fields: Vec<(String, CheckableTerm)>,
+    },
+    /// Projection: `e.x`.
+    Proj {
+        term: Node<Term>,
+        field: String,
+    },
+    /// A hole, with the names of the local variables around it.
+    Hole {
+        span: Span,
+        names: Vec<String>,
     },
 }
 
 /// Term↓
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum CheckableTerm {
-    InfereableTerm { term: Box<Term> },
-    Lambda { term: Box<CheckableTerm> },
-    Succ { term: Box<CheckableTerm> },
+    InfereableTerm {
+        term: Node<Term>,
+    },
+    Lambda {
+        name: Binder,
+        term: Node<CheckableTerm>,
+    },
+    Succ {
+        term: Node<CheckableTerm>,
+    },
     Zero,
+    /// Record literals `{ x = a, y = b }`, sorted by field names.
+    Record {
+        fields: Vec<(String, CheckableTerm)>,
+    },
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Value {
     VNeutral(Neutral),
-    VAbs(Box<Closure<Value, EvalCtx, Value>>),
+    VAbs(Box<Closure>),
     VUniverse,
     VPi {
-        val: Box<Value>,
-        body: Box<Closure<Value, EvalCtx, Value>>, // Box<dyn Callable<Value>>
+        val: Node<Value>,
+        body: Box<Closure>,
     },
     VZero,
     VSucc {
-        pred: Box<Value>,
+        pred: Node<Value>,
     },
     VNat,
+    /// A record type is a telescope: each field type is evaluated once the values of the
+    /// previous fields are known.
+    VRecordType {
+        fields: Vec<(String, CheckableTerm)>,
+        ctx: Box<EvalCtx>,
+    },
+    VRecord(Vec<(String, Value)>),
+    /// A global definition applied to arguments, e.g., `Vec ℕ 3`. It is read back as it is, and
+    /// only unfolded into `def` applied to the arguments once it is forced.
+    VGlued {
+        head: Node<Neutral>,
+        def: Node<Value>,
+    },
+    /// An argument whose evaluation is delayed under the lazy strategy.
+    VThunk(Box<Thunk>),
 }
 
 /// A neutral term is just a variable applied to a possibly empty sequence of values or
 /// is just a lambda abstraction. Neutral terms are good if we want to evalaute types on
 /// the fly.
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Neutral {
     NVar(VariableName),
-    NApp(Box<Neutral>, Box<Value>),
+    NApp(Node<Neutral>, Node<Value>),
+    NProj(Node<Neutral>, String),
+    /// The hole at `span`, which is stuck until it is filled. Holes are only convertible to
+    /// themselves.
+    NHole(Span),
+    /// A case expression whose scrutinee is not known enough to pick an arm.
+    NCase {
+        scrutinee: Node<Value>,
+        arms: Vec<Clause>,
+        ctx: Box<EvalCtx>,
+    },
 }
 
 /// Some trivial literal terms.
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum LitTerm {
     Int(i32),
     Bool(bool),
     Str(String),
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum UnaryTerm {
-    Not(Box<Term>),
-    Neg(Box<Term>),
+    Not(Node<Term>),
+    Neg(Node<Term>),
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BinaryTerm {
     Logical(BinaryLogicalExpr),
     Arith(BinaryArithmeticExpr),
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BinaryLogicalExpr {
     /// Addition: `e1 + e2`.
-    Add((Box<Term>, Box<Term>)),
+    Add((Node<Term>, Node<Term>)),
     /// Subtraction: `e1 - e2`.
-    Sub((Box<Term>, Box<Term>)),
+    Sub((Node<Term>, Node<Term>)),
     /// Multiplication: `e1 * e2`.
-    Mul((Box<Term>, Box<Term>)),
+    Mul((Node<Term>, Node<Term>)),
     /// Division: `e1 / e2`.
-    Div((Box<Term>, Box<Term>)),
+    Div((Node<Term>, Node<Term>)),
     /// Modulo: `e1 % e2`.
-    Mod((Box<Term>, Box<Term>)),
+    Mod((Node<Term>, Node<Term>)),
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BinaryArithmeticExpr {
     /// Less than: `e1 < e2`.
-    Lt((Box<Term>, Box<Term>)),
+    Lt((Node<Term>, Node<Term>)),
     /// Less than or equal to: `e1 <= e2`.
-    Le((Box<Term>, Box<Term>)),
+    Le((Node<Term>, Node<Term>)),
     /// Greater than: `e1 > e2`.
-    Gt((Box<Term>, Box<Term>)),
+    Gt((Node<Term>, Node<Term>)),
     /// Greater than or equal to: `e1 >= e2`.
-    Ge((Box<Term>, Box<Term>)),
+    Ge((Node<Term>, Node<Term>)),
     /// Equality: `e1 == e2`.
-    Eq((Box<Term>, Box<Term>)),
+    Eq((Node<Term>, Node<Term>)),
     /// Inequality: `e1 != e2`.
-    Ne((Box<Term>, Box<Term>)),
+    Ne((Node<Term>, Node<Term>)),
 }
 
 impl fmt::Debug for LitTerm {
@@ -217,7 +314,7 @@ impl fmt::Debug for Term {
         match self {
             Term::AnnotatedTerm { term, ty } => write!(f, "{:?} :: {:?}", term, ty),
             Term::App { clos, arg } => write!(f, "App ({:?})({:?})", clos, arg),
-            Term::DependentFunctionSpace { arg, ret } => write!(f, "∀ {:?} . {:?}", arg, ret),
+            Term::DependentFunctionSpace { arg, ret, .. } => write!(f, "∀ {:?} . {:?}", arg, ret),
             Term::Var(x) => write!(f, "{:?}", x),
             Term::Bounded(n) => write!(f, "_{}", n),
             Term::Universe => write!(f, "𝒰"),
@@ -229,6 +326,25 @@ impl fmt::Debug for Term {
             Term::Zero => write!(f, "O"),
             Term::Succ { pred } => write!(f, "S({:?})", pred),
             Term::Nat => write!(f, "ℕ"),
+            Term::Hole { .. } => write!(f, "?"),
+            Term::Case { scrutinee, arms } => {
+                write!(f, "case {:?} of {{ ", scrutinee)?;
+                for (i, arm) in arms.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{:?} -> {:?}", arm.patterns[0], arm.body)?;
+                }
+                write!(f, " }}")
+            }
+            Term::RecordType { fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, ty)| format!("{} : {:?}", name, ty))
+                    .collect::<Vec<_>>();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Term::Proj { term, field } => write!(f, "({:?}).{}", term, field),
         }
     }
 }
@@ -247,9 +363,16 @@ impl fmt::Debug for CheckableTerm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CheckableTerm::InfereableTerm { term } => write!(f, "{:?}", term),
-            CheckableTerm::Lambda { term } => write!(f, "λ . {:?}", term),
+            CheckableTerm::Lambda { term, .. } => write!(f, "λ . {:?}", term),
             CheckableTerm::Succ { term } => write!(f, "S({:?})", term),
             CheckableTerm::Zero => write!(f, "O"),
+            CheckableTerm::Record { fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, e)| format!("{} = {:?}", name, e))
+                    .collect::<Vec<_>>();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
         }
     }
 }
@@ -259,19 +382,24 @@ impl fmt::Debug for Neutral {
         match self {
             Neutral::NVar(x) => write!(f, "{:?}", x),
             Neutral::NApp(n, v) => write!(f, "App ({:?}) {:?}", n, v),
+            Neutral::NProj(n, field) => write!(f, "({:?}).{}", n, field),
+            Neutral::NHole(_) => write!(f, "?"),
+            Neutral::NCase { scrutinee, .. } => write!(f, "case {:?} of {{ .. }}", scrutinee),
         }
     }
 }
 
 impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let lifted = lift(0, self.clone());
-        write!(f, "{:?}", lifted)
+        match fuel::unmetered(|| lift(0, self.clone())) {
+            Ok(lifted) => write!(f, "{:?}", lifted),
+            Err(e) => write!(f, "<{e}>"),
+        }
     }
 }
 
 impl UnaryTerm {
-    pub fn extract_operand(&self) -> Box<Term> {
+    pub fn extract_operand(&self) -> Node<Term> {
         match self {
             UnaryTerm::Not(e) => e.clone(),
             UnaryTerm::Neg(e) => e.clone(),
@@ -280,7 +408,7 @@ impl UnaryTerm {
 }
 
 impl BinaryTerm {
-    pub fn extract_operands(&self) -> (Box<Term>, Box<Term>) {
+    pub fn extract_operands(&self) -> (Node<Term>, Node<Term>) {
         match self {
             BinaryTerm::Logical(e) => match e {
                 BinaryLogicalExpr::Add((e1, e2)) => (e1.clone(), e2.clone()),
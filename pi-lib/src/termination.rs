@@ -0,0 +1,227 @@
+//! A structural termination checker for recursive definitions.
+//!
+//! A definition is accepted if there is one argument position that decreases in every recursive
+//! call, i.e., the argument is a variable bound strictly inside the pattern at that position, or
+//! inside a case arm that matches on such a variable.
+//! Since values of `ℕ` are finite, such a definition can only unfold finitely many times.
+
+use crate::{
+    err::{EvalError, EvalResult},
+    fixpoint::Fixpoint,
+    pattern::{Clause, Pattern},
+    stack,
+    term::{CheckableTerm, Term, VariableName},
+};
+
+/// Collects the recursive calls of one clause together with the positions that decrease.
+///
+/// Variables are identified by their de Bruijn levels, i.e., the pattern variables of the clause
+/// are numbered from 0 and binders in the body get the following numbers.
+struct CallCollector<'a> {
+    name: &'a str,
+    arity: usize,
+    /// For each argument position, the variables that are structurally smaller than the argument.
+    smaller: Vec<Vec<usize>>,
+    /// For each argument position, the variable that is the argument itself, if any.
+    exact: Vec<Option<usize>>,
+    /// For each recursive call, whether each position decreases.
+    calls: Vec<(Term, Vec<bool>)>,
+}
+
+/// Numbers the variables bound by `pattern` from `next` on, returning those strictly below a
+/// constructor and the one that is the whole pattern.
+fn bind_pattern(pattern: &Pattern, next: &mut usize) -> (Vec<usize>, Option<usize>) {
+    fn go(pattern: &Pattern, next: &mut usize, below: &mut Vec<usize>) {
+        match pattern {
+            Pattern::Var(_) => {
+                below.push(*next);
+                *next += 1;
+            }
+            Pattern::Zero => (),
+            Pattern::Succ(pred) => go(pred, next, below),
+        }
+    }
+
+    match pattern {
+        Pattern::Var(_) => {
+            *next += 1;
+            (vec![], Some(*next - 1))
+        }
+        _ => {
+            let mut below = vec![];
+            go(pattern, next, &mut below);
+            (below, None)
+        }
+    }
+}
+
+impl<'a> CallCollector<'a> {
+    fn new(fix: &'a Fixpoint, clause: &Clause) -> Self {
+        let mut next = 0;
+        let (smaller, exact) = clause
+            .patterns
+            .iter()
+            .map(|p| bind_pattern(p, &mut next))
+            .unzip();
+
+        Self {
+            name: &fix.name,
+            arity: fix.arity,
+            smaller,
+            exact,
+            calls: vec![],
+        }
+    }
+
+    /// The level of the variable `arg` refers to under `depth` binders, if it is one.
+    fn level(arg: &CheckableTerm, depth: usize) -> Option<usize> {
+        let mut arg = arg;
+        loop {
+            match arg {
+                CheckableTerm::InfereableTerm { term } => match term.as_ref() {
+                    Term::AnnotatedTerm { term, .. } => arg = term,
+                    Term::Bounded(idx) if *idx < depth => return Some(depth - idx - 1),
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+    }
+
+    /// Whether `arg` is structurally smaller than the argument at position `pos`.
+    fn is_smaller(&self, arg: &CheckableTerm, pos: usize, depth: usize) -> bool {
+        Self::level(arg, depth).is_some_and(|level| self.smaller[pos].contains(&level))
+    }
+
+    /// Walks a case arm. Matching on a variable that is (smaller than) an argument makes the
+    /// variables below the constructor smaller than that argument.
+    fn walk_arm(&mut self, scrutinee: Option<usize>, arm: &Clause, depth: usize) {
+        let saved = self.smaller.clone();
+        let mut next = depth;
+        let (below, whole) = bind_pattern(&arm.patterns[0], &mut next);
+
+        if let Some(level) = scrutinee {
+            for pos in 0..self.arity {
+                if self.smaller[pos].contains(&level) {
+                    self.smaller[pos].extend(below.iter().chain(whole.iter()));
+                } else if self.exact[pos] == Some(level) {
+                    self.smaller[pos].extend(below.iter());
+                }
+            }
+        }
+
+        self.walk_checked(&arm.body, next);
+        self.smaller = saved;
+    }
+
+    fn is_self(&self, term: &Term) -> bool {
+        matches!(term, Term::Var(VariableName::Global(name)) if name == self.name)
+    }
+
+    fn walk_checked(&mut self, term: &CheckableTerm, depth: usize) {
+        stack::grow(move || match term {
+            CheckableTerm::InfereableTerm { term } => self.walk(term, depth),
+            CheckableTerm::Lambda { term, .. } => self.walk_checked(term, depth + 1),
+            CheckableTerm::Succ { term } => self.walk_checked(term, depth),
+            CheckableTerm::Zero => (),
+            CheckableTerm::Record { fields } => {
+                fields.iter().for_each(|(_, e)| self.walk_checked(e, depth))
+            }
+        })
+    }
+
+    fn walk(&mut self, term: &Term, depth: usize) {
+        stack::grow(move || match term {
+            Term::App { .. } => {
+                let mut head = term;
+                let mut args = vec![];
+                while let Term::App { clos, arg } = head {
+                    args.push(arg.as_ref());
+                    head = clos;
+                }
+                args.reverse();
+
+                match self.is_self(head) {
+                    true => {
+                        let decreasing = (0..self.arity)
+                            .map(|pos| pos < args.len() && self.is_smaller(args[pos], pos, depth))
+                            .collect();
+                        self.calls.push((term.clone(), decreasing));
+                    }
+                    false => self.walk(head, depth),
+                }
+
+                args.into_iter()
+                    .for_each(|arg| self.walk_checked(arg, depth));
+            }
+            // A bare occurrence cannot be checked, e.g., when the function is passed around.
+            Term::Var(_) if self.is_self(term) => {
+                self.calls.push((term.clone(), vec![false; self.arity]))
+            }
+            Term::AnnotatedTerm { term, ty } => {
+                self.walk_checked(term, depth);
+                self.walk_checked(ty, depth);
+            }
+            Term::DependentFunctionSpace { arg, ret, .. } => {
+                self.walk_checked(arg, depth);
+                self.walk_checked(ret, depth + 1);
+            }
+            Term::IfElse { cond, conseq, alt } => {
+                self.walk(cond, depth);
+                self.walk(conseq, depth);
+                self.walk(alt, depth);
+            }
+            Term::Binary(e) => {
+                let (lhs, rhs) = e.extract_operands();
+                self.walk(&lhs, depth);
+                self.walk(&rhs, depth);
+            }
+            Term::Unary(e) => self.walk(&e.extract_operand(), depth),
+            Term::Succ { pred } => self.walk(pred, depth),
+            Term::Case { scrutinee, arms } => {
+                self.walk_checked(scrutinee, depth);
+                let level = Self::level(scrutinee, depth);
+                arms.iter().for_each(|arm| self.walk_arm(level, arm, depth));
+            }
+            Term::RecordType { fields } => fields
+                .iter()
+                .enumerate()
+                .for_each(|(i, (_, ty))| self.walk_checked(ty, depth + i)),
+            Term::Proj { term, .. } => self.walk(term, depth),
+            Term::Var(_)
+            | Term::Bounded(_)
+            | Term::Universe
+            | Term::Nat
+            | Term::Zero
+            | Term::Hole { .. } => (),
+        })
+    }
+}
+
+/// Checks that `fix` is structurally recursive.
+pub fn check_termination(fix: &Fixpoint) -> EvalResult<()> {
+    let calls = fix
+        .clauses
+        .iter()
+        .flat_map(|clause| {
+            let mut collector = CallCollector::new(fix, clause);
+            let depth = clause.patterns.iter().map(Pattern::arity).sum();
+            collector.walk_checked(&clause.body, depth);
+            collector.calls
+        })
+        .collect::<Vec<_>>();
+
+    if (0..fix.arity).any(|pos| calls.iter().all(|(_, decreasing)| decreasing[pos])) {
+        return Ok(());
+    }
+
+    let offending = calls
+        .iter()
+        .map(|(call, _)| format!("{:?}", call))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(EvalError::TerminationError(format!(
+        "{} is not structurally recursive: no argument decreases in every recursive call ({})",
+        fix.name, offending
+    )))
+}
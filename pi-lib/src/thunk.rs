@@ -0,0 +1,248 @@
+//! Call-by-need evaluation.
+//!
+//! Under the [`Strategy::Lazy`] strategy, the argument of an application is not evaluated before
+//! the function is applied but delayed in a [`Thunk`], which is evaluated the first time that its
+//! value is inspected and then remembers it. Arguments that a function never looks at, e.g., the
+//! witnesses passed to a proof, are then never computed.
+
+use std::{
+    cell::Cell,
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+
+use crate::{
+    env::EvalCtx,
+    err::EvalResult,
+    eval::eval_checked,
+    stack::Node,
+    term::{CheckableTerm, Value},
+};
+
+/// How the arguments of applications are evaluated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Arguments are evaluated before the function is applied (call-by-value).
+    #[default]
+    Strict,
+    /// Arguments are evaluated once they are needed, and at most once (call-by-need).
+    Lazy,
+}
+
+thread_local! {
+    /// The strategy of the evaluations on this thread.
+    static STRATEGY: Cell<Strategy> = const { Cell::new(Strategy::Strict) };
+}
+
+/// The strategy of the evaluations on this thread.
+pub fn strategy() -> Strategy {
+    STRATEGY.with(Cell::get)
+}
+
+/// Runs `f` with `strategy`, e.g., for a whole run of the interpreter.
+pub fn with_strategy<T>(strategy: Strategy, f: impl FnOnce() -> T) -> T {
+    /// Restores the outer strategy, even if `f` panics.
+    struct Restore(Strategy);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            STRATEGY.with(|s| s.set(self.0));
+        }
+    }
+
+    let _restore = Restore(STRATEGY.with(|s| s.replace(strategy)));
+    f()
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Strategy::Strict),
+            "lazy" => Ok(Strategy::Lazy),
+            _ => Err(format!(
+                "Unknown strategy {s}; expected `strict` or `lazy`."
+            )),
+        }
+    }
+}
+
+/// A term whose evaluation under `ctx` is delayed. Clones of a thunk share its value, so it is
+/// evaluated at most once.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Thunk {
+    pub term: Node<CheckableTerm>,
+    pub ctx: EvalCtx,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    val: Arc<OnceLock<Value>>,
+}
+
+impl Thunk {
+    pub fn new(term: Node<CheckableTerm>, ctx: EvalCtx) -> Self {
+        Self {
+            term,
+            ctx,
+            val: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// The value of the term, which is never a thunk itself.
+    pub fn force(&self) -> EvalResult<Value> {
+        if let Some(val) = self.val.get() {
+            return Ok(val.clone());
+        }
+
+        let mut val = eval_checked((*self.term).clone(), self.ctx.clone())?;
+        while let Value::VThunk(thunk) = val {
+            val = thunk.force()?;
+        }
+
+        Ok(self.val.get_or_init(|| val).clone())
+    }
+}
+
+// A thunk is the same as another one if it delays the same term in the same context, whether
+// either has been forced or not.
+impl PartialEq for Thunk {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.val, &other.val) || (self.term == other.term && self.ctx == other.ctx)
+    }
+}
+
+impl Eq for Thunk {}
+
+impl Hash for Thunk {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.term.hash(state);
+        self.ctx.hash(state);
+    }
+}
+
+impl fmt::Debug for Thunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.val.get() {
+            Some(val) => write!(f, "{:?}", val),
+            None => write!(f, "delayed {:?} with {:?}", self.term, self.ctx.1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::{strategy, with_strategy, Strategy, Thunk};
+    use crate::{
+        env::TypeCtx,
+        err::EvalError,
+        eval::eval,
+        parse::{handle_statement, CmdParser},
+        pattern::{Clause, Pattern},
+        prelude::load_prelude,
+        stack::Node,
+        term::{Binder, CheckableTerm, Term, Value},
+    };
+
+    #[test]
+    fn test_lazy_app() {
+        // (λ x -> O) (S _5), whose argument cannot be evaluated.
+        let term = Term::App {
+            clos: Node::new(Term::AnnotatedTerm {
+                term: Node::new(CheckableTerm::Lambda {
+                    name: Binder::new("x"),
+                    term: Node::new(CheckableTerm::Zero),
+                }),
+                ty: Node::new(CheckableTerm::Zero),
+            }),
+            arg: Node::new(CheckableTerm::InfereableTerm {
+                term: Node::new(Term::Succ {
+                    pred: Node::new(Term::Bounded(5)),
+                }),
+            }),
+        };
+
+        let strict = with_strategy(Strategy::Strict, || eval(term.clone(), Default::default()));
+        let lazy = with_strategy(Strategy::Lazy, || eval(term, Default::default()));
+        assert!(matches!(strict, Err(EvalError::UnboundVariable(_))));
+        assert!(matches!(lazy, Ok(Value::VZero)));
+    }
+
+    #[test]
+    fn test_forced_errors() {
+        // (λ x -> case x of { O -> O; S k -> O }) (S _5), whose scrutinee cannot be evaluated.
+        let case = Term::Case {
+            scrutinee: Node::new(CheckableTerm::InfereableTerm {
+                term: Node::new(Term::Bounded(0)),
+            }),
+            arms: vec![
+                Clause {
+                    patterns: vec![Pattern::Zero],
+                    body: CheckableTerm::Zero,
+                },
+                Clause {
+                    patterns: vec![Pattern::Succ(Box::new(Pattern::Var(Binder::new("k"))))],
+                    body: CheckableTerm::Zero,
+                },
+            ],
+        };
+        let term = Term::App {
+            clos: Node::new(Term::AnnotatedTerm {
+                term: Node::new(CheckableTerm::Lambda {
+                    name: Binder::new("x"),
+                    term: Node::new(CheckableTerm::InfereableTerm {
+                        term: Node::new(case),
+                    }),
+                }),
+                ty: Node::new(CheckableTerm::Zero),
+            }),
+            arg: Node::new(CheckableTerm::InfereableTerm {
+                term: Node::new(Term::Succ {
+                    pred: Node::new(Term::Bounded(5)),
+                }),
+            }),
+        };
+
+        // The error is reported rather than leaving the case stuck.
+        let lazy = with_strategy(Strategy::Lazy, || eval(term, Default::default()));
+        assert!(matches!(lazy, Err(EvalError::UnboundVariable(_))));
+    }
+
+    #[test]
+    fn test_strategy_restored() {
+        let res = panic::catch_unwind(|| with_strategy(Strategy::Lazy, || panic!("stopped")));
+        assert!(res.is_err());
+        assert_eq!(strategy(), Strategy::Strict);
+    }
+
+    #[test]
+    fn test_memoised() {
+        let thunk = Thunk::new(Node::new(CheckableTerm::Zero), Default::default());
+        let shared = thunk.clone();
+
+        assert!(matches!(shared.force(), Ok(Value::VZero)));
+        assert!(thunk.val.get().is_some());
+    }
+
+    #[test]
+    fn test_lazy_prelude() {
+        let mut ctx = TypeCtx::new();
+        load_prelude(&mut ctx).unwrap();
+
+        let mut run = |input: &str| {
+            let stmt = CmdParser::new()
+                .parse(input)
+                .map_err(|e| EvalError::ParseError(e.to_string()))?;
+            with_strategy(Strategy::Lazy, || handle_statement(stmt, &mut ctx))
+                .map(|v| format!("{v:?}"))
+        };
+
+        assert_eq!(run("eval (mul 2 3);").unwrap(), "S(S(S(S(S(S(O))))))");
+        assert_eq!(run("eval (const ℕ ℕ 1 (mul 9 9));").unwrap(), "S(O)");
+        assert!(run("let p := refl ℕ 4 :: Eq ℕ (add 2 2) 4;").is_ok());
+        assert!(run("let q := refl ℕ 5 :: Eq ℕ (add 2 2) 5;").is_err());
+    }
+}
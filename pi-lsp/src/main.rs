@@ -0,0 +1,26 @@
+//! A language server for `.pi` files that speaks LSP over stdio.
+
+mod position;
+mod server;
+
+use anyhow::Result;
+use lsp_server::Connection;
+use pi_lib::{env::TypeCtx, prelude::load_prelude};
+
+fn main() -> Result<()> {
+    // Stdout is for the protocol, so the logs go to stderr.
+    env_logger::builder()
+        .format_timestamp(None)
+        .format_target(false)
+        .init();
+
+    let mut ctx = TypeCtx::new();
+    load_prelude(&mut ctx)?;
+
+    let (connection, io_threads) = Connection::stdio();
+    server::run(&connection, ctx, server::default_limits())?;
+    drop(connection);
+    io_threads.join()?;
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! Conversions between byte offsets and LSP positions, whose columns count UTF-16 code units.
+
+use lsp_types::{Position, Range};
+use pi_lib::ast::Span;
+
+/// The byte offset of `pos` in `source`, clamped to the end of its line.
+pub fn to_offset(source: &str, pos: Position) -> usize {
+    let start = source
+        .split_inclusive('\n')
+        .take(pos.line as usize)
+        .map(str::len)
+        .sum::<usize>();
+    let line = source[start..].split('\n').next().unwrap_or("");
+
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= pos.character as usize {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    start + line.len()
+}
+
+pub fn to_position(source: &str, offset: usize) -> Position {
+    let before = &source[..offset.min(source.len())];
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[start..].encode_utf16().count() as u32,
+    }
+}
+
+pub fn to_range(source: &str, span: Span) -> Range {
+    Range {
+        start: to_position(source, span.start),
+        end: to_position(source, span.end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Position;
+
+    use super::{to_offset, to_position};
+
+    #[test]
+    fn test_position() {
+        let source = "def x :: ℕ;\nlet 𝑦 := x;\n";
+        for offset in [0, 4, source.find(';').unwrap(), source.find(":=").unwrap()] {
+            assert_eq!(to_offset(source, to_position(source, offset)), offset);
+        }
+
+        // `𝑦` is two UTF-16 code units.
+        let pos = to_position(source, source.find(":=").unwrap());
+        assert_eq!(pos, Position::new(1, 7));
+        assert_eq!(
+            to_offset(source, Position::new(0, 100)),
+            source.find('\n').unwrap()
+        );
+    }
+}
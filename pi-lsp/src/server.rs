@@ -0,0 +1,648 @@
+//! The language server: it keeps a checked [`Document`] for every open file.
+
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{anyhow, Result};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+        Notification as _, PublishDiagnostics,
+    },
+    request::{
+        CodeActionRequest, Completion, DocumentSymbolRequest, ExecuteCommand, GotoDefinition,
+        HoverRequest, Request as _,
+    },
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, CodeActionResponse, CompletionItem, CompletionItemKind,
+    CompletionOptions, CompletionParams, CompletionResponse, Diagnostic, DiagnosticSeverity,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DidSaveTextDocumentParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    ExecuteCommandOptions, ExecuteCommandParams, GotoDefinitionParams, GotoDefinitionResponse,
+    Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent,
+    MarkupKind, OneOf, Position, PublishDiagnosticsParams, Range, SaveOptions, ServerCapabilities,
+    SymbolKind, TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, TextEdit, Url, WorkspaceEdit,
+};
+use pi_lib::{
+    ast::Span,
+    document::{Document, Symbol},
+    env::TypeCtx,
+    eval::force,
+    fuel::{set_limits, Limits},
+    hole::{self, Goal},
+    parse::KEYWORDS,
+    term::Value as PiValue,
+};
+use serde_json::Value;
+
+use crate::position::{to_offset, to_range};
+
+/// The commands on holes. They take the URI of the document, the position of the hole and, for
+/// `pi.give` and `pi.refine`, a term or a function. They return the edit to apply.
+pub const GIVE: &str = "pi.give";
+pub const REFINE: &str = "pi.refine";
+pub const CASE_SPLIT: &str = "pi.caseSplit";
+pub const INTRO: &str = "pi.intro";
+
+/// How long checking a statement may take by default before it is reported as an error, so that
+/// a statement that does not terminate does not hang the server.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn default_limits() -> Limits {
+    Limits {
+        timeout: Some(TIMEOUT),
+        ..Default::default()
+    }
+}
+
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                    include_text: Some(true),
+                })),
+                ..Default::default()
+            },
+        )),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: [GIVE, REFINE, CASE_SPLIT, INTRO].map(String::from).to_vec(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+struct Server {
+    /// The context every document is checked in, e.g., the prelude.
+    base: TypeCtx,
+    documents: HashMap<Url, Document>,
+}
+
+/// Answers the client on `connection` until it shuts the server down. Every statement of a
+/// document is checked within `limits`.
+pub fn run(connection: &Connection, base: TypeCtx, limits: Limits) -> Result<()> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    set_limits(limits);
+
+    let mut server = Server {
+        base,
+        documents: HashMap::new(),
+    };
+
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                connection
+                    .sender
+                    .send(Message::Response(server.request(req)))?;
+            }
+            Message::Notification(not) => {
+                let method = not.method.clone();
+                match server.notification(not) {
+                    Ok(Some(params)) => {
+                        let not = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+                        connection.sender.send(Message::Notification(not))?;
+                    }
+                    Ok(None) => (),
+                    // The client is not told about failed notifications, but we go on.
+                    Err(e) => log::error!("Cannot handle {method}: {e}"),
+                }
+            }
+            Message::Response(_) => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn symbol_kind(kind: &str) -> SymbolKind {
+    match kind {
+        "def" => SymbolKind::CONSTANT,
+        "let" => SymbolKind::VARIABLE,
+        "rec" => SymbolKind::FUNCTION,
+        "record" => SymbolKind::STRUCT,
+        _ => SymbolKind::MODULE,
+    }
+}
+
+/// The edit that turns `old` into `new`, which only replaces what differs.
+fn edit(uri: Url, old: &str, new: &str) -> WorkspaceEdit {
+    let prefix = old
+        .char_indices()
+        .zip(new.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(old.len().min(new.len()), |((i, _), _)| i);
+    let suffix = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum::<usize>();
+
+    let span = Span {
+        start: prefix,
+        end: old.len() - suffix,
+    };
+    let text = new[prefix..new.len() - suffix].to_string();
+    WorkspaceEdit::new(HashMap::from([(
+        uri,
+        vec![TextEdit::new(to_range(old, span), text)],
+    )]))
+}
+
+impl Server {
+    fn request(&self, req: Request) -> Response {
+        let res = match req.method.as_str() {
+            HoverRequest::METHOD => self.handle(req.params, Self::hover),
+            GotoDefinition::METHOD => self.handle(req.params, Self::definition),
+            DocumentSymbolRequest::METHOD => self.handle(req.params, Self::symbols),
+            Completion::METHOD => self.handle(req.params, Self::completion),
+            CodeActionRequest::METHOD => self.handle(req.params, Self::code_actions),
+            ExecuteCommand::METHOD => serde_json::from_value(req.params)
+                .map_err(Into::into)
+                .and_then(|params| Ok(serde_json::to_value(self.execute_command(params)?)?)),
+            method => {
+                return Response::new_err(
+                    req.id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("Unsupported request {method}"),
+                )
+            }
+        };
+
+        match res {
+            Ok(res) => Response::new_ok(req.id, res),
+            Err(e) => Response::new_err(req.id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    fn handle<P, R>(&self, params: Value, f: fn(&Self, P) -> Option<R>) -> Result<Value>
+    where
+        P: serde::de::DeserializeOwned,
+        R: serde::Serialize,
+    {
+        Ok(serde_json::to_value(f(
+            self,
+            serde_json::from_value(params)?,
+        ))?)
+    }
+
+    /// Checks the documents that changed and returns their diagnostics.
+    fn notification(&mut self, not: Notification) -> Result<Option<PublishDiagnosticsParams>> {
+        let (uri, text) = match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(not.params)?;
+                let doc = params.text_document;
+                let path = doc.uri.to_file_path().ok();
+                self.documents
+                    .insert(doc.uri.clone(), Document::new(path, self.base.clone()));
+                (doc.uri, Some(doc.text))
+            }
+            DidChangeTextDocument::METHOD => {
+                let mut params: DidChangeTextDocumentParams = serde_json::from_value(not.params)?;
+                // We only ask for whole documents.
+                let text = params.content_changes.pop().map(|change| change.text);
+                (params.text_document.uri, text)
+            }
+            DidSaveTextDocument::METHOD => {
+                let params: DidSaveTextDocumentParams = serde_json::from_value(not.params)?;
+                (params.text_document.uri, params.text)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(not.params)?;
+                self.documents.remove(&params.text_document.uri);
+                return Ok(Some(PublishDiagnosticsParams::new(
+                    params.text_document.uri,
+                    vec![],
+                    None,
+                )));
+            }
+            _ => return Ok(None),
+        };
+
+        let Some(doc) = self.documents.get_mut(&uri) else {
+            return Ok(None);
+        };
+        if let Some(text) = text {
+            let count = doc.update(text);
+            log::debug!("Checked {count} statement(s) of {uri}");
+        }
+
+        let errors = doc.statements().filter_map(|stmt| {
+            let e = stmt.error.as_ref()?;
+            Some(Diagnostic {
+                range: stmt
+                    .span
+                    .map_or(Range::default(), |span| to_range(doc.source(), span)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("pi".to_string()),
+                message: e.to_string(),
+                ..Default::default()
+            })
+        });
+        let goals = doc
+            .statements()
+            .flat_map(|stmt| &stmt.goals)
+            .map(|goal| Diagnostic {
+                range: to_range(doc.source(), goal.span),
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                source: Some("pi".to_string()),
+                message: goal.to_string(),
+                ..Default::default()
+            });
+        let diagnostics = errors.chain(goals).collect();
+
+        Ok(Some(PublishDiagnosticsParams::new(uri, diagnostics, None)))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let pos = params.text_document_position_params;
+        let doc = self.documents.get(&pos.text_document.uri)?;
+        let (name, ty, span) = doc.hover(to_offset(doc.source(), pos.position))?;
+        let value = match doc.ctx().doc(&name) {
+            Some(comment) => format!("```\n{name} : {ty}\n```\n\n{comment}"),
+            None => format!("```\n{name} : {ty}\n```"),
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(to_range(doc.source(), span)),
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let pos = params.text_document_position_params;
+        let doc = self.documents.get(&pos.text_document.uri)?;
+        let def = doc.definition(to_offset(doc.source(), pos.position))?;
+
+        Some(GotoDefinitionResponse::Scalar(Location {
+            uri: pos.text_document.uri,
+            range: to_range(doc.source(), def.name_span),
+        }))
+    }
+
+    fn symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let doc = self.documents.get(&params.text_document.uri)?;
+
+        #[allow(deprecated)]
+        fn convert(doc: &Document, symbol: Symbol) -> DocumentSymbol {
+            DocumentSymbol {
+                detail: doc.ctx().type_of(&symbol.name).map(|ty| ty.to_string()),
+                kind: symbol_kind(symbol.kind),
+                tags: None,
+                deprecated: None,
+                range: to_range(doc.source(), symbol.span),
+                selection_range: to_range(doc.source(), symbol.name_span),
+                children: Some(
+                    symbol
+                        .children
+                        .into_iter()
+                        .map(|s| convert(doc, s))
+                        .collect(),
+                ),
+                name: symbol.name,
+            }
+        }
+
+        Some(DocumentSymbolResponse::Nested(
+            doc.symbols().into_iter().map(|s| convert(doc, s)).collect(),
+        ))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let doc = self
+            .documents
+            .get(&params.text_document_position.text_document.uri)?;
+
+        let names = doc
+            .ctx()
+            .globals()
+            .into_iter()
+            .map(|(name, ty)| CompletionItem {
+                label: name,
+                kind: Some(CompletionItemKind::VARIABLE),
+                detail: Some(ty.to_string()),
+                ..Default::default()
+            });
+        let keywords = KEYWORDS.iter().map(|kw| CompletionItem {
+            label: kw.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..Default::default()
+        });
+
+        Some(CompletionResponse::Array(names.chain(keywords).collect()))
+    }
+
+    /// The goal of the hole at `pos`.
+    fn goal_at(&self, uri: &Url, pos: Position) -> Option<(&Document, usize, &Goal)> {
+        let doc = self.documents.get(uri)?;
+        let offset = to_offset(doc.source(), pos);
+        let goal = doc
+            .statements()
+            .flat_map(|stmt| &stmt.goals)
+            .find(|goal| goal.span.start <= offset && offset <= goal.span.end)?;
+
+        Some((doc, offset, goal))
+    }
+
+    /// Introducing lambdas and splitting on the natural numbers in scope at a hole.
+    fn code_actions(&self, params: CodeActionParams) -> Option<CodeActionResponse> {
+        let uri = params.text_document.uri;
+        let (doc, offset, goal) = self.goal_at(&uri, params.range.start)?;
+        let (source, ctx) = (doc.source(), doc.base());
+
+        let intro = (
+            "Introduce lambdas".to_string(),
+            hole::intro(source, &ctx, offset),
+        );
+        let splits = goal
+            .locals
+            .iter()
+            .filter(|(_, ty)| matches!(force(ty.clone()), Ok(PiValue::VNat)))
+            .map(|(x, _)| {
+                (
+                    format!("Split on {x}"),
+                    hole::case_split(source, &ctx, offset, x),
+                )
+            });
+
+        Some(
+            std::iter::once(intro)
+                .chain(splits)
+                .filter_map(|(title, res)| {
+                    Some(CodeActionOrCommand::CodeAction(CodeAction {
+                        title,
+                        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                        edit: Some(edit(uri.clone(), source, &res.ok()?)),
+                        ..Default::default()
+                    }))
+                })
+                .collect(),
+        )
+    }
+
+    fn execute_command(&self, params: ExecuteCommandParams) -> Result<WorkspaceEdit> {
+        let mut args = params.arguments.into_iter();
+        let (uri, pos): (Url, Position) = match (args.next(), args.next()) {
+            (Some(uri), Some(pos)) => (serde_json::from_value(uri)?, serde_json::from_value(pos)?),
+            _ => return Err(anyhow!("Expected a document and a position")),
+        };
+        let text = args.next().and_then(|text| text.as_str().map(String::from));
+
+        let doc = self
+            .documents
+            .get(&uri)
+            .ok_or_else(|| anyhow!("{uri} is not open"))?;
+        let (source, ctx) = (doc.source(), doc.base());
+        let offset = to_offset(source, pos);
+
+        let res = match (params.command.as_str(), text) {
+            (GIVE, Some(term)) => hole::give(source, &ctx, offset, &term),
+            (REFINE, Some(f)) => hole::refine(source, &ctx, offset, &f),
+            (CASE_SPLIT, Some(x)) => hole::case_split(source, &ctx, offset, &x),
+            (INTRO, _) => hole::intro(source, &ctx, offset),
+            (command @ (GIVE | REFINE | CASE_SPLIT), None) => {
+                return Err(anyhow!("{command} needs a third argument"))
+            }
+            (command, _) => return Err(anyhow!("Unsupported command {command}")),
+        }?;
+
+        Ok(edit(uri, source, &res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use lsp_server::{Connection, Message, Notification, Request, RequestId};
+    use lsp_types::{
+        notification::{
+            DidChangeTextDocument, DidOpenTextDocument, Exit, Initialized, Notification as _,
+            PublishDiagnostics,
+        },
+        request::{
+            CodeActionRequest, Completion, DocumentSymbolRequest, ExecuteCommand, GotoDefinition,
+            HoverRequest, Initialize, Request as _, Shutdown,
+        },
+        CodeActionOrCommand, CodeActionResponse, CompletionResponse, DiagnosticSeverity,
+        DocumentSymbolResponse, GotoDefinitionResponse, Hover, HoverContents, InitializeParams,
+        Position, PublishDiagnosticsParams, Url, WorkspaceEdit,
+    };
+    use pi_lib::{env::TypeCtx, fuel::Limits};
+    use serde_json::{json, Value};
+
+    use super::{run, GIVE};
+
+    /// A client that talks to a server in another thread.
+    struct Client {
+        connection: Connection,
+        id: i32,
+    }
+
+    impl Client {
+        fn request(&mut self, method: &str, params: Value) -> Value {
+            self.id += 1;
+            let req = Request::new(RequestId::from(self.id), method.to_string(), params);
+            self.connection.sender.send(req.into()).unwrap();
+
+            match self.connection.receiver.recv().unwrap() {
+                Message::Response(resp) => resp.result.unwrap_or_default(),
+                msg => panic!("unexpected message {msg:?}"),
+            }
+        }
+
+        fn notify(&self, method: &str, params: Value) {
+            let not = Notification::new(method.to_string(), params);
+            self.connection.sender.send(not.into()).unwrap();
+        }
+
+        fn diagnostics(&self) -> PublishDiagnosticsParams {
+            match self.connection.receiver.recv().unwrap() {
+                Message::Notification(not) if not.method == PublishDiagnostics::METHOD => {
+                    serde_json::from_value(not.params).unwrap()
+                }
+                msg => panic!("unexpected message {msg:?}"),
+            }
+        }
+    }
+
+    impl Client {
+        fn start() -> (Self, thread::JoinHandle<()>) {
+            Self::start_with(Limits::default())
+        }
+
+        fn start_with(limits: Limits) -> (Self, thread::JoinHandle<()>) {
+            let (server, connection) = Connection::memory();
+            let handle = thread::spawn(move || run(&server, TypeCtx::new(), limits).unwrap());
+            let mut client = Client { connection, id: 0 };
+
+            let params = serde_json::to_value(InitializeParams::default()).unwrap();
+            assert!(
+                client.request(Initialize::METHOD, params)["capabilities"]["hoverProvider"]
+                    .as_bool()
+                    .unwrap()
+            );
+            client.notify(Initialized::METHOD, json!({}));
+
+            (client, handle)
+        }
+
+        fn stop(mut self, handle: thread::JoinHandle<()>) {
+            self.request(Shutdown::METHOD, Value::Null);
+            self.notify(Exit::METHOD, Value::Null);
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_server() {
+        let (mut client, handle) = Client::start();
+
+        let uri = Url::parse("file:///tmp/test.pi").unwrap();
+        let text = "def x :: ℕ;\nlet y := S x;\neval z;\n";
+        client.notify(
+            DidOpenTextDocument::METHOD,
+            json!({ "textDocument": { "uri": uri, "languageId": "pi", "version": 0, "text": text } }),
+        );
+        let diagnostics = client.diagnostics().diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, Position::new(2, 0));
+
+        let at = |line, character| json!({ "textDocument": { "uri": uri }, "position": Position::new(line, character) });
+        let hover: Hover =
+            serde_json::from_value(client.request(HoverRequest::METHOD, at(1, 11))).unwrap();
+        match hover.contents {
+            HoverContents::Markup(content) => assert!(content.value.contains("x : ℕ")),
+            contents => panic!("unexpected hover {contents:?}"),
+        }
+
+        let def: GotoDefinitionResponse =
+            serde_json::from_value(client.request(GotoDefinition::METHOD, at(1, 11))).unwrap();
+        match def {
+            GotoDefinitionResponse::Scalar(location) => {
+                assert_eq!(location.range.start, Position::new(0, 4))
+            }
+            def => panic!("unexpected definition {def:?}"),
+        }
+
+        let params = json!({ "textDocument": { "uri": uri } });
+        let symbols: DocumentSymbolResponse =
+            serde_json::from_value(client.request(DocumentSymbolRequest::METHOD, params)).unwrap();
+        match symbols {
+            DocumentSymbolResponse::Nested(symbols) => {
+                let names = symbols.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+                assert_eq!(names, ["x", "y"]);
+            }
+            symbols => panic!("unexpected symbols {symbols:?}"),
+        }
+
+        let completion: CompletionResponse =
+            serde_json::from_value(client.request(Completion::METHOD, at(2, 5))).unwrap();
+        match completion {
+            CompletionResponse::Array(items) => {
+                assert!(items.iter().any(|item| item.label == "y"));
+                assert!(items.iter().any(|item| item.label == "let"));
+            }
+            completion => panic!("unexpected completion {completion:?}"),
+        }
+
+        client.notify(
+            DidChangeTextDocument::METHOD,
+            json!({
+                "textDocument": { "uri": uri, "version": 1 },
+                "contentChanges": [{ "text": text.replace("eval z;", "eval y;") }],
+            }),
+        );
+        assert!(client.diagnostics().diagnostics.is_empty());
+
+        client.stop(handle);
+    }
+
+    #[test]
+    fn test_limits() {
+        let (client, handle) = Client::start_with(Limits {
+            fuel: Some(100),
+            ..Default::default()
+        });
+
+        // A notification that cannot be read is skipped.
+        client.notify(DidOpenTextDocument::METHOD, json!({ "textDocument": 0 }));
+
+        let uri = Url::parse("file:///tmp/limits.pi").unwrap();
+        let text = "let succ := λ n -> S n :: ℕ -> ℕ;\n\
+                    let two := λ f -> λ x -> f (f x) :: (ℕ -> ℕ) -> ℕ -> ℕ;\n\
+                    eval (two (two (two (two succ))) O);\n";
+        client.notify(
+            DidOpenTextDocument::METHOD,
+            json!({ "textDocument": { "uri": uri, "languageId": "pi", "version": 0, "text": text } }),
+        );
+        let diagnostics = client.diagnostics().diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, Position::new(2, 0));
+        assert!(diagnostics[0].message.contains("ran out of fuel"));
+
+        client.stop(handle);
+    }
+
+    #[test]
+    fn test_holes() {
+        let (mut client, handle) = Client::start();
+
+        let uri = Url::parse("file:///tmp/holes.pi").unwrap();
+        let text = "let f := ? :: ℕ -> ℕ;\n";
+        client.notify(
+            DidOpenTextDocument::METHOD,
+            json!({ "textDocument": { "uri": uri, "languageId": "pi", "version": 0, "text": text } }),
+        );
+        let diagnostics = client.diagnostics().diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].severity,
+            Some(DiagnosticSeverity::INFORMATION)
+        );
+        assert_eq!(diagnostics[0].message, "? : ℕ -> ℕ");
+
+        let hole = Position::new(0, 9);
+        let params = json!({
+            "textDocument": { "uri": uri },
+            "range": { "start": hole, "end": hole },
+            "context": { "diagnostics": [] },
+        });
+        let actions: CodeActionResponse =
+            serde_json::from_value(client.request(CodeActionRequest::METHOD, params)).unwrap();
+        match &actions[..] {
+            [CodeActionOrCommand::CodeAction(action)] => {
+                let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+                assert_eq!(edits[0].new_text, "(λ x -> ?)");
+            }
+            actions => panic!("unexpected actions {actions:?}"),
+        }
+
+        let params = json!({ "command": GIVE, "arguments": [uri, hole, "λ n -> S n"] });
+        let edit: WorkspaceEdit =
+            serde_json::from_value(client.request(ExecuteCommand::METHOD, params)).unwrap();
+        let edits = &edit.changes.unwrap()[&uri];
+        assert_eq!(edits[0].new_text, "(λ n -> S n)");
+        assert_eq!(edits[0].range.start, hole);
+        assert_eq!(edits[0].range.end, Position::new(0, 10));
+
+        client.stop(handle);
+    }
+}